@@ -0,0 +1,55 @@
+// Compare the u128 and split 64-bit implementations of scale_tsc
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use tsc_simulator::math;
+
+// A second's worth of host TSC values at a few points in a host's uptime, so
+// both small and large products are covered
+fn host_tscs() -> Vec<u64> {
+    [
+        1_000_000_000u64,
+        3_600_000_000_000,
+        86_400_000_000_000 * 365,
+    ]
+    .iter()
+    .flat_map(|&base| (0..1000).map(move |i| base + i * 1_000_000))
+    .collect()
+}
+
+fn bench_scale_tsc(c: &mut Criterion) {
+    let tscs = host_tscs();
+
+    // 2.5 GHz guest on a 3 GHz host, in AMD and Intel formats
+    for (name, frac) in [("amd", 32), ("intel", 48)] {
+        let mult = math::freq_multiplier(
+            2_500_000_000,
+            3_000_000_000,
+            frac,
+            64 - frac,
+        )
+        .unwrap();
+
+        let mut group = c.benchmark_group(format!("scale_tsc_{name}"));
+        group.bench_function("u128", |b| {
+            b.iter(|| {
+                for &t in tscs.iter() {
+                    black_box(math::scale_tsc(black_box(t), mult, frac).ok());
+                }
+            })
+        });
+        group.bench_function("split", |b| {
+            b.iter(|| {
+                for &t in tscs.iter() {
+                    black_box(
+                        math::scale_tsc_split(black_box(t), mult, frac).ok(),
+                    );
+                }
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_scale_tsc);
+criterion_main!(benches);
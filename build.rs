@@ -1,4 +1,6 @@
 fn main() {
+    println!("cargo:rerun-if-changed=src/asm_math.s");
+
     cc::Build::new()
         .file("src/asm_math.s")
         .compile("asm_math.a");
@@ -1,4 +1,5 @@
-use libc::{c_uint, c_ulonglong};
+use crate::math::{would_freq_multiplier_overflow, MathError, Result};
+use libc::{c_longlong, c_uint, c_ulonglong};
 
 extern "C" {
     pub fn calc_freq_multiplier(
@@ -12,6 +13,56 @@ extern "C" {
         multiplier: c_ulonglong,
         frac_size: c_uint,
     ) -> c_ulonglong;
+
+    pub fn calc_migration(
+        host_tsc: c_ulonglong,
+        guest_tsc: c_ulonglong,
+        guest_hz: c_ulonglong,
+        host_hz: c_ulonglong,
+        frac_size: c_uint,
+        out_mult: *mut c_ulonglong,
+        out_offset: *mut c_longlong,
+    );
+}
+
+// The asm routines shift by `frac_size` (and by `64 - frac_size`) using %cl,
+// which only honors the low 6 bits of the count, and divide `guest_hz <<
+// frac_size` by `host_hz` with divq, which faults (#DE) on a zero divisor or a
+// quotient wider than 64 bits. Reject inputs the asm can't handle before
+// crossing into it.
+fn check_args(guest_hz: u64, host_hz: u64, frac_size: u32) -> Result<()> {
+    if frac_size == 0 || frac_size >= 64 {
+        return Err(MathError::InvalidInput(format!(
+            "frac_size={} not supported by asm implementation (must be 1-63)",
+            frac_size
+        )));
+    }
+    if host_hz == 0 {
+        return Err(MathError::InvalidFrequency {
+            name: "host frequency",
+        });
+    }
+    let int_size = 64 - frac_size;
+    if would_freq_multiplier_overflow(guest_hz, host_hz, frac_size, int_size) {
+        return Err(MathError::RatioTooLarge {
+            guest_hz,
+            host_hz,
+            int_size,
+            frac_size,
+        });
+    }
+
+    Ok(())
+}
+
+pub fn freq_multiplier(
+    guest_hz: u64,
+    host_hz: u64,
+    frac_size: u32,
+) -> Result<u64> {
+    check_args(guest_hz, host_hz, frac_size)?;
+
+    Ok(unsafe { calc_freq_multiplier(guest_hz, host_hz, frac_size) })
 }
 
 pub fn calc_tsc_offset(
@@ -20,8 +71,8 @@ pub fn calc_tsc_offset(
     guest_hz: u64,
     host_hz: u64,
     frac_size: u32,
-) -> i64 {
-    let mult = unsafe { calc_freq_multiplier(guest_hz, host_hz, frac_size) };
+) -> Result<i64> {
+    let mult = freq_multiplier(guest_hz, host_hz, frac_size)?;
     let host_tsc_scaled =
         unsafe { scale_tsc(initial_host_tsc, mult, frac_size) };
 
@@ -31,9 +82,40 @@ pub fn calc_tsc_offset(
         ((initial_guest_tsc - host_tsc_scaled), false)
     };
 
-    let res = if negate { -(diff as i64) } else { diff as i64 };
+    // as the hardware would, wrapping: a diff of 2^63 is i64::MIN
+    if negate {
+        Ok((diff as i64).wrapping_neg())
+    } else {
+        Ok(diff as i64)
+    }
+}
+
+// Compute the frequency multiplier and TSC offset for a guest starting to run
+// on a host, in a single call into asm
+pub fn migration_params(
+    initial_host_tsc: u64,
+    initial_guest_tsc: u64,
+    guest_hz: u64,
+    host_hz: u64,
+    frac_size: u32,
+) -> Result<(u64, i64)> {
+    check_args(guest_hz, host_hz, frac_size)?;
+
+    let mut mult: c_ulonglong = 0;
+    let mut offset: c_longlong = 0;
+    unsafe {
+        calc_migration(
+            initial_host_tsc,
+            initial_guest_tsc,
+            guest_hz,
+            host_hz,
+            frac_size,
+            &mut mult,
+            &mut offset,
+        )
+    };
 
-    res
+    Ok((mult, offset))
 }
 
 pub fn calc_guest_tsc(
@@ -43,18 +125,18 @@ pub fn calc_guest_tsc(
     guest_hz: u64,
     cur_host_tsc: u64,
     frac_size: u32,
-) -> u64 {
-    let mult = unsafe { calc_freq_multiplier(guest_hz, host_hz, frac_size) };
-    let offset = calc_tsc_offset(
+) -> Result<u64> {
+    let (mult, offset) = migration_params(
         initial_host_tsc,
         initial_guest_tsc,
         guest_hz,
         host_hz,
         frac_size,
-    );
+    )?;
     let host_tsc_scaled = unsafe { scale_tsc(cur_host_tsc, mult, frac_size) };
 
-    let guest_tsc = host_tsc_scaled as i64 + offset;
+    // the hardware adds the offset modulo 2^64
+    let guest_tsc = host_tsc_scaled.wrapping_add(offset as u64);
 
-    guest_tsc as u64
+    Ok(guest_tsc)
 }
@@ -2,6 +2,7 @@
 
 .global calc_freq_multiplier
 .global scale_tsc
+.global calc_migration
 
 /*
  * calc_freq_multiplier: calculates the ratio of guest_hz / host_hz, with
@@ -78,3 +79,62 @@ scale_tsc:
 	orq %rdx, %rax
 
 	ret
+
+
+/*
+ * calc_migration: calculates both the frequency multiplier and the TSC offset
+ * for a guest starting to run on a host, in one call.
+ *
+ * multiplier = (guest_hz * (1 << FRAC_SIZE)) / host_hz
+ * offset = guest_tsc - ((host_tsc * multiplier) >> FRAC_SIZE)
+ *
+ * %rdi: uint64_t host_tsc
+ * %rsi: uint64_t guest_tsc
+ * %rdx: uint64_t guest_hz
+ * %rcx: uint64_t host_hz
+ * %r8: uint32_t frac_size
+ * %r9: uint64_t *out_mult
+ * 8(%rsp): int64_t *out_offset
+ */
+calc_migration:
+	/* Move the frequencies out of the way of %rdx and %cl */
+	movq %rdx, %r10
+	movq %rcx, %r11
+
+	/*
+	 * Create scaling factor: 1 << frac_size
+	 * Store result in %rax
+	 */
+	movl %r8d, %ecx
+	movq $1, %rax
+	shlq %cl, %rax
+
+	/*
+	 * Multiply: guest_hz (%r10) * scaling_factor (%rax)
+	 * Divide: result by host_hz (%r11)
+	 * Multiplier is in %rax
+	 */
+	mulq %r10
+	divq %r11
+	movq %rax, (%r9)
+
+	/*
+	 * Multiply host_tsc (%rdi) * multiplier (%rax)
+	 * mulq result is in RDX:RAX
+	 */
+	mulq %rdi
+
+	/* Shift the 128-bit product right `frac_size` bits, as in scale_tsc */
+	shrq %cl, %rax
+	movl $64, %ecx
+	subl %r8d, %ecx
+	shlq %cl, %rdx
+	orq %rdx, %rax
+
+	/* offset = guest_tsc - scaled host_tsc */
+	movq %rsi, %rdx
+	subq %rax, %rdx
+	movq 8(%rsp), %rax
+	movq %rdx, (%rax)
+
+	ret
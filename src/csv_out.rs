@@ -0,0 +1,52 @@
+// Write simulation rows as CSV
+//
+// A header row, then one line per step on each host, with the
+// TSC values in decimal:
+//   t,guest_tsc,host_tsc,host_index,phase
+
+use std::io::Write;
+
+use crate::rows::{Phase, RowSink};
+
+pub struct RowWriter {
+    out: Box<dyn Write>,
+    rows: usize,
+}
+
+impl RowWriter {
+    pub fn create(mut out: Box<dyn Write>) -> anyhow::Result<Self> {
+        writeln!(out, "t,guest_tsc,host_tsc,host_index,phase")?;
+
+        Ok(Self { out, rows: 0 })
+    }
+}
+
+impl RowSink for RowWriter {
+    fn push(
+        &mut self,
+        t: i64,
+        guest_tsc: u64,
+        host_tsc: u64,
+        host_index: u32,
+        phase: Phase,
+    ) -> anyhow::Result<()> {
+        writeln!(
+            self.out,
+            "{},{},{},{},{}",
+            t,
+            guest_tsc,
+            host_tsc,
+            host_index,
+            phase.label()
+        )?;
+        self.rows += 1;
+
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> anyhow::Result<usize> {
+        self.out.flush()?;
+
+        Ok(self.rows)
+    }
+}
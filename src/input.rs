@@ -0,0 +1,497 @@
+//! Parsing and checking the inputs to a simulation: the hosts a guest boots
+//! on, migrates to, is retuned on and has its host's TSC reset on, and the
+//! frequencies and multiplier format they're given in. These take the same
+//! strings as the command line does, so that other frontends, such as scenario
+//! files, describe the same runs.
+
+use anyhow::{anyhow, Result};
+use clap_num::maybe_hex;
+
+use crate::math::{freq_multiplier, uptime_to_tsc, NS_PER_SEC};
+use crate::{FRAC_SIZE_AMD, FRAC_SIZE_INTEL, INT_SIZE_AMD, INT_SIZE_INTEL};
+
+/// The hardware a guest runs on, which sets the default multiplier format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    Amd,
+    Intel,
+}
+
+impl Arch {
+    /// The multiplier format (int, frac bits) the hardware uses
+    pub fn format(&self) -> (u32, u32) {
+        match self {
+            Arch::Amd => (INT_SIZE_AMD, FRAC_SIZE_AMD),
+            Arch::Intel => (INT_SIZE_INTEL, FRAC_SIZE_INTEL),
+        }
+    }
+}
+
+/// Host specification for simulation boot/migration of a guest
+#[derive(Debug, PartialEq)]
+pub struct HostDef {
+    pub start: usize,
+    pub host_tsc: u64,
+    pub host_freq: u64,
+    /// the guest's new frequency, if this is a retune on the same host rather
+    /// than a migration
+    pub guest_freq: Option<u64>,
+    /// whether this is the host's TSC being reset, keeping the guest's
+    /// multiplier and offset, rather than a migration
+    pub tsc_event: bool,
+}
+
+/// Ensure every second of the run, and so every migration time, which can't be
+/// past its end, can be counted in ns
+pub fn check_duration(duration: usize) -> Result<()> {
+    let max = u64::MAX / NS_PER_SEC as u64;
+    if duration as u64 > max {
+        return Err(anyhow!(
+            "duration of {}s is longer than the most that can be simulated, \
+             {}s",
+            duration,
+            max
+        ));
+    }
+
+    Ok(())
+}
+
+/// Resolve the initial guest TSC, which may be specified as either a raw TSC
+/// value or an uptime in seconds at the guest frequency
+pub fn resolve_initial_guest_tsc(
+    initial_guest_tsc: u64,
+    initial_guest_uptime_sec: Option<u64>,
+    guest_hz: u64,
+) -> Result<u64> {
+    match initial_guest_uptime_sec {
+        Some(uptime) => Ok(uptime_to_tsc(uptime, guest_hz)?),
+        None => Ok(initial_guest_tsc),
+    }
+}
+
+// Split a migration, retune or TSC event into its whitespace-separated fields,
+// given their usage, e.g. "<t> <host_tsc> <host_hz>"
+fn split_fields<'a, const N: usize>(
+    s: &'a str,
+    usage: &str,
+) -> Result<[&'a str; N]> {
+    let fields: Vec<&str> = s.split_whitespace().collect();
+    fields[..].try_into().map_err(|_| {
+        anyhow!(
+            "expected \"{}\", got {} fields in \"{}\"",
+            usage,
+            fields.len(),
+            s
+        )
+    })
+}
+
+// Parse one of those fields, which may be hex
+fn parse_field(name: &str, v: &str) -> Result<u64> {
+    maybe_hex::<u64>(v)
+        .map_err(|e| anyhow!("invalid {} \"{}\": {}", name, v, e))
+}
+
+// Parse a frequency field, as parse_frequency does
+fn parse_freq_field(name: &str, v: &str) -> Result<u64> {
+    parse_frequency(v).map_err(|e| anyhow!("invalid {}: {}", name, e))
+}
+
+/// Parse a migration, `<t> <host_tsc> <host_hz>`, where each field may be hex
+/// and the frequency may have a suffix, e.g. "2.4GHz"
+pub fn parse_host_def(s: &str) -> Result<HostDef> {
+    let [start, host_tsc, host_freq] =
+        split_fields(s, "<t> <host_tsc> <host_hz>")?;
+
+    Ok(HostDef {
+        start: parse_field("time", start)? as usize,
+        host_tsc: parse_field("host TSC", host_tsc)?,
+        host_freq: parse_freq_field("host frequency", host_freq)?,
+        guest_freq: None,
+        tsc_event: false,
+    })
+}
+
+/// Parse the boot host and the migrations away from it, given either at a time
+/// (`<t> <host_tsc> <host_hz>`) or when the previous host's TSC reaches a value
+/// (`<src_host_tsc> <host_tsc> <host_hz>`), in time order.
+pub fn parse_hosts(
+    initial_host_tsc: u64,
+    initial_host_hz: u64,
+    input_hosts: Vec<String>,
+    input_tsc_hosts: Vec<String>,
+    duration: usize,
+) -> Result<Vec<HostDef>> {
+    let mut res: Vec<HostDef> = Vec::new();
+    res.push(HostDef {
+        start: 0,
+        host_tsc: initial_host_tsc,
+        host_freq: initial_host_hz,
+        guest_freq: None,
+        tsc_event: false,
+    });
+
+    for s in input_hosts.iter() {
+        res.push(parse_host_def(s)?);
+    }
+
+    // sort by time order
+    res.sort_by_key(|h| h.start);
+    check_host_starts(&res, duration)?;
+
+    // each of these migrates away from the most recent host, at the time its
+    // TSC reaches the given value
+    for s in input_tsc_hosts.iter() {
+        let [src_tsc, host_tsc, host_freq] =
+            split_fields(s, "<src_host_tsc> <host_tsc> <host_hz>")?;
+        let src_tsc = parse_field("source host TSC", src_tsc)?;
+        let host_tsc = parse_field("host TSC", host_tsc)?;
+        let host_freq = parse_freq_field("host frequency", host_freq)?;
+
+        let src = res.last().unwrap();
+        let end_tsc = (duration - src.start) as u128 * src.host_freq as u128
+            + src.host_tsc as u128;
+        if src_tsc < src.host_tsc || src_tsc as u128 > end_tsc {
+            return Err(anyhow!(
+                "source host TSC {} outside of host's active range {}-{}",
+                src_tsc,
+                src.host_tsc,
+                end_tsc
+            ));
+        }
+        let start =
+            src.start + ((src_tsc - src.host_tsc) / src.host_freq) as usize;
+
+        res.push(HostDef {
+            start,
+            host_tsc,
+            host_freq,
+            guest_freq: None,
+            tsc_event: false,
+        });
+    }
+
+    check_host_starts(&res, duration)?;
+
+    Ok(res)
+}
+
+/// Ensure each migration starts after the host before it, so each host runs the
+/// guest for at least a second, and no later than the end of the run. The boot
+/// host starts at 0, so no migration can.
+pub fn check_host_starts(hosts: &[HostDef], duration: usize) -> Result<()> {
+    for (i, pair) in hosts.windows(2).enumerate() {
+        let (prev, h) = (&pair[0], &pair[1]);
+        if h.start <= prev.start {
+            return Err(anyhow!(
+                "migration {} at {}s must be after {} at {}s",
+                i + 1,
+                h.start,
+                if i == 0 {
+                    "boot".to_string()
+                } else {
+                    format!("migration {}", i)
+                },
+                prev.start
+            ));
+        }
+        if h.start > duration {
+            return Err(anyhow!(
+                "migration {} at {}s is past the duration of {}s",
+                i + 1,
+                h.start,
+                duration
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Add a segment for each guest frequency change to the hosts parsed by
+/// parse_hosts. A retune keeps the guest on the same host, so the new segment
+/// continues that host's TSC; only the multiplier and offset change. Each one
+/// happens on the first host, starting from the previous retune's, whose TSC
+/// reaches the given value while the guest is on it.
+pub fn add_retunes(
+    hosts: &mut Vec<HostDef>,
+    input_retunes: Vec<String>,
+    duration: usize,
+) -> Result<()> {
+    let mut from = 0;
+    for s in input_retunes.iter() {
+        let [retune_tsc, guest_freq] =
+            split_fields(s, "<host_tsc> <guest_hz>")?;
+        let retune_tsc = parse_field("host TSC", retune_tsc)?;
+        let guest_freq = parse_freq_field("guest frequency", guest_freq)?;
+
+        // a host's TSC range ends where the next one takes over the guest,
+        // or at the end of the run for the last host
+        let (i, src) = hosts
+            .iter()
+            .enumerate()
+            .skip(from)
+            .find(|(i, h)| {
+                let end = hosts.get(i + 1).map_or(duration, |n| n.start);
+                let end_tsc = (end - h.start) as u128 * h.host_freq as u128
+                    + h.host_tsc as u128;
+                retune_tsc >= h.host_tsc
+                    && match hosts.get(i + 1) {
+                        Some(_) => (retune_tsc as u128) < end_tsc,
+                        None => retune_tsc as u128 <= end_tsc,
+                    }
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "host TSC {} is not reached while the guest is on any host",
+                    retune_tsc
+                )
+            })?;
+
+        let secs = (retune_tsc - src.host_tsc) / src.host_freq;
+        let retune = HostDef {
+            start: src.start + secs as usize,
+            host_tsc: src.host_tsc + secs * src.host_freq,
+            host_freq: src.host_freq,
+            guest_freq: Some(guest_freq),
+            tsc_event: false,
+        };
+        hosts.insert(i + 1, retune);
+        from = i + 1;
+    }
+
+    Ok(())
+}
+
+/// Add a segment for each host TSC reset to the hosts parsed by parse_hosts.
+/// Each one continues the host the guest is on at its time, with the same
+/// frequency, from the new TSC.
+pub fn add_tsc_events(
+    hosts: &mut Vec<HostDef>,
+    input_events: Vec<String>,
+    duration: usize,
+) -> Result<()> {
+    for s in input_events.iter() {
+        let [start, host_tsc] = split_fields(s, "<t> <new_host_tsc>")?;
+        let start = parse_field("time", start)? as usize;
+        let host_tsc = parse_field("host TSC", host_tsc)?;
+
+        if start == 0 || start > duration {
+            return Err(anyhow!(
+                "TSC event at {}s must be after boot and within the duration \
+                 of {}s",
+                start,
+                duration
+            ));
+        }
+        if hosts.iter().any(|h| h.start == start) {
+            return Err(anyhow!(
+                "TSC event at {}s is at the same time as another migration, \
+                 retune or TSC event",
+                start
+            ));
+        }
+
+        // the last segment starting before the event
+        let i = hosts.iter().rposition(|h| h.start < start).unwrap();
+        let event = HostDef {
+            start,
+            host_tsc,
+            host_freq: hosts[i].host_freq,
+            guest_freq: None,
+            tsc_event: true,
+        };
+        hosts.insert(i + 1, event);
+    }
+
+    Ok(())
+}
+
+// A small seeded PRNG (SplitMix64), so that random scenarios can be replayed
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // A value in lo..=hi
+    fn range(&mut self, lo: u64, hi: u64) -> u64 {
+        lo + self.next_u64() % (hi - lo + 1)
+    }
+}
+
+/// Generate `count` migrations at distinct random whole seconds within the
+/// duration, each to a host with a plausible frequency (1-5 GHz, in MHz) that
+/// has been up for up to a year
+pub fn random_migrations(
+    count: usize,
+    seed: u64,
+    duration: usize,
+) -> Result<Vec<HostDef>> {
+    if count >= duration {
+        return Err(anyhow!(
+            "{} random migrations don't fit in {}s: at most one per second \
+             after boot",
+            count,
+            duration
+        ));
+    }
+
+    let mut rng = SplitMix64(seed);
+
+    // pick distinct times in 1..duration (Floyd's algorithm)
+    let mut starts = std::collections::BTreeSet::new();
+    for j in (duration - count)..duration {
+        let t = rng.range(1, j as u64) as usize;
+        if !starts.insert(t) {
+            starts.insert(j);
+        }
+    }
+
+    Ok(starts
+        .into_iter()
+        .map(|start| {
+            let host_freq = rng.range(1_000, 5_000) * 1_000_000;
+            let uptime_sec = rng.range(0, 365 * 24 * 60 * 60);
+            HostDef {
+                start,
+                host_tsc: uptime_sec * host_freq,
+                host_freq,
+                guest_freq: None,
+                tsc_event: false,
+            }
+        })
+        .collect())
+}
+
+/// The --migrate value for a migration, as parse_host_def reads it
+pub fn migrate_arg(h: &HostDef) -> String {
+    format!("{} {} {}", h.start, h.host_tsc, h.host_freq)
+}
+
+/// Parse a frequency in Hz, or with a Hz, kHz, MHz or GHz suffix (in any case),
+/// which may have a decimal point, e.g. "2.4GHz". Values without a suffix may
+/// be hex, as other numeric arguments can. A frequency of 0 is rejected, as the
+/// TSC would never advance.
+pub fn parse_frequency(s: &str) -> Result<u64> {
+    match parse_hz(s.trim())? {
+        0 => Err(anyhow!("frequency must be nonzero: {}", s.trim())),
+        hz => Ok(hz),
+    }
+}
+
+/// Parse a frequency as parse_frequency does, allowing 0
+pub fn parse_hz(s: &str) -> Result<u64> {
+    if s.starts_with('-') {
+        return Err(anyhow!("frequency must not be negative: {}", s));
+    }
+
+    let lower = s.to_ascii_lowercase();
+    let Some((num, exp)) = [("ghz", 9), ("mhz", 6), ("khz", 3), ("hz", 0)]
+        .into_iter()
+        .find_map(|(suffix, exp)| {
+            lower.strip_suffix(suffix).map(|n| (n.trim_end(), exp))
+        })
+    else {
+        return maybe_hex::<u64>(s)
+            .map_err(|e| anyhow!("invalid frequency {:?}: {}", s, e));
+    };
+
+    // a whole number, optionally followed by a fractional part
+    let (int, frac) = num.split_once('.').unwrap_or((num, ""));
+    let is_digits =
+        |d: &str| !d.is_empty() && d.bytes().all(|b| b.is_ascii_digit());
+    if !is_digits(int) || (num.contains('.') && !is_digits(frac)) {
+        return Err(anyhow!("invalid frequency {:?}: expected e.g. 2.4GHz", s));
+    }
+
+    let frac = frac.trim_end_matches('0');
+    if frac.len() > exp {
+        return Err(anyhow!("frequency {:?} is not a whole number of Hz", s));
+    }
+
+    format!("{}{:0<width$}", int, frac, width = exp)
+        .parse()
+        .map_err(|e| anyhow!("invalid frequency {:?}: {}", s, e))
+}
+
+/// Ensure a frequency is usable as a divisor
+pub fn check_hz(name: &str, hz: u64) -> Result<()> {
+    if hz == 0 {
+        return Err(anyhow!("{} must be nonzero", name));
+    }
+
+    Ok(())
+}
+
+/// The multiplier format to use for `arch`, with either part of its own format
+/// overridden. Both arches hold the multiplier in a 64-bit register, so the
+/// result must still fit in one.
+pub fn arch_format(
+    arch: Arch,
+    int_size: Option<u32>,
+    frac_size: Option<u32>,
+) -> Result<(u32, u32)> {
+    let (default_int, default_frac) = arch.format();
+    let int_size = int_size.unwrap_or(default_int);
+    let frac_size = frac_size.unwrap_or(default_frac);
+
+    check_format(int_size, frac_size)
+        .map_err(|e| anyhow!("{} (arch {:?})", e, arch))?;
+
+    Ok((int_size, frac_size))
+}
+
+/// Ensure a multiplier format fits in 64 bits, with integer bits and fractional
+/// bits to shift by
+pub fn check_format(int_size: u32, frac_size: u32) -> Result<()> {
+    if int_size == 0
+        || frac_size == 0
+        || frac_size >= 64
+        || int_size > 64 - frac_size
+    {
+        return Err(anyhow!(
+            "invalid multiplier format {}.{}: must fit in 64 bits with at \
+             least 1 int bit and 1-63 frac bits",
+            int_size,
+            frac_size
+        ));
+    }
+
+    Ok(())
+}
+
+/// Ensure the multiplier format can hold the guest/host frequency ratio on every
+/// host a simulated guest runs on
+pub fn check_ratios(
+    guest_hz: u64,
+    hosts: &[HostDef],
+    int_size: u32,
+    frac_size: u32,
+) -> Result<()> {
+    let mut guest_hz = guest_hz;
+    for h in hosts.iter() {
+        guest_hz = h.guest_freq.unwrap_or(guest_hz);
+        freq_multiplier(guest_hz, h.host_freq, frac_size, int_size).map_err(
+            |e| {
+                anyhow!(
+                    "multiplier format {}.{} can't represent a guest/host \
+                     ratio of {}/{}: {}",
+                    int_size,
+                    frac_size,
+                    guest_hz,
+                    h.host_freq,
+                    e
+                )
+            },
+        )?;
+    }
+
+    Ok(())
+}
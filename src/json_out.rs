@@ -0,0 +1,134 @@
+// Write simulation rows as a JSON document
+//
+// The document is an object with:
+// - meta: how the rows were produced (tool version, implementation, multiplier
+//   format, when, and the command line)
+// - header: the simulated guest and hosts
+// - rows: one object per step on each host, written as they're
+//   simulated
+
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::rows::{Phase, RowSink};
+
+#[derive(Serialize)]
+pub struct Meta {
+    pub version: &'static str,
+    pub implementation: &'static str,
+    pub format: String,
+    pub timestamp: String,
+    pub inputs: Vec<String>,
+}
+
+impl Meta {
+    pub fn new(int_size: u32, frac_size: u32) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            implementation: "rust",
+            format: format!("{}.{}", int_size, frac_size),
+            timestamp: utc_timestamp(std::time::SystemTime::now()),
+            inputs: std::env::args().skip(1).collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Host {
+    pub start: usize,
+    pub host_tsc: u64,
+    pub host_freq: u64,
+    // set for a retune on the same host rather than a migration
+    pub guest_freq: Option<u64>,
+    // set for a reset of the host TSC, only included when it is
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub tsc_event: bool,
+}
+
+#[derive(Serialize)]
+pub struct Header {
+    pub duration: usize,
+    pub guest_hz: u64,
+    pub actual_guest_hz: Option<u64>,
+    pub initial_guest_tsc: u64,
+    pub hosts: Vec<Host>,
+}
+
+#[derive(Serialize)]
+struct Row {
+    t: i64,
+    guest_tsc: u64,
+    host_tsc: u64,
+    host_index: u32,
+    phase: Phase,
+}
+
+pub struct RowWriter {
+    out: Box<dyn Write>,
+    rows: usize,
+}
+
+impl RowWriter {
+    // Start the document, up to the opening of the rows array
+    pub fn create(
+        mut out: Box<dyn Write>,
+        meta: &Meta,
+        header: &Header,
+    ) -> anyhow::Result<Self> {
+        write!(out, "{{\"meta\":")?;
+        serde_json::to_writer(&mut out, meta)?;
+        write!(out, ",\"header\":")?;
+        serde_json::to_writer(&mut out, header)?;
+        write!(out, ",\"rows\":[")?;
+
+        Ok(Self { out, rows: 0 })
+    }
+}
+
+impl RowSink for RowWriter {
+    fn push(
+        &mut self,
+        t: i64,
+        guest_tsc: u64,
+        host_tsc: u64,
+        host_index: u32,
+        phase: Phase,
+    ) -> anyhow::Result<()> {
+        let row = Row {
+            t,
+            guest_tsc,
+            host_tsc,
+            host_index,
+            phase,
+        };
+        if self.rows > 0 {
+            write!(self.out, ",")?;
+        }
+        writeln!(self.out)?;
+        serde_json::to_writer(&mut self.out, &row)?;
+        self.rows += 1;
+
+        Ok(())
+    }
+
+    // Close the rows array and the document
+    fn finish(mut self: Box<Self>) -> anyhow::Result<usize> {
+        writeln!(self.out, "\n]}}")?;
+        self.out.flush()?;
+
+        Ok(self.rows)
+    }
+}
+
+// Format a time as an RFC 3339 UTC timestamp, to the second
+fn utc_timestamp(time: std::time::SystemTime) -> String {
+    let secs = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    chrono::DateTime::from_timestamp(secs as i64, 0)
+        .unwrap_or_default()
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
@@ -0,0 +1,43 @@
+//! Time-related calculations for virtualized TSCs.
+//!
+//! A guest's TSC is derived from its host's: the host TSC is scaled by the
+//! ratio of the guest and host frequencies, then an offset is added so the
+//! guest TSC starts where it should (0 at boot, or where it left off on the
+//! source host after a migration):
+//!
+//! ```text
+//! guest_tsc = ((host_tsc * multiplier) >> frac_size) + offset
+//! ```
+//!
+//! The multiplier is the frequency ratio as an unsigned fixed-point number
+//! with `int_size` integer bits and `frac_size` fractional bits, which must
+//! fit in 64 bits: 8.32 on AMD ([`INT_SIZE_AMD`], [`FRAC_SIZE_AMD`]) and
+//! 16.48 on Intel ([`INT_SIZE_INTEL`], [`FRAC_SIZE_INTEL`]). The multiplier
+//! is truncated to that precision, and products are computed with 128-bit
+//! intermediates so they can't overflow before the shift. The offset is a
+//! signed 64-bit value. [`math::FixedPoint`] keeps a multiplier together with
+//! its format.
+//!
+//! The [`math`] module implements these calculations in Rust, returning a
+//! [`math::MathError`] for any input whose result can't be represented, which
+//! tells an overflow apart from invalid input. The [`asm_math`]
+//! module implements the core of them in assembly, as the hardware would, to
+//! cross-check against.
+//!
+//! The [`input`] module parses and checks the inputs to a simulation, and the
+//! [`rows`] module and its writers ([`csv_out`], [`json_out`] and, with the
+//! `parquet` feature, `parquet_out`) write out the rows it produces.
+
+pub mod asm_math;
+pub mod csv_out;
+pub mod input;
+pub mod json_out;
+pub mod math;
+#[cfg(feature = "parquet")]
+pub mod parquet_out;
+pub mod rows;
+
+pub const INT_SIZE_INTEL: u32 = 16;
+pub const FRAC_SIZE_INTEL: u32 = 48;
+pub const INT_SIZE_AMD: u32 = 8;
+pub const FRAC_SIZE_AMD: u32 = 32;
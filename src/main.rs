@@ -1,33 +1,121 @@
 // A tool for calculating time-related values
 
-use crate::math::*;
+use tsc_simulator::asm_math;
+use tsc_simulator::input::*;
+use tsc_simulator::math::*;
+#[cfg(feature = "parquet")]
+use tsc_simulator::parquet_out;
+use tsc_simulator::rows::{Phase, RowSink};
+use tsc_simulator::{csv_out, json_out};
 
 use anyhow::anyhow;
-use clap::{clap_derive::ArgEnum, Parser, Subcommand};
+use clap::{
+    clap_derive::ArgEnum, CommandFactory, FromArgMatches, Parser, Subcommand,
+};
 use clap_num::maybe_hex;
 
-mod asm_math;
-mod math;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod scenario;
 mod tests;
+mod vectors;
 
-pub const INT_SIZE_INTEL: u32 = 16;
-pub const FRAC_SIZE_INTEL: u32 = 48;
-pub const INT_SIZE_AMD: u32 = 8;
-pub const FRAC_SIZE_AMD: u32 = 32;
+const SECS_PER_DAY: u64 = 86400;
 
 /// TSC Simulator
 #[derive(Debug, Parser)]
 struct Opt {
     #[clap(subcommand)]
     cmd: Command,
+
+    /// Check that the inputs are valid and representable, printing "OK" or
+    /// the first error, without computing or printing anything else
+    #[clap(long, global = true, takes_value = false)]
+    dry_run: bool,
+
+    /// Number of fractional digits to print floating point values (ratios,
+    /// ppm, fractional ticks) with; each value has its own default
+    #[clap(long, global = true)]
+    precision: Option<usize>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum)]
+enum OutputFormat {
+    #[clap(alias = "table")]
+    Text,
+    Json,
+    Csv,
+    Parquet,
+}
+
+// The CLI's names for the library's enums, which it keeps free of clap
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum)]
+enum RoundingArg {
+    /// Round down, which never overstates elapsed time
+    Truncate,
+    /// Round to the nearest unit, with halves rounded up
+    Nearest,
+    /// Round up
+    Ceil,
+}
+
+impl From<RoundingArg> for Rounding {
+    fn from(r: RoundingArg) -> Self {
+        match r {
+            RoundingArg::Truncate => Rounding::Truncate,
+            RoundingArg::Nearest => Rounding::Nearest,
+            RoundingArg::Ceil => Rounding::Ceil,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum)]
+enum ResumeModeArg {
+    /// Pick up where the guest left off, as if no time passed
+    Continue,
+    /// Jump forward by the wall-clock time the guest was paused
+    Realtime,
+}
+
+impl From<ResumeModeArg> for ResumeMode {
+    fn from(m: ResumeModeArg) -> Self {
+        match m {
+            ResumeModeArg::Continue => ResumeMode::Continue,
+            ResumeModeArg::Realtime => ResumeMode::Realtime,
+        }
+    }
 }
 
-#[derive(Debug, Clone, ArgEnum)]
-enum Arch {
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ArchArg {
     Amd,
     Intel,
 }
 
+impl From<ArchArg> for Arch {
+    fn from(a: ArchArg) -> Self {
+        match a {
+            ArchArg::Amd => Arch::Amd,
+            ArchArg::Intel => Arch::Intel,
+        }
+    }
+}
+
+// Whether a format writes the simulation rows as a document that can stand in
+// for the table on stdout
+fn is_document(format: OutputFormat) -> bool {
+    matches!(format, OutputFormat::Json | OutputFormat::Csv)
+}
+
+// Whether the last second on a host the guest migrates away from is shown, in
+// addition to the first second on the next host at the same time
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum)]
+enum Boundary {
+    Inclusive,
+    Exclusive,
+}
+
 #[derive(Debug, Copy, Clone, ArgEnum)]
 pub enum MathImpl {
     Asm,
@@ -35,16 +123,31 @@ pub enum MathImpl {
     All,
 }
 
+// parsed once, so the simulate variant's size doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Subcommand)]
 enum Command {
     /// Calculate a specific value
     Calc {
         #[clap(subcommand)]
         cmd: CalcCommand,
+
+        /// Also print 64-bit results as both their u64 and i64
+        /// interpretations
+        #[clap(long, global = true, takes_value = false)]
+        both_signs: bool,
     },
 
+    /// Run the embedded test vectors through the rust and asm implementations
+    Selftest,
+
     /// Simulate what TSC values a host and guest have over time
     Simulate {
+        /// TOML file to read the boot host, guest frequency, duration, arch
+        /// and migrations from; flags given here override the file
+        #[clap(long)]
+        scenario: Option<std::path::PathBuf>,
+
         /// Duration (seconds)
         #[clap(short = 'd', long, default_value = "20")]
         duration: usize,
@@ -54,33 +157,189 @@ enum Command {
         initial_host_tsc: u64,
 
         /// Initial Host Frequency (Hz)
-        #[clap(short = 'f', default_value = "1000000000")]
+        #[clap(
+            short = 'f',
+            value_parser=parse_frequency,
+            default_value = "1000000000"
+        )]
         initial_host_hz: u64,
 
-        /// Guest Frequency (Hz)
-        #[clap(short = 'g', long, default_value = "1000000000")]
+        /// Guest Frequency (Hz), as advertised to the guest
+        #[clap(
+            short = 'g',
+            long,
+            alias = "nominal-guest-hz",
+            value_parser=parse_frequency,
+            default_value = "1000000000"
+        )]
         guest_hz: u64,
 
-        /// Migrate to host at t seconds: "<t> <host_tsc> <host_hz>"
+        /// Frequency the guest TSC is actually scaled to (Hz), if it differs
+        /// from the advertised one; drift is still measured against the
+        /// advertised frequency
+        #[clap(long, value_parser=parse_frequency, conflicts_with = "retunes")]
+        actual_guest_hz: Option<u64>,
+
+        /// Frequency correction (ppm) applied to the guest frequency, as a
+        /// hypervisor slewing the guest to keep wall-clock time would
+        #[clap(long, default_value = "0", allow_hyphen_values = true)]
+        guest_ppm: i32,
+
+        /// Initial Guest TSC value
+        #[clap(long, value_parser=maybe_hex::<u64>, default_value = "0")]
+        initial_guest_tsc: u64,
+
+        /// Initial Guest uptime (seconds), instead of an initial Guest TSC
+        #[clap(
+            long,
+            value_parser=maybe_hex::<u64>,
+            conflicts_with = "initial-guest-tsc"
+        )]
+        initial_guest_uptime_sec: Option<u64>,
+
+        /// Migrate to host at t seconds: "<t> <host_tsc> <host_hz>". As
+        /// with other frequencies, <host_hz> may have a suffix, e.g.
+        /// "10 0 2.4GHz".
         #[clap(long = "migrate")]
         hosts: Vec<String>,
 
+        /// Migrate to host when the source host's TSC reaches a value:
+        /// "<src_host_tsc> <host_tsc> <host_hz>". Migrations must be given
+        /// in order, and happen at the last whole second before the source
+        /// TSC value.
+        #[clap(long = "migrate-at-tsc", conflicts_with = "hosts")]
+        tsc_hosts: Vec<String>,
+
+        /// Change the guest frequency when the current host's TSC reaches a
+        /// value: "<host_tsc> <new_guest_hz>". Retunes must be given in
+        /// order, and happen at the last whole second before the TSC value.
+        #[clap(long = "retune", conflicts_with = "check-realtime")]
+        retunes: Vec<String>,
+
+        /// Reset the current host's TSC at t seconds, as buggy firmware
+        /// might: "<t> <new_host_tsc>". Unlike a migration, the host
+        /// frequency and the guest's TSC offset are kept, so the guest TSC
+        /// jumps with the host's.
+        #[clap(long = "tsc-event")]
+        tsc_events: Vec<String>,
+
+        /// Migrate to this many hosts, with random times, TSCs and
+        /// frequencies; the migrations are printed as --migrate flags so the
+        /// run can be replayed
+        #[clap(long, conflicts_with_all = &["hosts", "tsc-hosts"])]
+        random_migrations: Option<usize>,
+
+        /// Seed for --random-migrations; the same seed gives the same
+        /// migrations
+        #[clap(long, default_value = "0", requires = "random-migrations")]
+        seed: u64,
+
         /// Architecture of hosts
         #[clap(long, arg_enum, default_value = "amd")]
-        arch: Arch,
+        arch: ArchArg,
+
+        /// Override the number of integer bits of the arch's multiplier
+        #[clap(long)]
+        int_size: Option<u32>,
+
+        /// Override the number of fractional bits of the arch's multiplier
+        #[clap(long)]
+        frac_size: Option<u32>,
 
         /// Print TSC values as hexadecimal
         #[clap(long, takes_value = false)]
         hex: bool,
-    },
-}
 
-// Host specification for simulation boot/migration of a guest
-#[derive(Debug)]
-struct HostDef {
-    start: usize,
-    host_tsc: u64,
-    host_freq: u64,
+        /// Also print the guest TSC as elapsed seconds at the guest frequency
+        #[clap(long, takes_value = false)]
+        as_seconds: bool,
+
+        /// Also extrapolate the guest TSC for this many seconds before boot
+        #[clap(long, default_value = "0")]
+        pre_boot_sec: usize,
+
+        /// Time between simulated steps (ns); the last step on each host is
+        /// shortened to end at the next migration or the end of the run
+        #[clap(long, default_value = "1000000000")]
+        step_ns: u64,
+
+        /// Serve Prometheus metrics on localhost at this port, advancing the
+        /// simulation in real time (requires the `metrics` feature)
+        #[clap(long)]
+        metrics_port: Option<u16>,
+
+        /// Advance the simulation at wall-clock speed, emitting rows as
+        /// simulated time passes
+        #[clap(long, takes_value = false)]
+        real_time: bool,
+
+        /// Speed-up factor for real-time pacing
+        #[clap(long, default_value = "1.0")]
+        speed: f64,
+
+        /// Whether to also show the source host's row at each migration time;
+        /// by default only the destination host's row is shown
+        #[clap(long, arg_enum, default_value = "exclusive")]
+        boundary: Boundary,
+
+        /// Print a histogram of the per-step guest TSC increment error at
+        /// the end of the run
+        #[clap(long, takes_value = false)]
+        drift_histogram: bool,
+
+        /// Also print how far the guest's elapsed time, read from its TSC at
+        /// the guest frequency, is ahead of real time (ns)
+        #[clap(long, takes_value = false, conflicts_with = "retunes")]
+        show_drift: bool,
+
+        /// Also print the guest and host TSCs as hrtime (ns), each at its
+        /// own frequency
+        #[clap(long, takes_value = false)]
+        show_hrtime: bool,
+
+        /// Print the --show-hrtime columns as seconds rather than ns
+        #[clap(long, takes_value = false, requires = "show-hrtime")]
+        human: bool,
+
+        /// Also plot how far the guest's elapsed time is ahead of real time
+        /// at each step as a sparkline, fit to the terminal width
+        #[clap(long, takes_value = false, conflicts_with = "retunes")]
+        plot: bool,
+
+        /// Format to emit simulation rows in; json and csv are written to
+        /// stdout unless --output is given, and parquet requires --output and the
+        /// `parquet` feature
+        #[clap(long, arg_enum, default_value = "text")]
+        format: OutputFormat,
+
+        /// File to write simulation rows to, for formats that need one
+        #[clap(long)]
+        output: Option<std::path::PathBuf>,
+
+        /// Run under both the AMD (8.32) and Intel (16.48) multiplier
+        /// formats, printing each one's guest TSC and their difference
+        #[clap(
+            long,
+            takes_value = false,
+            conflicts_with_all = &[
+                "arch", "int-size", "frac-size", "format", "output",
+                "pre-boot-sec", "as-seconds", "show-drift", "show-hrtime",
+                "drift-histogram", "check-realtime", "metrics-port",
+                "real-time", "plot",
+            ]
+        )]
+        compare_arch: bool,
+
+        /// Check that the guest's elapsed time at the end of the run matches
+        /// the duration, exiting nonzero if it doesn't
+        #[clap(long, takes_value = false)]
+        check_realtime: bool,
+
+        /// Allowed discrepancy for --check-realtime (ppm); defaults to the
+        /// worst case drift for the multiplier format
+        #[clap(long, requires = "check-realtime")]
+        tolerance_ppm: Option<f64>,
+    },
 }
 
 #[derive(Debug, Subcommand)]
@@ -94,10 +353,14 @@ enum CalcCommand {
         /// Frequency (Hz)
         #[clap(
             short = 'f',
-            value_parser=maybe_hex::<u64>,
+            value_parser=parse_frequency,
             default_value = "1000000000")
         ]
         freq_hz: u64,
+
+        /// How to round a partial unit
+        #[clap(long, arg_enum, default_value = "truncate")]
+        rounding: RoundingArg,
     },
 
     /// Given an hrtime and a frequency, compute TSC value
@@ -109,10 +372,34 @@ enum CalcCommand {
         /// Frequency (Hz)
         #[clap(
             short = 'f',
-            value_parser=maybe_hex::<u64>,
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        freq_hz: u64,
+
+        /// How to round a partial unit
+        #[clap(long, arg_enum, default_value = "truncate")]
+        rounding: RoundingArg,
+    },
+
+    /// Given a TSC value, a frequency, and the wall-clock time at which the
+    /// TSC was 0, compute the wall-clock time of the TSC value
+    Wallclock {
+        /// TSC value
+        #[clap(long, value_parser=maybe_hex::<u64>)]
+        tsc: u64,
+
+        /// Frequency (Hz)
+        #[clap(
+            long,
+            value_parser=parse_frequency,
             default_value = "1000000000")
         ]
         freq_hz: u64,
+
+        /// Wall-clock time at which the TSC was 0 (ns since the Unix epoch)
+        #[clap(long, allow_hyphen_values = true, default_value = "0")]
+        boot_epoch_ns: i64,
     },
 
     /// Compute a guest's TSC value
@@ -124,18 +411,27 @@ enum CalcCommand {
         /// Initial Guest TSC value
         #[clap(
             short = 't',
+            long,
             value_parser=maybe_hex::<u64>,
             default_value = "0")
         ]
         initial_guest_tsc: u64,
 
+        /// Initial Guest uptime (seconds), instead of an initial Guest TSC
+        #[clap(
+            long,
+            value_parser=maybe_hex::<u64>,
+            conflicts_with = "initial-guest-tsc"
+        )]
+        initial_guest_uptime_sec: Option<u64>,
+
         /// Current Host TSC value
         host_tsc: u64,
 
         /// Host Frequency (Hz)
         #[clap(
             short = 'f',
-            value_parser=maybe_hex::<u64>,
+            value_parser=parse_frequency,
             default_value = "1000000000")
         ]
         host_hz: u64,
@@ -143,12 +439,14 @@ enum CalcCommand {
         /// Guest Frequency (Hz)
         #[clap(
             short = 'g',
-            value_parser=maybe_hex::<u64>,
+            value_parser=parse_frequency,
             default_value = "1000000000")
         ]
         guest_hz: u64,
 
-        /// Calculate related values in assembly, rust, or both
+        /// Calculate related values in assembly, rust, or both (exiting
+        /// nonzero if they disagree). The asm can trap on inputs the rust
+        /// rejects, so it's only run on inputs the rust accepts.
         #[clap(short = 'm', arg_enum, default_value = "rust")]
         math_impl: MathImpl,
 
@@ -157,6 +455,78 @@ enum CalcCommand {
         int_size: u32,
         #[clap(long, default_value = "32")]
         frac_size: u32,
+
+        /// Width of the hardware TSC offset field (bits); narrower fields
+        /// truncate the offset (sign-extended) before it is applied
+        #[clap(
+            long,
+            value_parser = clap::value_parser!(u32).range(1..=64),
+            default_value = "64"
+        )]
+        offset_bits: u32,
+
+        /// Width of the guest TSC (bits); a guest TSC above 2^N - 1 is an
+        /// error unless --saturate is given
+        #[clap(
+            long,
+            value_parser = clap::value_parser!(u32).range(1..=64),
+            default_value = "64"
+        )]
+        tsc_width: u32,
+
+        /// Clamp a guest TSC that exceeds --tsc-width rather than failing
+        #[clap(long, takes_value = false)]
+        saturate: bool,
+    },
+
+    /// Compute the guest TSC in both rust and assembly, and report how far
+    /// apart they are
+    Compare {
+        /// Initial Host TSC value (at boot or time of migration)
+        #[clap(short = 'i', value_parser=maybe_hex::<u64>)]
+        initial_host_tsc: u64,
+
+        /// Initial Guest TSC value
+        #[clap(
+            short = 't',
+            long,
+            value_parser=maybe_hex::<u64>,
+            default_value = "0")
+        ]
+        initial_guest_tsc: u64,
+
+        /// Initial Guest uptime (seconds), instead of an initial Guest TSC
+        #[clap(
+            long,
+            value_parser=maybe_hex::<u64>,
+            conflicts_with = "initial-guest-tsc"
+        )]
+        initial_guest_uptime_sec: Option<u64>,
+
+        /// Current Host TSC value
+        host_tsc: u64,
+
+        /// Host Frequency (Hz)
+        #[clap(
+            short = 'f',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        host_hz: u64,
+
+        /// Guest Frequency (Hz)
+        #[clap(
+            short = 'g',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        guest_hz: u64,
+
+        // AMD defaults
+        #[clap(long, default_value = "8")]
+        int_size: u32,
+        #[clap(long, default_value = "32")]
+        frac_size: u32,
     },
 
     /// Compute a guest's TSC offset
@@ -168,15 +538,24 @@ enum CalcCommand {
         /// Initial Guest TSC value
         #[clap(
             short = 't',
+            long,
             value_parser=maybe_hex::<u64>,
             default_value = "0")
         ]
         initial_guest_tsc: u64,
 
+        /// Initial Guest uptime (seconds), instead of an initial Guest TSC
+        #[clap(
+            long,
+            value_parser=maybe_hex::<u64>,
+            conflicts_with = "initial-guest-tsc"
+        )]
+        initial_guest_uptime_sec: Option<u64>,
+
         /// Guest Frequency (Hz)
         #[clap(
             short = 'g',
-            value_parser=maybe_hex::<u64>,
+            value_parser=parse_frequency,
             default_value = "1000000000")
         ]
         guest_hz: u64,
@@ -184,12 +563,14 @@ enum CalcCommand {
         /// Host Frequency (Hz)
         #[clap(
             short = 'f',
-            value_parser=maybe_hex::<u64>,
+            value_parser=parse_frequency,
             default_value = "1000000000")
         ]
         host_hz: u64,
 
-        /// Calculate related values in assembly, rust, or both
+        /// Calculate related values in assembly, rust, or both (exiting
+        /// nonzero if they disagree). The asm can trap on inputs the rust
+        /// rejects, so it's only run on inputs the rust accepts.
         #[clap(short = 'm', arg_enum, default_value = "rust")]
         math_impl: MathImpl,
 
@@ -203,11 +584,11 @@ enum CalcCommand {
     /// Compute the frequency multiplier for a guest and a host
     Freq {
         /// Host Frequency (Hz)
-        #[clap(short = 'f', value_parser=maybe_hex::<u64>)]
+        #[clap(short = 'f', value_parser=parse_frequency)]
         host_hz: u64,
 
         /// Guest Frequency (Hz)
-        #[clap(short = 'g', value_parser=maybe_hex::<u64>)]
+        #[clap(short = 'g', value_parser=parse_frequency)]
         guest_hz: u64,
 
         /// Number of int bits in multiplier
@@ -218,416 +599,4829 @@ enum CalcCommand {
         #[clap(long, default_value = "32")]
         frac_size: u32,
 
-        /// Calculate related values in assembly, rust, or both
+        /// Calculate related values in assembly, rust, or both (exiting
+        /// nonzero if they disagree). The asm can trap on inputs the rust
+        /// rejects, so it's only run on inputs the rust accepts.
         #[clap(short = 'm', arg_enum, default_value = "rust")]
         math_impl: MathImpl,
-    },
-}
-
-fn cmd_simulate(
-    duration: usize,
-    guest_hz: u64,
-    hosts: Vec<HostDef>,
-    arch: Arch,
-    print_hex: bool,
-) {
-    assert!(hosts.len() > 0);
 
-    println!(" {:<15} {} {:<30}", "DURATION", duration, "seconds");
-    println!(" {:>15} {} {:<30}", "GUEST FREQUENCY", guest_hz, "Hz");
-    println!("");
-    for (i, h) in hosts.iter().enumerate() {
-        println!(" {:<15}", format!("HOST {}", i));
-        println!(" {:>15} {} {:<30}", "START TIME", h.start, "seconds");
-        println!(" {:>15} {:<30}", "TSC", h.host_tsc);
-        println!(" {:>15} {} {:<30}", "FREQUENCY", h.host_freq, "Hz");
-        println!("");
-    }
-    println!("");
+        /// Also break the multiplier down into its integer and fractional
+        /// parts, and the ratio it represents
+        #[clap(long, takes_value = false)]
+        explain: bool,
+    },
 
-    let (int_size, frac_size) = match arch {
-        Arch::Amd => (INT_SIZE_AMD, FRAC_SIZE_AMD),
-        Arch::Intel => (INT_SIZE_INTEL, FRAC_SIZE_INTEL),
-    };
-    let num_hosts = hosts.len();
-    let mut start_guest_tsc = 0;
-    let mut cur_guest_tsc = start_guest_tsc;
+    /// Show which fractional bits of the frequency multiplier are set, and
+    /// how much of the ratio is lost to truncation
+    BitContribution {
+        /// Host Frequency (Hz)
+        #[clap(short = 'f', value_parser=parse_frequency)]
+        host_hz: u64,
 
-    println!("{:<10} {:>16} {:>16}", "TIME", "GUEST_TSC", "HOST_TSC");
+        /// Guest Frequency (Hz)
+        #[clap(short = 'g', value_parser=parse_frequency)]
+        guest_hz: u64,
 
-    for h in 0..num_hosts {
-        let start = hosts[h].start;
+        /// Number of int bits in multiplier
+        #[clap(long, default_value = "8")]
+        int_size: u32,
 
-        // end time is either: the duration, or the start of the next host
-        let end = if num_hosts == (h + 1) {
-            duration
-        } else {
-            hosts[h + 1].start
-        };
+        /// Number of frac bits in multiplier
+        #[clap(long, default_value = "32")]
+        frac_size: u32,
+    },
 
-        let start_host_tsc = hosts[h].host_tsc;
-        let host_hz = hosts[h].host_freq;
-        let desc = if h == 0 {
-            "GUEST_BOOT ".to_string()
-        } else {
-            format!("MIGRATION {} ", h)
-        };
+    /// Show the range of guest/host ratios a multiplier format can
+    /// represent, and its resolution
+    Ratio {
+        /// Number of int bits in multiplier
+        #[clap(long, default_value = "8")]
+        int_size: u32,
 
-        // print the header for this host
-        println!("=== {desc:=<77}");
+        /// Number of frac bits in multiplier
+        #[clap(long, default_value = "32")]
+        frac_size: u32,
+    },
 
-        let mut cur_host_tsc = start_host_tsc;
+    /// Find a guest frequency near a target whose ratio to each of a set of
+    /// host frequencies is exactly representable
+    CommonExactFreq {
+        /// Host Frequency (Hz), may be repeated
+        #[clap(short = 'f', value_parser=parse_frequency, required = true)]
+        host_hz: Vec<u64>,
 
-        for t in start..=end {
-            // find the guest TSC for this point in time
-            match guest_tsc(
-                start_host_tsc,
-                start_guest_tsc,
-                host_hz,
-                guest_hz,
-                cur_host_tsc,
-                frac_size,
-                int_size,
-            ) {
-                Ok(tsc) => {
-                    cur_guest_tsc = tsc;
-                }
-                Err(e) => {
-                    eprintln!("could not calculate guest tsc: {}", e);
-                    return;
-                }
-            }
+        /// Target Guest Frequency (Hz)
+        #[clap(short = 'g', value_parser=parse_frequency)]
+        guest_hz: u64,
 
-            // print the host and guest TSC values
-            if print_hex {
-                println!(
-                    "{:<10} {:#16x} {:#16x}",
-                    t, cur_guest_tsc, cur_host_tsc,
-                );
-            } else {
-                println!(
-                    "{:<10} {:#16} {:#16}",
-                    t, cur_guest_tsc, cur_host_tsc,
-                );
-            }
+        /// Search window around the target (Hz)
+        #[clap(
+            short = 'w',
+            long,
+            value_parser=maybe_hex::<u64>,
+            default_value = "1000000"
+        )]
+        window: u64,
 
-            cur_host_tsc = tsc_incr(cur_host_tsc, host_hz);
-        }
+        /// Number of int bits in multiplier
+        #[clap(long, default_value = "8")]
+        int_size: u32,
 
-        start_guest_tsc = cur_guest_tsc;
-    }
-}
+        /// Number of frac bits in multiplier
+        #[clap(long, default_value = "32")]
+        frac_size: u32,
+    },
 
-fn parse_hosts(
-    initial_host_tsc: u64,
-    initial_host_hz: u64,
-    input_hosts: Vec<String>,
-    duration: usize,
-) -> anyhow::Result<Vec<HostDef>> {
-    let mut res: Vec<HostDef> = Vec::new();
-    res.push(HostDef {
-        start: 0,
-        host_tsc: initial_host_tsc,
-        host_freq: initial_host_hz,
-    });
+    /// Compute the error in a guest's TSC offset due to multiplier truncation
+    OffsetError {
+        /// Initial Host TSC value
+        #[clap(value_parser=maybe_hex::<u64>)]
+        initial_host_tsc: u64,
 
-    for s in input_hosts.iter() {
-        let mut split = s.split(" ");
+        /// Guest Frequency (Hz)
+        #[clap(
+            short = 'g',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        guest_hz: u64,
 
-        let start: usize = split.next().unwrap().parse()?;
-        let host_tsc: u64 = split.next().unwrap().parse()?;
-        let host_freq: u64 = split.next().unwrap().parse()?;
+        /// Host Frequency (Hz)
+        #[clap(
+            short = 'f',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        host_hz: u64,
 
-        if start > duration {
-            return Err(anyhow!("cannot migrate past duration"));
-        }
+        // AMD defaults
+        #[clap(long, default_value = "8")]
+        int_size: u32,
+        #[clap(long, default_value = "32")]
+        frac_size: u32,
+    },
 
-        res.push(HostDef {
-            start,
-            host_tsc,
-            host_freq,
-        });
-    }
+    /// Compute a guest's TSC offset and show its two's complement encoding
+    OffsetBits {
+        /// Initial Host TSC value
+        #[clap(value_parser=maybe_hex::<u64>)]
+        initial_host_tsc: u64,
 
-    // sort by time order
-    res.sort_by(|a, b| a.start.cmp(&b.start));
+        /// Initial Guest TSC value
+        #[clap(
+            short = 't',
+            long,
+            value_parser=maybe_hex::<u64>,
+            default_value = "0")
+        ]
+        initial_guest_tsc: u64,
 
-    // TODO: check for duplicates
+        /// Guest Frequency (Hz)
+        #[clap(
+            short = 'g',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        guest_hz: u64,
+
+        /// Host Frequency (Hz)
+        #[clap(
+            short = 'f',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        host_hz: u64,
+
+        // AMD defaults
+        #[clap(long, default_value = "8")]
+        int_size: u32,
+        #[clap(long, default_value = "32")]
+        frac_size: u32,
+    },
+
+    /// Compute the TSC offset for a cold migration, onto a host whose TSC
+    /// was reset and is behind the guest's
+    ColdMigrate {
+        /// Guest TSC value to resume with
+        #[clap(value_parser=maybe_hex::<u64>)]
+        guest_tsc: u64,
+
+        /// Destination Host TSC value when the guest resumes
+        #[clap(value_parser=maybe_hex::<u64>)]
+        dest_host_tsc: u64,
+
+        /// Guest Frequency (Hz)
+        #[clap(
+            short = 'g',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        guest_hz: u64,
+
+        /// Destination Host Frequency (Hz)
+        #[clap(
+            short = 'f',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        host_hz: u64,
+
+        // AMD defaults
+        #[clap(long, default_value = "8")]
+        int_size: u32,
+        #[clap(long, default_value = "32")]
+        frac_size: u32,
+    },
+
+    /// Compute the guest ticks gained or lost by a single migration
+    HopError {
+        /// Guest TSC value at migration
+        #[clap(value_parser=maybe_hex::<u64>)]
+        guest_tsc: u64,
+
+        /// Guest Frequency (Hz)
+        #[clap(
+            short = 'g',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        guest_hz: u64,
+
+        /// Source Host TSC value at migration
+        #[clap(long, value_parser=maybe_hex::<u64>)]
+        src_tsc: u64,
+
+        /// Source Host Frequency (Hz)
+        #[clap(long, value_parser=parse_frequency, default_value = "1000000000")]
+        src_hz: u64,
+
+        /// Destination Host TSC value at migration
+        #[clap(long, value_parser=maybe_hex::<u64>)]
+        dst_tsc: u64,
+
+        /// Destination Host Frequency (Hz)
+        #[clap(long, value_parser=parse_frequency, default_value = "1000000000")]
+        dst_hz: u64,
+
+        // AMD defaults
+        #[clap(long, default_value = "8")]
+        int_size: u32,
+        #[clap(long, default_value = "32")]
+        frac_size: u32,
+    },
+
+    /// Check that a guest's TSC is continuous across a migration, computing
+    /// it on the source and destination hosts at the migration instant
+    VerifyMigration {
+        /// Source Host TSC value at boot (initial guest TSC: 0)
+        #[clap(long, value_parser=maybe_hex::<u64>)]
+        src_boot_tsc: u64,
+
+        /// Source Host Frequency (Hz)
+        #[clap(long, value_parser=parse_frequency, default_value = "1000000000")]
+        src_hz: u64,
+
+        /// Source Host TSC value at migration
+        #[clap(long, value_parser=maybe_hex::<u64>)]
+        src_tsc: u64,
+
+        /// Guest Frequency (Hz)
+        #[clap(
+            short = 'g',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        guest_hz: u64,
+
+        /// Destination Host TSC value at migration
+        #[clap(long, value_parser=maybe_hex::<u64>)]
+        dst_tsc: u64,
+
+        /// Destination Host Frequency (Hz)
+        #[clap(long, value_parser=parse_frequency, default_value = "1000000000")]
+        dst_hz: u64,
+
+        // AMD defaults
+        #[clap(long, default_value = "8")]
+        int_size: u32,
+        #[clap(long, default_value = "32")]
+        frac_size: u32,
+    },
+
+    /// Compute the destination host TSC at which a guest TSC deadline falls,
+    /// for a guest migrated between hosts
+    DeadlineMap {
+        /// Guest TSC deadline
+        #[clap(value_parser=maybe_hex::<u64>)]
+        deadline: u64,
+
+        /// Initial source Host TSC value (at boot or time of migration)
+        #[clap(short = 'i', value_parser=maybe_hex::<u64>)]
+        initial_host_tsc: u64,
+
+        /// Initial Guest TSC value
+        #[clap(
+            short = 't',
+            long,
+            value_parser=maybe_hex::<u64>,
+            default_value = "0")
+        ]
+        initial_guest_tsc: u64,
+
+        /// Source Host Frequency (Hz)
+        #[clap(
+            short = 'f',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        host_hz: u64,
+
+        /// Guest Frequency (Hz)
+        #[clap(
+            short = 'g',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        guest_hz: u64,
+
+        /// Source Host TSC value at migration
+        #[clap(long, value_parser=maybe_hex::<u64>)]
+        src_tsc: u64,
+
+        /// Destination Host TSC value at migration
+        #[clap(long, value_parser=maybe_hex::<u64>)]
+        dst_tsc: u64,
+
+        /// Destination Host Frequency (Hz)
+        #[clap(long, value_parser=parse_frequency, default_value = "1000000000")]
+        dst_hz: u64,
+
+        // AMD defaults
+        #[clap(long, default_value = "8")]
+        int_size: u32,
+        #[clap(long, default_value = "32")]
+        frac_size: u32,
+    },
+
+    /// Print the guest TSC as a linear function of the host TSC, and how it
+    /// changes across a migration
+    LineEquation {
+        /// Initial Host TSC value (at boot or time of migration)
+        #[clap(short = 'i', value_parser=maybe_hex::<u64>)]
+        initial_host_tsc: u64,
+
+        /// Initial Guest TSC value
+        #[clap(
+            short = 't',
+            long,
+            value_parser=maybe_hex::<u64>,
+            default_value = "0")
+        ]
+        initial_guest_tsc: u64,
+
+        /// Host Frequency (Hz)
+        #[clap(
+            short = 'f',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        host_hz: u64,
+
+        /// Guest Frequency (Hz)
+        #[clap(
+            short = 'g',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        guest_hz: u64,
+
+        /// Source Host TSC value at migration, to also show the line after a
+        /// migration
+        #[clap(long, value_parser=maybe_hex::<u64>, requires = "dst-tsc")]
+        src_tsc: Option<u64>,
+
+        /// Destination Host TSC value at migration
+        #[clap(long, value_parser=maybe_hex::<u64>, requires = "src-tsc")]
+        dst_tsc: Option<u64>,
+
+        /// Destination Host Frequency (Hz)
+        #[clap(long, value_parser=parse_frequency, default_value = "1000000000")]
+        dst_hz: u64,
+
+        // AMD defaults
+        #[clap(long, default_value = "8")]
+        int_size: u32,
+        #[clap(long, default_value = "32")]
+        frac_size: u32,
+    },
+
+    /// Compute the TSC offset to write when resuming a paused guest, either
+    /// continuing from where it was paused or catching up to real time
+    ResumeAt {
+        /// Initial Host TSC value (at boot or time of migration)
+        #[clap(short = 'i', value_parser=maybe_hex::<u64>)]
+        initial_host_tsc: u64,
+
+        /// Initial Guest TSC value
+        #[clap(
+            short = 't',
+            long,
+            value_parser=maybe_hex::<u64>,
+            default_value = "0")
+        ]
+        initial_guest_tsc: u64,
+
+        /// Host TSC value when the guest was paused
+        #[clap(value_parser=maybe_hex::<u64>)]
+        pause_host_tsc: u64,
+
+        /// Host TSC value when the guest is resumed
+        #[clap(value_parser=maybe_hex::<u64>)]
+        resume_host_tsc: u64,
+
+        /// How to account for the time the guest was paused
+        #[clap(long, arg_enum, default_value = "continue")]
+        mode: ResumeModeArg,
+
+        /// Wall-clock time the guest was paused (seconds), for realtime mode;
+        /// defaults to the host TSC elapsed between pause and resume
+        #[clap(long)]
+        paused_sec: Option<u64>,
+
+        /// Host Frequency (Hz)
+        #[clap(
+            short = 'f',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        host_hz: u64,
+
+        /// Guest Frequency (Hz)
+        #[clap(
+            short = 'g',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        guest_hz: u64,
+
+        // AMD defaults
+        #[clap(long, default_value = "8")]
+        int_size: u32,
+        #[clap(long, default_value = "32")]
+        frac_size: u32,
+    },
+
+    /// Compute the TSC offset to write when resuming a paused guest on the
+    /// same host, so the guest sees no time elapse during the pause
+    ResumeRegisters {
+        /// Initial Host TSC value (at boot or time of migration)
+        #[clap(short = 'i', value_parser=maybe_hex::<u64>)]
+        initial_host_tsc: u64,
+
+        /// Initial Guest TSC value
+        #[clap(
+            short = 't',
+            long,
+            value_parser=maybe_hex::<u64>,
+            default_value = "0")
+        ]
+        initial_guest_tsc: u64,
+
+        /// Host TSC value when the guest was paused
+        #[clap(value_parser=maybe_hex::<u64>)]
+        pause_host_tsc: u64,
+
+        /// Host TSC value when the guest is resumed
+        #[clap(value_parser=maybe_hex::<u64>)]
+        resume_host_tsc: u64,
+
+        /// Host Frequency (Hz)
+        #[clap(
+            short = 'f',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        host_hz: u64,
+
+        /// Guest Frequency (Hz)
+        #[clap(
+            short = 'g',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        guest_hz: u64,
+
+        // AMD defaults
+        #[clap(long, default_value = "8")]
+        int_size: u32,
+        #[clap(long, default_value = "32")]
+        frac_size: u32,
+    },
+
+    /// Compute summary statistics of the guest TSC for host TSCs read from
+    /// stdin (one per line)
+    GuestTscStats {
+        /// Initial Host TSC value (at boot or time of migration)
+        #[clap(short = 'i', value_parser=maybe_hex::<u64>)]
+        initial_host_tsc: u64,
+
+        /// Initial Guest TSC value
+        #[clap(
+            short = 't',
+            long,
+            value_parser=maybe_hex::<u64>,
+            default_value = "0")
+        ]
+        initial_guest_tsc: u64,
+
+        /// Host Frequency (Hz)
+        #[clap(
+            short = 'f',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        host_hz: u64,
+
+        /// Guest Frequency (Hz)
+        #[clap(
+            short = 'g',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        guest_hz: u64,
+
+        // AMD defaults
+        #[clap(long, default_value = "8")]
+        int_size: u32,
+        #[clap(long, default_value = "32")]
+        frac_size: u32,
+    },
+
+    /// Compute the guest TSC for each elapsed time (nanoseconds since the
+    /// host TSC was at the anchor) read from stdin (one per line)
+    GuestTscAtTrace {
+        /// Initial Host TSC value (at boot or time of migration)
+        #[clap(short = 'i', value_parser=maybe_hex::<u64>)]
+        initial_host_tsc: u64,
+
+        /// Initial Guest TSC value
+        #[clap(
+            short = 't',
+            long,
+            value_parser=maybe_hex::<u64>,
+            default_value = "0")
+        ]
+        initial_guest_tsc: u64,
+
+        /// Host Frequency (Hz)
+        #[clap(
+            short = 'f',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        host_hz: u64,
+
+        /// Guest Frequency (Hz)
+        #[clap(
+            short = 'g',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        guest_hz: u64,
+
+        // AMD defaults
+        #[clap(long, default_value = "8")]
+        int_size: u32,
+        #[clap(long, default_value = "32")]
+        frac_size: u32,
+    },
+
+    /// Compute a guest's effective TSC frequency from two guest TSC readings
+    /// taken at known host TSC values
+    ObservedFreq {
+        /// Host TSC value of first reading
+        #[clap(value_parser=maybe_hex::<u64>)]
+        h1: u64,
+
+        /// Guest TSC value of first reading
+        #[clap(value_parser=maybe_hex::<u64>)]
+        g1: u64,
+
+        /// Host TSC value of second reading
+        #[clap(value_parser=maybe_hex::<u64>)]
+        h2: u64,
+
+        /// Guest TSC value of second reading
+        #[clap(value_parser=maybe_hex::<u64>)]
+        g2: u64,
+
+        /// Host Frequency (Hz)
+        #[clap(
+            short = 'f',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        host_hz: u64,
+
+        /// Configured Guest Frequency (Hz)
+        #[clap(
+            short = 'g',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        guest_hz: u64,
+    },
+
+    /// Compute the host frequency for which a multiplier is the ratio to a
+    /// guest frequency
+    HostForMult {
+        /// Frequency multiplier
+        #[clap(value_parser=maybe_hex::<u64>)]
+        multiplier: u64,
+
+        /// Guest Frequency (Hz)
+        #[clap(
+            short = 'g',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        guest_hz: u64,
+
+        // AMD defaults
+        #[clap(long, default_value = "8")]
+        int_size: u32,
+        #[clap(long, default_value = "32")]
+        frac_size: u32,
+    },
+
+    /// Find the guest frequencies that share a guest frequency's multiplier,
+    /// and the smallest guest frequency step the format can resolve
+    Aliasing {
+        /// Guest Frequency (Hz)
+        #[clap(
+            short = 'g',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        guest_hz: u64,
+
+        /// Host Frequency (Hz)
+        #[clap(
+            short = 'f',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        host_hz: u64,
+
+        // AMD defaults
+        #[clap(long, default_value = "8")]
+        int_size: u32,
+        #[clap(long, default_value = "32")]
+        frac_size: u32,
+    },
+
+    /// Compute how far off the guest clock will be after a day, from the
+    /// truncated frequency multiplier
+    DailyError {
+        /// Guest Frequency (Hz)
+        #[clap(
+            short = 'g',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        guest_hz: u64,
+
+        /// Host Frequency (Hz)
+        #[clap(
+            short = 'f',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        host_hz: u64,
+
+        // AMD defaults
+        #[clap(long, default_value = "8")]
+        int_size: u32,
+        #[clap(long, default_value = "32")]
+        frac_size: u32,
+    },
+
+    /// Find the largest difference between the guest's hrtime and the true
+    /// elapsed time over a run, from the truncated frequency multiplier
+    MaxDrift {
+        /// Duration (seconds)
+        #[clap(short = 'd', long, default_value = "86400")]
+        duration: u64,
+
+        /// Guest Frequency (Hz)
+        #[clap(
+            short = 'g',
+            long,
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        guest_hz: u64,
+
+        /// Host Frequency (Hz)
+        #[clap(
+            short = 'f',
+            long,
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        host_hz: u64,
+
+        // AMD defaults
+        #[clap(long, default_value = "8")]
+        int_size: u32,
+        #[clap(long, default_value = "32")]
+        frac_size: u32,
+    },
+
+    /// Compute how often the low 32 bits of the guest TSC wrap, and the next
+    /// guest TSC at which they do
+    Wrap32 {
+        /// Guest Frequency (Hz)
+        #[clap(
+            short = 'g',
+            value_parser=parse_frequency,
+            default_value = "1000000000")
+        ]
+        guest_hz: u64,
+
+        /// Guest TSC to find the next wrap after
+        #[clap(long, value_parser=maybe_hex::<u64>, default_value = "0")]
+        start_tsc: u64,
+    },
+
+    /// Compute a host TSC frequency from two timestamped TSC readings
+    Calibrate {
+        /// Time of first sample (nanoseconds)
+        #[clap(value_parser=maybe_hex::<u64>)]
+        t1: u64,
+
+        /// TSC value of first sample
+        #[clap(value_parser=maybe_hex::<u64>)]
+        tsc1: u64,
+
+        /// Time of second sample (nanoseconds)
+        #[clap(value_parser=maybe_hex::<u64>)]
+        t2: u64,
+
+        /// TSC value of second sample
+        #[clap(value_parser=maybe_hex::<u64>)]
+        tsc2: u64,
+
+        /// Nominal frequency (Hz) to report deviation against
+        #[clap(short = 'n', long, value_parser=parse_frequency)]
+        nominal_hz: Option<u64>,
+    },
+}
+
+// The inputs to a simulation, once the simulate flags have been checked and
+// the hosts parsed
+struct SimulateOptions {
+    duration: usize,
+    guest_hz: u64,
+    actual_guest_hz: Option<u64>,
+    initial_guest_tsc: u64,
+    hosts: Vec<HostDef>,
+    int_size: u32,
+    frac_size: u32,
+    print_hex: bool,
+    as_seconds: bool,
+    pre_boot_sec: usize,
+    step_ns: u64,
+    boundary: Boundary,
+    metrics_port: Option<u16>,
+    pacing: Option<std::time::Duration>,
+    drift_histogram: bool,
+    show_drift: bool,
+    show_hrtime: bool,
+    human: bool,
+    plot: bool,
+    format: OutputFormat,
+    output: Option<std::path::PathBuf>,
+    realtime_tolerance_ppm: Option<f64>,
+    precision: Option<usize>,
+}
+
+fn cmd_simulate(opts: SimulateOptions) -> bool {
+    let SimulateOptions {
+        duration,
+        guest_hz,
+        actual_guest_hz,
+        initial_guest_tsc,
+        hosts,
+        int_size,
+        frac_size,
+        print_hex,
+        as_seconds,
+        pre_boot_sec,
+        step_ns,
+        boundary,
+        metrics_port,
+        pacing,
+        drift_histogram,
+        show_drift,
+        show_hrtime,
+        human,
+        plot,
+        format,
+        output,
+        realtime_tolerance_ppm,
+        precision,
+    } = opts;
+    assert!(!hosts.is_empty());
+
+    // rows only go to stdout as text; other formats are written to a file,
+    // or for json and csv, in place of everything else on stdout
+    let print_rows = format == OutputFormat::Text;
+    if print_rows && output.is_some() {
+        eprintln!("--output is only used with --format json, csv or parquet");
+        return false;
+    }
+    if step_ns == 0 {
+        eprintln!("step must be at least 1ns");
+        return false;
+    }
+    // documents record time in whole seconds
+    let whole_steps = step_ns.is_multiple_of(NS_PER_SEC as u64);
+    if !whole_steps && !print_rows {
+        eprintln!("--step-ns must be whole seconds unless --format is text");
+        return false;
+    }
+    let quiet = is_document(format) && output.is_none();
+    if quiet && (drift_histogram || plot || realtime_tolerance_ppm.is_some()) {
+        eprintln!(
+            "rows are written to stdout; use --output to also print a summary"
+        );
+        return false;
+    }
+
+    let out: Box<dyn std::io::Write> = match &output {
+        Some(path) if is_document(format) => {
+            match std::fs::File::create(path) {
+                Ok(f) => Box::new(std::io::BufWriter::new(f)),
+                Err(e) => {
+                    eprintln!("could not create {}: {}", path.display(), e);
+                    return false;
+                }
+            }
+        }
+        _ => Box::new(std::io::stdout()),
+    };
+    let mut doc: Option<Box<dyn RowSink>> = match format {
+        OutputFormat::Json => {
+            let header = json_out::Header {
+                duration,
+                guest_hz,
+                actual_guest_hz,
+                initial_guest_tsc,
+                hosts: hosts
+                    .iter()
+                    .map(|h| json_out::Host {
+                        start: h.start,
+                        host_tsc: h.host_tsc,
+                        host_freq: h.host_freq,
+                        guest_freq: h.guest_freq,
+                        tsc_event: h.tsc_event,
+                    })
+                    .collect(),
+            };
+            let meta = json_out::Meta::new(int_size, frac_size);
+            match json_out::RowWriter::create(out, &meta, &header) {
+                Ok(w) => Some(Box::new(w)),
+                Err(e) => {
+                    eprintln!("could not write header: {}", e);
+                    return false;
+                }
+            }
+        }
+        OutputFormat::Csv => match csv_out::RowWriter::create(out) {
+            Ok(w) => Some(Box::new(w)),
+            Err(e) => {
+                eprintln!("could not write header: {}", e);
+                return false;
+            }
+        },
+        _ => None,
+    };
+
+    #[cfg(not(feature = "parquet"))]
+    if format == OutputFormat::Parquet {
+        eprintln!(
+            "parquet output requires building with the `parquet` feature"
+        );
+        return false;
+    }
+
+    #[cfg(feature = "parquet")]
+    let mut parquet = match (format, &output) {
+        (OutputFormat::Parquet, Some(path)) => {
+            match parquet_out::RowWriter::create(path) {
+                Ok(w) => Some(w),
+                Err(e) => {
+                    eprintln!("could not create {}: {}", path.display(), e);
+                    return false;
+                }
+            }
+        }
+        (OutputFormat::Parquet, None) => {
+            eprintln!("parquet output requires --output");
+            return false;
+        }
+        _ => None,
+    };
+
+    #[cfg(not(feature = "metrics"))]
+    if metrics_port.is_some() {
+        eprintln!("metrics require building with the `metrics` feature");
+        return false;
+    }
+
+    #[cfg(feature = "metrics")]
+    let metrics = match metrics_port.map(metrics::MetricsServer::start) {
+        Some(Ok(m)) => Some(m),
+        Some(Err(e)) => {
+            eprintln!("could not start metrics server: {}", e);
+            return false;
+        }
+        None => None,
+    };
+
+    if !quiet {
+        print_scenario(
+            duration,
+            guest_hz,
+            actual_guest_hz,
+            initial_guest_tsc,
+            &hosts,
+        );
+    }
+
+    let num_hosts = hosts.len();
+    let mut start_guest_tsc = initial_guest_tsc;
+    let mut cur_guest_tsc = start_guest_tsc;
+
+    // the guest frequency can change at each retune; until then, the TSC is
+    // scaled to the actual frequency, which may differ from the advertised one
+    let mut nominal_guest_hz = guest_hz;
+    let mut cur_guest_hz = actual_guest_hz.unwrap_or(guest_hz);
+    let (mut host_index, mut retune_index, mut event_index) = (0, 0, 0);
+
+    // the guest TSC each host's steps are anchored to, which a TSC event
+    // keeps the offset from
+    let mut anchor_guest_tsc = start_guest_tsc;
+
+    // per-step increment error, in guest ticks, mapped to how often it occurs
+    let mut drift_hist: std::collections::BTreeMap<i128, usize> =
+        std::collections::BTreeMap::new();
+
+    // the drift at each step shown, to plot
+    let mut plot_drift: Vec<i128> = Vec::new();
+
+    if !quiet {
+        let mut header =
+            format!("{:<10} {:>16} {:>16}", "TIME", "GUEST_TSC", "HOST_TSC");
+        if as_seconds {
+            header += &format!(" {:>20}", "GUEST_SECONDS");
+        }
+        if show_drift {
+            header += &format!(" {:>16}", "DRIFT_NS");
+        }
+        if show_hrtime {
+            header += &format!(" {:>20} {:>20}", "GUEST_HRTIME", "HOST_HRTIME");
+        }
+        println!("{}", header);
+    }
+
+    // extrapolate back from the boot host's anchor
+    if pre_boot_sec > 0 {
+        let boot = &hosts[0];
+
+        if !quiet {
+            println!("=== {:=<77}", "PRE_BOOT ");
+        }
+
+        // every second before the limit underflows, so say so once rather
+        // than for each of them
+        let limit = match pre_boot_limit(
+            boot.host_tsc,
+            boot.host_freq,
+            initial_guest_tsc,
+            cur_guest_hz,
+            frac_size,
+            int_size,
+        ) {
+            Ok(l) => l as usize,
+            Err(e) => {
+                eprintln!("could not calculate guest tsc: {}", e);
+                return false;
+            }
+        };
+        if pre_boot_sec > limit && print_rows {
+            let t = if limit == 0 {
+                "0".to_string()
+            } else {
+                format!("-{limit}")
+            };
+            println!("would underflow before t={}", t);
+        }
+
+        for n in (1..=pre_boot_sec.min(limit)).rev() {
+            let time = format!("-{n}");
+            let host_tsc = boot.host_tsc - n as u64 * boot.host_freq;
+
+            match guest_tsc_signed(
+                boot.host_tsc,
+                initial_guest_tsc,
+                boot.host_freq,
+                cur_guest_hz,
+                host_tsc,
+                frac_size,
+                int_size,
+            ) {
+                Ok(tsc) => {
+                    if let Some(w) = &mut doc {
+                        let time = -(n as i64);
+                        let phase = Phase::PreBoot;
+                        if let Err(e) =
+                            w.push(time, tsc as u64, host_tsc, 0, phase)
+                        {
+                            eprintln!("could not write row: {}", e);
+                            return false;
+                        }
+                    }
+
+                    #[cfg(feature = "parquet")]
+                    if let Some(w) = &mut parquet {
+                        let time = -(n as i64);
+                        if let Err(e) = w.push(time, tsc as u64, host_tsc, 0) {
+                            eprintln!("could not write row: {}", e);
+                            return false;
+                        }
+                    }
+
+                    if print_rows {
+                        let drift = show_drift.then(|| {
+                            drift_ns(
+                                tsc as u64,
+                                initial_guest_tsc,
+                                guest_hz,
+                                -(n as i128) * NS_PER_SEC as i128,
+                            )
+                        });
+                        let hrtimes = match show_hrtime
+                            .then(|| {
+                                row_hrtimes(
+                                    tsc as u64,
+                                    guest_hz,
+                                    host_tsc,
+                                    boot.host_freq,
+                                )
+                            })
+                            .transpose()
+                        {
+                            Ok(h) => h,
+                            Err(e) => {
+                                eprintln!("could not calculate hrtime: {}", e);
+                                return false;
+                            }
+                        };
+                        println!(
+                            "{}",
+                            fmt_row(
+                                &time, tsc as u64, host_tsc, guest_hz,
+                                print_hex, as_seconds, drift, hrtimes, human
+                            )
+                        );
+                    }
+                }
+                Err(e) => {
+                    eprintln!("could not calculate guest tsc: {}", e);
+                    return false;
+                }
+            }
+        }
+    }
+
+    for h in 0..num_hosts {
+        let (steps, show_last) = host_steps(&hosts, h, duration, boundary);
+
+        let start_host_tsc = hosts[h].host_tsc;
+        let host_hz = hosts[h].host_freq;
+        let (desc, phase) = match hosts[h].guest_freq {
+            _ if h == 0 => ("GUEST_BOOT ".to_string(), Phase::Boot),
+            _ if hosts[h].tsc_event => {
+                event_index += 1;
+                (format!("TSC EVENT {} ", event_index), Phase::TscEvent)
+            }
+            Some(freq) => {
+                nominal_guest_hz = freq;
+                cur_guest_hz = freq;
+                retune_index += 1;
+                (format!("RETUNE {} ", retune_index), Phase::Retune)
+            }
+            None => {
+                host_index += 1;
+                let desc = format!("MIGRATION {} ", host_index);
+                (desc, Phase::Migration)
+            }
+        };
+
+        // print the header for this host
+        if !quiet {
+            println!("=== {desc:=<77}");
+        }
+
+        // a TSC event keeps the offset, so the guest TSC jumps by the scaled
+        // change in the host TSC rather than carrying on where it left off
+        if hosts[h].tsc_event {
+            start_guest_tsc = match guest_tsc(
+                hosts[h - 1].host_tsc,
+                anchor_guest_tsc,
+                host_hz,
+                cur_guest_hz,
+                start_host_tsc,
+                frac_size,
+                int_size,
+            ) {
+                Ok(tsc) => tsc,
+                Err(e) => {
+                    eprintln!("could not calculate guest tsc: {}", e);
+                    return false;
+                }
+            };
+        }
+        anchor_guest_tsc = start_guest_tsc;
+
+        // the multiplier and offset are fixed for as long as the guest is on
+        // this host
+        let (start_ns, end_ns) = match steps_ns(&steps) {
+            Ok(ns) => ns,
+            Err(e) => {
+                eprintln!("{}", e);
+                return false;
+            }
+        };
+        let points = match HostSteps::new(
+            start_host_tsc,
+            start_guest_tsc,
+            host_hz,
+            cur_guest_hz,
+            frac_size,
+            int_size,
+            start_ns,
+            end_ns,
+            step_ns,
+        ) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("could not calculate guest tsc: {}", e);
+                return false;
+            }
+        };
+
+        let mut prev = None;
+
+        for p in points {
+            let (t_ns, cur_host_tsc, guest) = match p {
+                Ok(p) => p,
+                Err(e) => {
+                    eprintln!("could not calculate guest tsc: {}", e);
+                    return false;
+                }
+            };
+            let t = t_ns / NS_PER_SEC as u64;
+            cur_guest_tsc = guest;
+
+            // the ideal increment is the guest frequency the guest was told,
+            // over the length of the step
+            if let Some((prev_ns, prev_tsc)) = prev {
+                let step = t_ns - prev_ns;
+                let err = cur_guest_tsc as i128
+                    - prev_tsc as i128
+                    - nominal_guest_hz as i128 * step as i128
+                        / NS_PER_SEC as i128;
+                *drift_hist.entry(err).or_insert(0) += 1;
+            }
+            prev = Some((t_ns, cur_guest_tsc));
+
+            // the next host's first step is at the same time, and it's only
+            // shown once; this step still hands off the guest TSC to it
+            if t_ns == end_ns && !show_last {
+                break;
+            }
+
+            if plot {
+                plot_drift.push(drift_ns(
+                    cur_guest_tsc,
+                    initial_guest_tsc,
+                    guest_hz,
+                    t_ns as i128,
+                ));
+            }
+
+            if let Some(w) = &mut doc {
+                if let Err(e) = w.push(
+                    t as i64,
+                    cur_guest_tsc,
+                    cur_host_tsc,
+                    host_index,
+                    phase,
+                ) {
+                    eprintln!("could not write row: {}", e);
+                    return false;
+                }
+            }
+
+            #[cfg(feature = "parquet")]
+            if let Some(w) = &mut parquet {
+                if let Err(e) =
+                    w.push(t as i64, cur_guest_tsc, cur_host_tsc, host_index)
+                {
+                    eprintln!("could not write row: {}", e);
+                    return false;
+                }
+            }
+
+            // print the host and guest TSC values
+            if print_rows {
+                let time = match t_ns % NS_PER_SEC as u64 {
+                    0 => t.to_string(),
+                    frac => format!("{}.{:09}", t, frac)
+                        .trim_end_matches('0')
+                        .to_string(),
+                };
+                let drift = show_drift.then(|| {
+                    drift_ns(
+                        cur_guest_tsc,
+                        initial_guest_tsc,
+                        guest_hz,
+                        t_ns as i128,
+                    )
+                });
+                let hrtimes = match show_hrtime
+                    .then(|| {
+                        row_hrtimes(
+                            cur_guest_tsc,
+                            nominal_guest_hz,
+                            cur_host_tsc,
+                            host_hz,
+                        )
+                    })
+                    .transpose()
+                {
+                    Ok(h) => h,
+                    Err(e) => {
+                        eprintln!("could not calculate hrtime: {}", e);
+                        return false;
+                    }
+                };
+                println!(
+                    "{}",
+                    fmt_row(
+                        &time,
+                        cur_guest_tsc,
+                        cur_host_tsc,
+                        nominal_guest_hz,
+                        print_hex,
+                        as_seconds,
+                        drift,
+                        hrtimes,
+                        human
+                    )
+                );
+            }
+
+            // publish this step
+            #[cfg(feature = "metrics")]
+            if let Some(m) = &metrics {
+                m.update(metrics::Sample {
+                    time_sec: t as usize,
+                    host_index: host_index as usize,
+                    guest_tsc: cur_guest_tsc,
+                    host_tsc: cur_host_tsc,
+                    drift_ns: drift_ns(
+                        cur_guest_tsc,
+                        initial_guest_tsc,
+                        guest_hz,
+                        t_ns as i128,
+                    ),
+                });
+            }
+
+            // wait for the next step to elapse in real time
+            if let (Some(sec), true) = (pacing, t_ns < end_ns) {
+                let step = step_ns.min(end_ns - t_ns);
+                std::thread::sleep(
+                    sec.mul_f64(step as f64 / NS_PER_SEC as f64),
+                );
+            }
+        }
+
+        start_guest_tsc = cur_guest_tsc;
+    }
+
+    if let Some(w) = doc {
+        match w.finish() {
+            Ok(rows) if !quiet => {
+                println!();
+                println!(
+                    "wrote {} rows to {}",
+                    rows,
+                    output.as_ref().unwrap().display()
+                );
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("could not finish writing rows: {}", e);
+                return false;
+            }
+        }
+    }
+
+    #[cfg(feature = "parquet")]
+    if let Some(w) = parquet {
+        match w.finish() {
+            Ok(rows) => {
+                println!();
+                println!(
+                    "wrote {} rows to {}",
+                    rows,
+                    output.as_ref().unwrap().display()
+                );
+            }
+            Err(e) => {
+                eprintln!("could not finish writing rows: {}", e);
+                return false;
+            }
+        }
+    }
+
+    if drift_histogram {
+        println!();
+        print_drift_histogram(&drift_hist, precision);
+    }
+
+    if plot {
+        println!();
+        print_drift_plot(&plot_drift);
+    }
+
+    if actual_guest_hz.is_some() && !quiet {
+        print_realized_freq(
+            duration,
+            guest_hz,
+            initial_guest_tsc,
+            cur_guest_tsc,
+            precision,
+        );
+    }
+
+    match realtime_tolerance_ppm {
+        Some(tolerance) => check_realtime(
+            duration,
+            guest_hz,
+            initial_guest_tsc,
+            cur_guest_tsc,
+            tolerance,
+            precision,
+        ),
+        None => true,
+    }
+}
+
+// One format's run under --compare-arch: where its guest TSC has got to, or
+// why it stopped
+struct ArchRun {
+    name: &'static str,
+    int_size: u32,
+    frac_size: u32,
+    start_guest_tsc: u64,
+    anchor_guest_tsc: u64,
+    error: Option<String>,
+}
+
+// Simulate a guest under both the AMD and Intel multiplier formats, printing
+// each one's guest TSC and how far apart they are at each step. A format that
+// can't run on a host, such as one whose ratio is too large for it, is left
+// out of the rows from then on and noted on them. Returns whether either
+// format ran to the end.
+#[allow(clippy::too_many_arguments)]
+fn cmd_simulate_compare_arch(
+    duration: usize,
+    guest_hz: u64,
+    actual_guest_hz: Option<u64>,
+    initial_guest_tsc: u64,
+    hosts: Vec<HostDef>,
+    step_ns: u64,
+    boundary: Boundary,
+    print_hex: bool,
+) -> bool {
+    assert!(!hosts.is_empty());
+
+    if step_ns == 0 {
+        eprintln!("step must be at least 1ns");
+        return false;
+    }
+
+    let mut runs =
+        [("amd", Arch::Amd), ("intel", Arch::Intel)].map(|(name, arch)| {
+            let (int_size, frac_size) = arch.format();
+            ArchRun {
+                name,
+                int_size,
+                frac_size,
+                start_guest_tsc: initial_guest_tsc,
+                anchor_guest_tsc: initial_guest_tsc,
+                error: None,
+            }
+        });
+
+    print_scenario(
+        duration,
+        guest_hz,
+        actual_guest_hz,
+        initial_guest_tsc,
+        &hosts,
+    );
+    for run in runs.iter() {
+        println!(" {:>15} {}.{}", run.name, run.int_size, run.frac_size);
+    }
+    println!();
+    println!(
+        "{:<10} {:>16} {:>20} {:>20} {:>12}",
+        "TIME", "HOST_TSC", "AMD_GUEST_TSC", "INTEL_GUEST_TSC", "INTEL-AMD"
+    );
+
+    let fmt_tsc = |tsc: Option<u64>| match tsc {
+        Some(tsc) if print_hex => format!("{:#x}", tsc),
+        Some(tsc) => tsc.to_string(),
+        None => "-".to_string(),
+    };
+
+    let mut cur_guest_hz = actual_guest_hz.unwrap_or(guest_hz);
+    for h in 0..hosts.len() {
+        let (steps, show_last) = host_steps(&hosts, h, duration, boundary);
+        let (start_ns, end_ns) = match steps_ns(&steps) {
+            Ok(ns) => ns,
+            Err(e) => {
+                eprintln!("{}", e);
+                return false;
+            }
+        };
+        let host = &hosts[h];
+        if let Some(freq) = host.guest_freq {
+            cur_guest_hz = freq;
+        }
+
+        let desc = match host.guest_freq {
+            _ if h == 0 => "GUEST_BOOT ".to_string(),
+            _ if host.tsc_event => format!("TSC EVENT AT {}s ", host.start),
+            Some(_) => format!("RETUNE AT {}s ", host.start),
+            None => format!("MIGRATION AT {}s ", host.start),
+        };
+        println!("=== {desc:=<77}");
+
+        // each format's steps on this host, if it's still running
+        let mut points: Vec<Option<HostSteps>> = Vec::new();
+        for run in runs.iter_mut() {
+            if run.error.is_some() {
+                points.push(None);
+                continue;
+            }
+
+            let steps = (|| {
+                // a TSC event keeps the offset, so the guest TSC jumps by the
+                // scaled change in the host TSC
+                if host.tsc_event {
+                    run.start_guest_tsc = guest_tsc(
+                        hosts[h - 1].host_tsc,
+                        run.anchor_guest_tsc,
+                        host.host_freq,
+                        cur_guest_hz,
+                        host.host_tsc,
+                        run.frac_size,
+                        run.int_size,
+                    )?;
+                }
+                run.anchor_guest_tsc = run.start_guest_tsc;
+
+                HostSteps::new(
+                    host.host_tsc,
+                    run.start_guest_tsc,
+                    host.host_freq,
+                    cur_guest_hz,
+                    run.frac_size,
+                    run.int_size,
+                    start_ns,
+                    end_ns,
+                    step_ns,
+                )
+            })();
+            match steps {
+                Ok(p) => points.push(Some(p)),
+                Err(e) => {
+                    println!(
+                        "{}: could not calculate guest tsc: {}",
+                        run.name, e
+                    );
+                    run.error = Some(e.to_string());
+                    points.push(None);
+                }
+            }
+        }
+
+        loop {
+            // the next step of each format still running
+            let mut step = None;
+            let mut guest = [None; 2];
+            for (i, run) in runs.iter_mut().enumerate() {
+                match points[i].as_mut().and_then(|p| p.next()) {
+                    Some(Ok((t_ns, host_tsc, tsc))) => {
+                        step = Some((t_ns, host_tsc));
+                        guest[i] = Some(tsc);
+                        run.start_guest_tsc = tsc;
+                    }
+                    Some(Err(e)) => {
+                        println!(
+                            "{}: could not calculate guest tsc: {}",
+                            run.name, e
+                        );
+                        run.error = Some(e.to_string());
+                        points[i] = None;
+                    }
+                    None => points[i] = None,
+                }
+            }
+            let Some((t_ns, host_tsc)) = step else {
+                break;
+            };
+
+            // the next host's first step is at the same time
+            if t_ns == end_ns && !show_last {
+                continue;
+            }
+
+            let time = match t_ns % NS_PER_SEC as u64 {
+                0 => (t_ns / NS_PER_SEC as u64).to_string(),
+                frac => format!("{}.{:09}", t_ns / NS_PER_SEC as u64, frac)
+                    .trim_end_matches('0')
+                    .to_string(),
+            };
+            let diff = match guest {
+                [Some(amd), Some(intel)] => {
+                    (intel as i128 - amd as i128).to_string()
+                }
+                _ => "-".to_string(),
+            };
+            let missing: Vec<&str> = runs
+                .iter()
+                .zip(guest.iter())
+                .filter(|(_, g)| g.is_none())
+                .map(|(run, _)| run.name)
+                .collect();
+            let note = if missing.is_empty() {
+                String::new()
+            } else {
+                format!(" (no {})", missing.join(", "))
+            };
+            println!(
+                "{:<10} {:>16} {:>20} {:>20} {:>12}{}",
+                time,
+                fmt_tsc(Some(host_tsc)),
+                fmt_tsc(guest[0]),
+                fmt_tsc(guest[1]),
+                diff,
+                note
+            );
+        }
+
+        if runs.iter().all(|run| run.error.is_some()) {
+            return false;
+        }
+    }
+
+    true
+}
+
+// The time steps simulated on host `h`, and whether the last of them is shown.
+// A host runs the guest from its start time up to the next host's start time,
+// or the end of the run. When the guest migrates away, that last step is the
+// same time as the next host's first step, so showing both would show the
+// migration time twice; with exclusive boundaries only the next host's is
+// shown.
+fn host_steps(
+    hosts: &[HostDef],
+    h: usize,
+    duration: usize,
+    boundary: Boundary,
+) -> (std::ops::RangeInclusive<usize>, bool) {
+    match hosts.get(h + 1) {
+        Some(next) => {
+            (hosts[h].start..=next.start, boundary == Boundary::Inclusive)
+        }
+        None => (hosts[h].start..=duration, true),
+    }
+}
+
+// The first and last of `steps`, in ns
+fn steps_ns(
+    steps: &std::ops::RangeInclusive<usize>,
+) -> anyhow::Result<(u64, u64)> {
+    let ns = |secs: usize| {
+        (secs as u64)
+            .checked_mul(NS_PER_SEC as u64)
+            .ok_or_else(|| anyhow!("time {}s overflows in ns", secs))
+    };
+
+    Ok((ns(*steps.start())?, ns(*steps.end())?))
+}
+
+// Compare the guest's elapsed time at the end of a simulation against the real
+// elapsed time, and report whether they differ by more than `tolerance_ppm`
+fn check_realtime(
+    duration: usize,
+    guest_hz: u64,
+    initial_guest_tsc: u64,
+    final_guest_tsc: u64,
+    tolerance_ppm: f64,
+    precision: Option<usize>,
+) -> bool {
+    let p = digits(precision, 6);
+
+    println!();
+    println!("=== {:=<77}", "REALTIME_CHECK ");
+
+    if duration == 0 {
+        println!("no time elapsed to check");
+        return true;
+    }
+
+    let guest_elapsed = match ticks_to_duration(
+        final_guest_tsc.saturating_sub(initial_guest_tsc),
+        guest_hz,
+    ) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("could not calculate guest elapsed time: {}", e);
+            return false;
+        }
+    };
+    let real_ns = duration as i128 * NS_PER_SEC as i128;
+    let diff_ns = guest_elapsed.as_nanos() as i128 - real_ns;
+    let ppm = diff_ns as f64 / real_ns as f64 * 1_000_000.0;
+
+    println!("real elapsed:  {} s", duration);
+    println!("guest elapsed: {:?}", guest_elapsed);
+    println!("discrepancy:   {} ns ({:.p$} ppm)", diff_ns, ppm);
+    println!("tolerance:     {:.p$} ppm", tolerance_ppm);
+
+    if ppm.abs() > tolerance_ppm {
+        println!("FAIL: guest time does not match real time");
+        false
+    } else {
+        println!("PASS");
+        true
+    }
+}
+
+// Compare the frequency the guest TSC was realized at over the run, once
+// scaled on each host, against the advertised frequency the guest believes
+fn print_realized_freq(
+    duration: usize,
+    nominal_hz: u64,
+    initial_guest_tsc: u64,
+    final_guest_tsc: u64,
+    precision: Option<usize>,
+) {
+    println!();
+    println!("=== {:=<77}", "REALIZED_FREQUENCY ");
+
+    if duration == 0 {
+        println!("no time elapsed to measure");
+        return;
+    }
+
+    let realized_hz = final_guest_tsc.saturating_sub(initial_guest_tsc) as f64
+        / duration as f64;
+    let ppm = (realized_hz - nominal_hz as f64) / nominal_hz as f64 * 1e6;
+
+    println!("nominal:  {} Hz", nominal_hz);
+    println!("realized: {:.p$} Hz", realized_hz, p = digits(precision, 3));
+    println!("error:    {:.p$} ppm", ppm, p = digits(precision, 6));
+}
+
+// Print the distribution of per-step guest TSC increment errors, along with
+// whether they cancel out over the run or accumulate into drift
+fn print_drift_histogram(
+    hist: &std::collections::BTreeMap<i128, usize>,
+    precision: Option<usize>,
+) {
+    const BAR_WIDTH: usize = 50;
+
+    println!("=== {:=<77}", "DRIFT_HISTOGRAM ");
+
+    let steps: usize = hist.values().sum();
+    if steps == 0 {
+        println!("no steps to report");
+        return;
+    }
+
+    let max_count = *hist.values().max().unwrap();
+    println!("{:>12} {:>10}", "ERROR_TICKS", "STEPS");
+    for (err, count) in hist {
+        let bar = (count * BAR_WIDTH).div_ceil(max_count);
+        println!("{:>12} {:>10} {}", err, count, "#".repeat(bar));
+    }
+    println!();
+
+    let total: i128 = hist.iter().map(|(e, c)| e * *c as i128).sum();
+    let worst = hist.keys().map(|e| e.abs()).max().unwrap();
+    println!("steps:       {}", steps);
+    println!(
+        "mean error:  {:.p$} ticks",
+        total as f64 / steps as f64,
+        p = digits(precision, 3)
+    );
+    println!("total drift: {} ticks", total);
+
+    // if the accumulated error is no worse than a single step's, the
+    // per-step errors are cancelling out rather than adding up
+    if total.abs() <= worst {
+        println!("error is bounded (oscillating)");
+    } else {
+        println!("error is cumulative (biased)");
+    }
+}
+
+// Render values as a sparkline of block characters, scaled from the smallest
+// value to the largest. Values beyond `width` are bucketed, keeping the one
+// furthest from 0 in each bucket, so that spikes still show.
+fn sparkline(values: &[i128], width: usize) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let points: Vec<i128> = if values.len() > width {
+        (0..width)
+            .map(|i| {
+                let n = values.len();
+                let bucket = &values[i * n / width..(i + 1) * n / width];
+                *bucket.iter().max_by_key(|v| v.abs()).unwrap()
+            })
+            .collect()
+    } else {
+        values.to_vec()
+    };
+
+    let (Some(&lo), Some(&hi)) = (points.iter().min(), points.iter().max())
+    else {
+        return String::new();
+    };
+    points
+        .iter()
+        .map(|&v| match hi - lo {
+            0 => BLOCKS[0],
+            range => BLOCKS[((v - lo) * 7 / range) as usize],
+        })
+        .collect()
+}
+
+// The terminal's width in columns, from $COLUMNS
+fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .filter(|&c| c > 0)
+        .unwrap_or(80)
+}
+
+// Plot the guest's drift from real time over the run, to show at a glance
+// whether it's bounded or growing
+fn print_drift_plot(drift: &[i128]) {
+    println!("=== {:=<77}", "DRIFT_PLOT ");
+
+    if drift.is_empty() {
+        println!("no steps to report");
+        return;
+    }
+
+    println!("{}", sparkline(drift, terminal_width()));
+    println!(
+        "min: {} ns, max: {} ns, steps: {}",
+        drift.iter().min().unwrap(),
+        drift.iter().max().unwrap(),
+        drift.len()
+    );
+}
+
+// With --both-signs, the u64 and i64 interpretations of a 64-bit result, to
+// cross-reference against tools that may show either
+fn signs(raw: u64, both_signs: bool) -> String {
+    if both_signs {
+        format!(" [u64: {}, i64: {}]", raw, raw as i64)
+    } else {
+        String::new()
+    }
+}
+
+// The number of fractional digits to print a floating point value with:
+// --precision if given, otherwise the value's own default
+fn digits(precision: Option<usize>, default: usize) -> usize {
+    precision.unwrap_or(default)
+}
+
+// Whether the asm and rust implementations agree: both computing the same
+// value, or both rejecting the inputs
+fn cross_check<T: PartialEq, E1, E2>(
+    asm: &Result<T, E1>,
+    rs: &Result<T, E2>,
+) -> bool {
+    match (asm, rs) {
+        (Ok(a), Ok(r)) => a == r,
+        (Err(_), Err(_)) => true,
+        _ => false,
+    }
+}
+
+// How far a result is from the reference value it should match, as an absolute
+// difference and a fraction of the reference
+fn divergence(value: i128, reference: i128) -> String {
+    let diff = value.abs_diff(reference);
+    if diff == 0 {
+        return "identical".to_string();
+    }
+
+    if reference == 0 {
+        format!("differ by {} (reference is 0)", diff)
+    } else {
+        let pct = diff as f64 / reference.unsigned_abs() as f64 * 100.0;
+        format!("differ by {} ({:.3e}% of {})", diff, pct, reference)
+    }
+}
+
+// Print the guest and the hosts it runs on, ahead of the simulated steps
+fn print_scenario(
+    duration: usize,
+    guest_hz: u64,
+    actual_guest_hz: Option<u64>,
+    initial_guest_tsc: u64,
+    hosts: &[HostDef],
+) {
+    println!(" {:<15} {} {:<30}", "DURATION", duration, "seconds");
+    println!(" {:>15} {} {:<30}", "GUEST FREQUENCY", guest_hz, "Hz");
+    if let Some(actual) = actual_guest_hz {
+        println!(" {:>15} {} {:<30}", "ACTUAL FREQ", actual, "Hz");
+    }
+    if initial_guest_tsc != 0 {
+        println!(" {:>15} {:<30}", "GUEST TSC", initial_guest_tsc);
+    }
+    println!();
+    let (mut num_migrations, mut num_retunes, mut num_events) = (0, 0, 0);
+    for h in hosts.iter() {
+        match h.guest_freq {
+            _ if h.tsc_event => {
+                num_events += 1;
+                println!(" {:<15}", format!("TSC EVENT {}", num_events));
+                println!(" {:>15} {} {:<30}", "START TIME", h.start, "seconds");
+                println!(" {:>15} {:<30}", "TSC", h.host_tsc);
+            }
+            Some(freq) => {
+                num_retunes += 1;
+                println!(" {:<15}", format!("RETUNE {}", num_retunes));
+                println!(" {:>15} {} {:<30}", "START TIME", h.start, "seconds");
+                println!(" {:>15} {:<30}", "TSC", h.host_tsc);
+                println!(" {:>15} {} {:<30}", "GUEST FREQUENCY", freq, "Hz");
+            }
+            None => {
+                println!(" {:<15}", format!("HOST {}", num_migrations));
+                println!(" {:>15} {} {:<30}", "START TIME", h.start, "seconds");
+                println!(" {:>15} {:<30}", "TSC", h.host_tsc);
+                println!(" {:>15} {} {:<30}", "FREQUENCY", h.host_freq, "Hz");
+                num_migrations += 1;
+            }
+        }
+        println!();
+    }
+    println!();
+}
+
+// Format a row of simulation output
+#[allow(clippy::too_many_arguments)]
+fn fmt_row(
+    time: &str,
+    guest_tsc: u64,
+    host_tsc: u64,
+    guest_hz: u64,
+    print_hex: bool,
+    as_seconds: bool,
+    drift_ns: Option<i128>,
+    hrtimes: Option<(u64, u64)>,
+    human: bool,
+) -> String {
+    let mut row = if print_hex {
+        format!("{:<10} {:#16x} {:#16x}", time, guest_tsc, host_tsc)
+    } else {
+        format!("{:<10} {:#16} {:#16}", time, guest_tsc, host_tsc)
+    };
+    if as_seconds {
+        row += &format!(" {:>20}", fmt_seconds(guest_tsc, guest_hz));
+    }
+    if let Some(drift) = drift_ns {
+        row += &format!(" {:>16}", drift);
+    }
+    if let Some((guest_ns, host_ns)) = hrtimes {
+        if human {
+            let ns = NS_PER_SEC as u64;
+            row += &format!(
+                " {:>20} {:>20}",
+                fmt_seconds(guest_ns, ns),
+                fmt_seconds(host_ns, ns)
+            );
+        } else {
+            row += &format!(" {:>20} {:>20}", guest_ns, host_ns);
+        }
+    }
+
+    row
+}
+
+// The guest and host TSCs as hrtime (ns), each at its own frequency
+fn row_hrtimes(
+    guest_tsc: u64,
+    guest_hz: u64,
+    host_tsc: u64,
+    host_hz: u64,
+) -> Result<(u64, u64), MathError> {
+    Ok((
+        hrtime(guest_tsc, guest_hz, Rounding::Truncate)?,
+        hrtime(host_tsc, host_hz, Rounding::Truncate)?,
+    ))
+}
+
+// How far a guest's elapsed time since boot, read from its TSC at the guest
+// frequency, is ahead of `t_ns` of real time since boot (ns)
+fn drift_ns(
+    guest_tsc: u64,
+    initial_guest_tsc: u64,
+    guest_hz: u64,
+    t_ns: i128,
+) -> i128 {
+    let guest_ns = (guest_tsc as i128 - initial_guest_tsc as i128)
+        * NS_PER_SEC as i128
+        / guest_hz as i128;
+
+    guest_ns - t_ns
+}
+
+// Format a tick count at a given frequency as fractional seconds
+fn fmt_seconds(ticks: u64, freq_hz: u64) -> String {
+    let secs = ticks / freq_hz;
+    let rem_ns =
+        ((ticks % freq_hz) as u128 * NS_PER_SEC as u128) / freq_hz as u128;
+
+    format!("{}.{:09}", secs, rem_ns)
+}
+
+// The generated migrations, as flags to replay them with
+fn replay_args(seed: u64, hosts: &[HostDef]) -> String {
+    let mut out = format!("random migrations (seed {}), to replay:\n", seed);
+    for h in hosts {
+        out.push_str(&format!("    --migrate \"{}\"\n", migrate_arg(h)));
+    }
+
+    out
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn cmd_offset(
+    initial_host_tsc: u64,
+    initial_guest_tsc: u64,
+    guest_hz: u64,
+    host_hz: u64,
+    math_impl: MathImpl,
+    frac_size: u32,
+    int_size: u32,
+    both_signs: bool,
+) -> bool {
+    println!("calculating TSC offset for parameters:");
+    println!("\tHost:");
+    println!(
+        "\t\tinitial TSC: {initial_host_tsc} ({:#x})",
+        initial_host_tsc
+    );
+    println!("\t\tfrequency: {host_hz} Hz");
+    println!("\tGuest:");
+    println!(
+        "\t\tinitial TSC: {initial_guest_tsc} ({:#x})",
+        initial_guest_tsc
+    );
+    println!("\t\tfrequency: {guest_hz} Hz");
+    println!("\tImplementation: {:?}", math_impl);
+    println!();
+
+    let rs_res = tsc_offset(
+        initial_host_tsc,
+        initial_guest_tsc,
+        guest_hz,
+        host_hz,
+        frac_size,
+        int_size,
+    );
+    // the asm can trap on inputs the rust rejects, so it only runs on inputs
+    // the rust accepts
+    let asm_res = match (math_impl, &rs_res) {
+        (MathImpl::Rust, _) => Err(anyhow!("not run")),
+        (MathImpl::All, Err(_)) => Err(anyhow!(
+            "not run, as the rust implementation rejects these inputs"
+        )),
+        _ if would_freq_multiplier_overflow(
+            guest_hz, host_hz, frac_size, int_size,
+        ) =>
+        {
+            Err(anyhow!("not run, as the multiplier would overflow"))
+        }
+        _ => asm_math::calc_tsc_offset(
+            initial_host_tsc,
+            initial_guest_tsc,
+            guest_hz,
+            host_hz,
+            frac_size,
+        )
+        .map_err(anyhow::Error::from),
+    };
+
+    // whether every result shown could be calculated and, with both
+    // implementations, whether they agree
+    let mut ok = true;
+    let mut agree = true;
+    match math_impl {
+        MathImpl::Asm => match asm_res {
+            Ok(offset) => {
+                println!(
+                    "TSC offset: {} ({:#x}){}",
+                    offset,
+                    offset,
+                    signs(offset_to_raw(offset), both_signs)
+                );
+            }
+            Err(e) => {
+                ok = false;
+                eprintln!("could not calculate TSC offset: {}", e);
+            }
+        },
+        MathImpl::Rust => match rs_res {
+            Ok(offset) => {
+                println!(
+                    "TSC offset: {} ({:#x}){}",
+                    offset,
+                    offset,
+                    signs(offset_to_raw(offset), both_signs)
+                );
+            }
+            Err(e) => {
+                ok = false;
+                eprintln!("could not calculate TSC offset: {}", e);
+            }
+        },
+        MathImpl::All => {
+            // compared before the results are consumed below
+            agree = cross_check(&asm_res, &rs_res);
+            let diff = match (&asm_res, &rs_res) {
+                (Ok(asm), Ok(rs)) => {
+                    Some(divergence(*asm as i128, *rs as i128))
+                }
+                _ => None,
+            };
+            match asm_res {
+                Ok(offset) => {
+                    println!(
+                        "TSC offset (asm):  {} ({:#x}){}",
+                        offset,
+                        offset,
+                        signs(offset_to_raw(offset), both_signs)
+                    );
+                }
+                Err(e) => {
+                    ok = false;
+                    eprintln!("could not calculate TSC offset (asm): {}", e);
+                }
+            }
+            match rs_res {
+                Ok(offset) => {
+                    println!(
+                        "TSC offset (rust): {} ({:#x}){}",
+                        offset,
+                        offset,
+                        signs(offset_to_raw(offset), both_signs)
+                    );
+                }
+                Err(e) => {
+                    ok = false;
+                    eprintln!("could not calculate TSC offset (rust): {}", e);
+                }
+            }
+            match diff {
+                Some(diff) => println!("asm vs rust:       {}", diff),
+                None if !agree => println!("asm vs rust:       MISMATCH"),
+                None => {}
+            }
+        }
+    }
+
+    ok && agree
+}
+
+fn cmd_offset_error(
+    initial_host_tsc: u64,
+    guest_hz: u64,
+    host_hz: u64,
+    frac_size: u32,
+    int_size: u32,
+    precision: Option<usize>,
+) -> bool {
+    println!("calculating TSC offset truncation error for parameters:");
+    println!("\tHost:");
+    println!(
+        "\t\tinitial TSC: {initial_host_tsc} ({:#x})",
+        initial_host_tsc
+    );
+    println!("\t\tfrequency: {host_hz} Hz");
+    println!("\tGuest:");
+    println!("\t\tfrequency: {guest_hz} Hz");
+    println!();
+    println!("\tMultiplier format:\t{}.{}", int_size, frac_size);
+    println!();
+
+    let err = match offset_error(
+        initial_host_tsc,
+        guest_hz,
+        host_hz,
+        frac_size,
+        int_size,
+    ) {
+        Ok(e) => e,
+        Err(e) => {
+            eprintln!("could not calculate TSC offset error: {}", e);
+            return false;
+        }
+    };
+
+    let p = digits(precision, 3);
+    println!("Offset error: {:.p$} ticks", err);
+    println!(
+        "Offset error: {:.p$} ns",
+        err * NS_PER_SEC as f64 / guest_hz as f64
+    );
+
+    true
+}
+
+fn cmd_cold_migrate(
+    guest_tsc: u64,
+    dest_host_tsc: u64,
+    guest_hz: u64,
+    host_hz: u64,
+    frac_size: u32,
+    int_size: u32,
+    both_signs: bool,
+) -> bool {
+    println!("calculating cold migration TSC offset for parameters:");
+    println!("\tDestination Host:");
+    println!("\t\tTSC: {dest_host_tsc} ({:#x})", dest_host_tsc);
+    println!("\t\tfrequency: {host_hz} Hz");
+    println!("\tGuest:");
+    println!("\t\tTSC: {guest_tsc} ({:#x})", guest_tsc);
+    println!("\t\tfrequency: {guest_hz} Hz");
+    println!();
+
+    let offset = match cold_migration_offset(
+        dest_host_tsc,
+        guest_tsc,
+        guest_hz,
+        host_hz,
+        frac_size,
+        int_size,
+    ) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("could not calculate cold migration TSC offset: {}", e);
+            return false;
+        }
+    };
+
+    println!(
+        "TSC offset: {} ({:#018x}){}",
+        offset,
+        offset_to_raw(offset),
+        signs(offset_to_raw(offset), both_signs)
+    );
+    println!("Headroom before i64 overflow: {}", i64::MAX - offset);
+
+    true
+}
+
+fn cmd_offset_bits(
+    initial_host_tsc: u64,
+    initial_guest_tsc: u64,
+    guest_hz: u64,
+    host_hz: u64,
+    frac_size: u32,
+    int_size: u32,
+) -> bool {
+    println!("calculating TSC offset encoding for parameters:");
+    println!("\tHost:");
+    println!(
+        "\t\tinitial TSC: {initial_host_tsc} ({:#x})",
+        initial_host_tsc
+    );
+    println!("\t\tfrequency: {host_hz} Hz");
+    println!("\tGuest:");
+    println!(
+        "\t\tinitial TSC: {initial_guest_tsc} ({:#x})",
+        initial_guest_tsc
+    );
+    println!("\t\tfrequency: {guest_hz} Hz");
+    println!();
+
+    let offset = match tsc_offset(
+        initial_host_tsc,
+        initial_guest_tsc,
+        guest_hz,
+        host_hz,
+        frac_size,
+        int_size,
+    ) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("could not calculate TSC offset: {}", e);
+            return false;
+        }
+    };
+    let raw = offset_to_raw(offset);
+
+    // set the sign bit apart from the rest, and group the bits by byte
+    let bits = format!("{:064b}", raw);
+    let mut bin = format!("[{}]", &bits[..1]);
+    for (i, c) in bits.chars().enumerate().skip(1) {
+        if i % 8 == 0 {
+            bin.push(' ');
+        }
+        bin.push(c);
+    }
+
+    println!("TSC offset (i64): {}", offset);
+    println!("TSC offset (raw): {:#018x}", raw);
+    println!("TSC offset (bin): {}", bin);
+    println!(
+        "Sign bit:         {} ({})",
+        raw >> 63,
+        if offset < 0 {
+            "negative"
+        } else {
+            "non-negative"
+        }
+    );
+
+    let back = offset_from_raw(raw);
+    if back == offset {
+        println!("Raw bits reinterpreted as i64: {} (ok)", back);
+    } else {
+        println!("Raw bits reinterpreted as i64: {} (MISMATCH)", back);
+    }
+
+    true
+}
+
+#[allow(clippy::too_many_arguments)]
+fn cmd_freq_multiplier(
+    guest_hz: u64,
+    host_hz: u64,
+    math_impl: MathImpl,
+    int_size: u32,
+    frac_size: u32,
+    explain: bool,
+    precision: Option<usize>,
+    both_signs: bool,
+) -> bool {
+    println!("calculating frequency multiplier for parameters:");
+    println!("\tHost:");
+    println!("\t\tfrequency: {host_hz} Hz");
+    println!("\tGuest:");
+    println!("\t\tfrequency: {guest_hz} Hz");
+    println!();
+    println!("\tMultiplier format:\t{}.{}", int_size, frac_size);
+    println!("\tImplementation:\t{:?}", math_impl);
+    println!();
+
+    let rs_res = freq_multiplier(guest_hz, host_hz, frac_size, int_size);
+    // the asm can trap on inputs the rust rejects, so it only runs on inputs
+    // the rust accepts
+    let asm_res = match (math_impl, &rs_res) {
+        (MathImpl::Rust, _) => Err(anyhow!("not run")),
+        (MathImpl::All, Err(_)) => Err(anyhow!(
+            "not run, as the rust implementation rejects these inputs"
+        )),
+        _ if would_freq_multiplier_overflow(
+            guest_hz, host_hz, frac_size, int_size,
+        ) =>
+        {
+            Err(anyhow!("not run, as the multiplier would overflow"))
+        }
+        _ => asm_math::freq_multiplier(guest_hz, host_hz, frac_size)
+            .map_err(anyhow::Error::from),
+    };
+
+    // the multiplier shown, to break down if asked
+    let shown = match math_impl {
+        MathImpl::Asm => asm_res.as_ref().ok().copied(),
+        _ => rs_res.as_ref().ok().copied(),
+    };
+
+    // with both implementations, whether they agree
+    let mut agree = true;
+    match math_impl {
+        MathImpl::Asm => match asm_res {
+            Ok(m) => {
+                println!(
+                    "Frequency multiplier: {} ({:#x}){}",
+                    m,
+                    m,
+                    signs(m, both_signs)
+                );
+            }
+            Err(e) => {
+                eprintln!("could not calculate frequency multiplier: {}", e);
+            }
+        },
+        MathImpl::Rust => match rs_res {
+            Ok(m) => {
+                println!(
+                    "Frequency multiplier: {} ({:#x}){}",
+                    m,
+                    m,
+                    signs(m, both_signs)
+                );
+            }
+            Err(e) => {
+                eprintln!("could not calculate frequency multiplier: {}", e);
+            }
+        },
+        MathImpl::All => {
+            // compared before the results are consumed below
+            agree = cross_check(&asm_res, &rs_res);
+            let diff = match (&asm_res, &rs_res) {
+                (Ok(asm), Ok(rs)) => {
+                    Some(divergence(*asm as i128, *rs as i128))
+                }
+                _ => None,
+            };
+            match asm_res {
+                Ok(m) => {
+                    println!(
+                        "Frequency multiplier (asm):  {} ({:#x}){}",
+                        m,
+                        m,
+                        signs(m, both_signs)
+                    );
+                }
+                Err(e) => {
+                    eprintln!(
+                        "could not calculate frequency multiplier (asm): {}",
+                        e
+                    );
+                }
+            }
+            match rs_res {
+                Ok(m) => {
+                    println!(
+                        "Frequency multiplier (rust): {} ({:#x}){}",
+                        m,
+                        m,
+                        signs(m, both_signs)
+                    );
+                }
+                Err(e) => {
+                    eprintln!(
+                        "could not calculate frequency multiplier (rust): {}",
+                        e
+                    );
+                }
+            }
+            match diff {
+                Some(diff) => println!("asm vs rust:                 {}", diff),
+                None if !agree => {
+                    println!("asm vs rust:                 MISMATCH")
+                }
+                None => {}
+            }
+        }
+    }
+
+    if let Ok(d) = guest_elapsed(
+        std::time::Duration::from_secs(1),
+        guest_hz,
+        host_hz,
+        frac_size,
+        int_size,
+    ) {
+        println!();
+        println!("Guest time per host second: {:?}", d);
+    }
+
+    if let Some(k) = ratio_is_power_of_two(guest_hz, host_hz) {
+        println!();
+        let dir = if k < 0 { "right" } else { "left" };
+        println!(
+            "Ratio is 2^{}: exact, scale by shifting {} {} bit(s)",
+            k,
+            dir,
+            k.abs()
+        );
+    }
+
+    if let (true, Some(m)) = (explain, shown) {
+        let p = digits(precision, 20);
+        let frac = m & ((1u64 << frac_size) - 1);
+
+        println!();
+        println!("Multiplier breakdown:");
+        println!("\tvalue:\t\t{}", m);
+        println!("\thex:\t\t{}", hex_grouped(m));
+        println!("\tint part:\t{}", m >> frac_size);
+        println!("\tfrac part:\t{}", fixed_point_decimal(frac, frac_size, p));
+        println!("\tratio:\t\t{}", fixed_point_decimal(m, frac_size, p));
+    }
+
+    // fails if the multiplier could not be calculated
+    agree && shown.is_some()
+}
+
+// Format a value in hex with its digits grouped in fours, e.g. 0x1_8000_0000
+fn hex_grouped(value: u64) -> String {
+    let hex = format!("{:x}", value);
+    let mut out = String::from("0x");
+    for (i, c) in hex.chars().enumerate() {
+        if i > 0 && (hex.len() - i) % 4 == 0 {
+            out.push('_');
+        }
+        out.push(c);
+    }
+
+    out
+}
+
+// The exact decimal value of a fixed-point number with `frac_size` fractional
+// bits, truncated to `digits` decimal places
+fn fixed_point_decimal(value: u64, frac_size: u32, digits: usize) -> String {
+    let mut out = format!("{}.", value >> frac_size);
+    let mut frac = (value & ((1u64 << frac_size) - 1)) as u128;
+    for _ in 0..digits.max(1) {
+        frac *= 10;
+        out.push(char::from(b'0' + (frac >> frac_size) as u8));
+        frac &= (1u128 << frac_size) - 1;
+    }
+
+    out
+}
+
+#[allow(clippy::too_many_arguments)]
+fn cmd_guest_tsc(
+    initial_host_tsc: u64,
+    initial_guest_tsc: u64,
+    host_tsc: u64,
+    host_hz: u64,
+    guest_hz: u64,
+    math_impl: MathImpl,
+    int_size: u32,
+    frac_size: u32,
+    offset_bits: u32,
+    tsc_width: u32,
+    saturate: bool,
+    both_signs: bool,
+) -> bool {
+    println!("calculating guest TSC for parameters:");
+    println!("\tHost:");
+    println!(
+        "\t\tinitial TSC: {initial_host_tsc} ({:#x})",
+        initial_host_tsc
+    );
+    println!("\t\tcurrent TSC: {host_tsc} ({:#x})", host_tsc);
+    println!("\t\tfrequency: {host_hz} Hz");
+    println!("\tGuest:");
+    println!(
+        "\t\tinitial TSC: {initial_guest_tsc} ({:#x})",
+        initial_guest_tsc
+    );
+    println!("\t\tfrequency: {guest_hz} Hz");
+    println!("\tImplementation: {:?}", math_impl);
+    if offset_bits < 64 {
+        println!("\tOffset field width: {offset_bits} bits");
+    }
+    if tsc_width < 64 {
+        println!("\tGuest TSC width: {tsc_width} bits");
+    }
+    println!();
+
+    let rs_res = guest_tsc(
+        initial_host_tsc,
+        initial_guest_tsc,
+        host_hz,
+        guest_hz,
+        host_tsc,
+        frac_size,
+        int_size,
+    );
+    // the asm can trap on inputs the rust rejects, so it only runs on inputs
+    // the rust accepts
+    let asm_res = match (math_impl, &rs_res) {
+        (MathImpl::Rust, _) => Err(anyhow!("not run")),
+        (MathImpl::All, Err(_)) => Err(anyhow!(
+            "not run, as the rust implementation rejects these inputs"
+        )),
+        _ if would_freq_multiplier_overflow(
+            guest_hz, host_hz, frac_size, int_size,
+        ) =>
+        {
+            Err(anyhow!("not run, as the multiplier would overflow"))
+        }
+        _ => asm_math::calc_guest_tsc(
+            initial_host_tsc,
+            initial_guest_tsc,
+            host_hz,
+            guest_hz,
+            host_tsc,
+            frac_size,
+        )
+        .map_err(anyhow::Error::from),
+    };
+
+    // keep the full-width result to compare a truncated offset against
+    let full_tsc = rs_res.as_ref().ok().copied();
+
+    // whether every result shown could be calculated and, with both
+    // implementations, whether they agree
+    let mut ok = true;
+    let mut agree = true;
+    match math_impl {
+        MathImpl::Asm => match asm_res {
+            Ok(tsc) => {
+                println!(
+                    "Guest TSC: {} ({:#x}){}",
+                    tsc,
+                    tsc,
+                    signs(tsc, both_signs)
+                );
+            }
+            Err(e) => {
+                ok = false;
+                eprintln!("could not calculate guest TSC: {}", e);
+            }
+        },
+        MathImpl::Rust => match rs_res {
+            Ok(tsc) => {
+                println!(
+                    "Guest TSC: {} ({:#x}){}",
+                    tsc,
+                    tsc,
+                    signs(tsc, both_signs)
+                );
+            }
+            Err(e) => {
+                ok = false;
+                eprintln!("could not calculate guest TSC: {}", e);
+            }
+        },
+        MathImpl::All => {
+            // compared before the results are consumed below
+            agree = cross_check(&asm_res, &rs_res);
+            let diff = match (&asm_res, &rs_res) {
+                (Ok(asm), Ok(rs)) => {
+                    Some(divergence(*asm as i128, *rs as i128))
+                }
+                _ => None,
+            };
+            match asm_res {
+                Ok(tsc) => {
+                    println!(
+                        "Guest TSC (asm):  {} ({:#x}){}",
+                        tsc,
+                        tsc,
+                        signs(tsc, both_signs)
+                    );
+                }
+                Err(e) => {
+                    ok = false;
+                    eprintln!("could not calculate guest TSC (asm): {}", e);
+                }
+            }
+            match rs_res {
+                Ok(tsc) => {
+                    println!(
+                        "Guest TSC (rust): {} ({:#x}){}",
+                        tsc,
+                        tsc,
+                        signs(tsc, both_signs)
+                    );
+                }
+                Err(e) => {
+                    ok = false;
+                    eprintln!("could not calculate guest TSC (rust): {}", e);
+                }
+            }
+            match diff {
+                Some(diff) => println!("asm vs rust:      {}", diff),
+                None if !agree => println!("asm vs rust:      MISMATCH"),
+                None => {}
+            }
+        }
+    }
+
+    if tsc_width < 64 && full_tsc.is_some() {
+        let mode = if saturate {
+            CeilingMode::Saturate
+        } else {
+            CeilingMode::Error
+        };
+        let res = tsc_width_ceiling(tsc_width).and_then(|ceiling| {
+            guest_tsc_ceiling(
+                initial_host_tsc,
+                initial_guest_tsc,
+                host_hz,
+                guest_hz,
+                host_tsc,
+                frac_size,
+                int_size,
+                ceiling,
+                mode,
+            )
+        });
+        match res {
+            Ok(tsc) => {
+                println!();
+                println!(
+                    "Guest TSC ({tsc_width} bits): {} ({:#x}){}",
+                    tsc,
+                    tsc,
+                    signs(tsc, both_signs)
+                );
+            }
+            Err(e) => {
+                ok = false;
+                eprintln!(
+                    "could not calculate {}-bit guest TSC: {}",
+                    tsc_width, e
+                );
+            }
+        }
+    }
+
+    if offset_bits < 64 {
+        if let Some(tsc) = full_tsc {
+            match truncated_offset_guest_tsc(
+                initial_host_tsc,
+                initial_guest_tsc,
+                host_hz,
+                guest_hz,
+                host_tsc,
+                frac_size,
+                int_size,
+                offset_bits,
+            ) {
+                Ok((offset, truncated)) => {
+                    println!();
+                    println!(
+                        "Truncated offset ({offset_bits} bits): {} ({:#x}){}",
+                        offset,
+                        offset,
+                        signs(offset_to_raw(offset), both_signs)
+                    );
+                    println!(
+                        "Guest TSC (truncated offset): {} ({:#x}){}",
+                        truncated,
+                        truncated,
+                        signs(truncated, both_signs)
+                    );
+                    println!(
+                        "Guest TSC error: {}",
+                        truncated as i128 - tsc as i128
+                    );
+                }
+                Err(e) => {
+                    ok = false;
+                    eprintln!(
+                        "could not calculate guest TSC with truncated offset: {}",
+                        e
+                    );
+                }
+            }
+        }
+    }
+
+    ok && agree
+}
+
+// The largest multiplier a format can hold: every int and frac bit set
+fn max_ratio_multiplier(int_size: u32, frac_size: u32) -> anyhow::Result<u64> {
+    check_format(int_size, frac_size)?;
+
+    Ok(u64::MAX >> (64 - int_size - frac_size))
+}
+
+fn cmd_ratio_bounds(
+    int_size: u32,
+    frac_size: u32,
+    precision: Option<usize>,
+) -> bool {
+    println!("calculating representable ratios for parameters:");
+    println!("\tMultiplier format:\t{}.{}", int_size, frac_size);
+    println!();
+
+    let max = match max_ratio_multiplier(int_size, frac_size) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("could not calculate ratio bounds: {}", e);
+            return false;
+        }
+    };
+
+    // 2^-frac_size takes exactly frac_size decimal places
+    let p = digits(precision, frac_size as usize);
+    println!(
+        "max_ratio:         {}",
+        fixed_point_decimal(max, frac_size, p)
+    );
+    println!(
+        "min_nonzero_ratio: {}",
+        fixed_point_decimal(1, frac_size, p)
+    );
+    println!(
+        "ulp:               {}",
+        fixed_point_decimal(1, frac_size, p)
+    );
+
+    true
+}
+
+fn cmd_bit_contribution(
+    guest_hz: u64,
+    host_hz: u64,
+    int_size: u32,
+    frac_size: u32,
+    precision: Option<usize>,
+    both_signs: bool,
+) -> bool {
+    println!("calculating fractional bit contributions for parameters:");
+    println!("\tHost:");
+    println!("\t\tfrequency: {host_hz} Hz");
+    println!("\tGuest:");
+    println!("\t\tfrequency: {guest_hz} Hz");
+    println!();
+    println!("\tMultiplier format:\t{}.{}", int_size, frac_size);
+    println!();
+
+    let m = match freq_multiplier(guest_hz, host_hz, frac_size, int_size) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("could not calculate frequency multiplier: {}", e);
+            return false;
+        }
+    };
+
+    let ideal = guest_hz as f64 / host_hz as f64;
+    let int_part = m >> frac_size;
+    let frac_mask = (1u64 << frac_size) - 1;
+
+    println!(
+        "Frequency multiplier: {} ({:#x}){}",
+        m,
+        m,
+        signs(m, both_signs)
+    );
+    println!(
+        "Fractional bits: {:0width$b}",
+        m & frac_mask,
+        width = frac_size as usize
+    );
+    println!();
+    println!("{:<8} {:>26}", "BIT", "VALUE");
+    println!("{:<8} {:>26}", "int", int_part);
+    for k in 1..=frac_size {
+        if m & (1u64 << (frac_size - k)) != 0 {
+            println!(
+                "{:<8} {:>26.p$}",
+                format!("2^-{k}"),
+                2f64.powi(-(k as i32)),
+                p = digits(precision, 20)
+            );
+        }
+    }
+    println!();
+
+    // The residual is exactly (guest_hz * 2^frac - m * host_hz), in units of
+    // 1 / (host_hz * 2^frac): the remainder dropped by the division
+    let rem = ((guest_hz as u128) << frac_size) - m as u128 * host_hz as u128;
+    let residual = rem as f64 / (host_hz as f64 * 2f64.powi(frac_size as i32));
+
+    let p = digits(precision, 20);
+    println!("Ideal ratio:       {:.p$}", ideal);
+    println!(
+        "Represented ratio: {:.p$}",
+        m as f64 / 2f64.powi(frac_size as i32)
+    );
+    println!(
+        "Residual:          {:.p$e}",
+        residual,
+        p = digits(precision, 6)
+    );
+
+    #[cfg(feature = "rational")]
+    if let Ok(exact) =
+        freq_multiplier_ratio(guest_hz, host_hz, frac_size, int_size)
+    {
+        println!(
+            "Represented ratio (exact): {}/{}",
+            exact.numer(),
+            exact.denom()
+        );
+    }
+
+    true
+}
+
+fn cmd_common_exact_freq(
+    host_hz: Vec<u64>,
+    guest_hz: u64,
+    window: u64,
+    int_size: u32,
+    frac_size: u32,
+) -> bool {
+    println!("searching for a common exact guest frequency for parameters:");
+    println!("\tHosts:");
+    for h in host_hz.iter() {
+        println!("\t\tfrequency: {h} Hz");
+    }
+    println!("\tGuest:");
+    println!("\t\ttarget frequency: {guest_hz} Hz");
+    println!("\t\tsearch window: +/- {window} Hz");
+    println!();
+    println!("\tMultiplier format:\t{}.{}", int_size, frac_size);
+    println!();
+
+    match common_exact_freq(guest_hz, &host_hz, frac_size, int_size, window) {
+        Ok(Some(g)) => {
+            println!(
+                "Guest frequency: {g} Hz ({:+} Hz from target)",
+                g as i128 - guest_hz as i128
+            );
+            for h in host_hz.iter() {
+                match freq_multiplier(g, *h, frac_size, int_size) {
+                    Ok(m) => {
+                        println!("\thost {h} Hz: multiplier {} ({:#x})", m, m)
+                    }
+                    Err(e) => eprintln!("\thost {h} Hz: {}", e),
+                }
+            }
+            true
+        }
+        Ok(None) => {
+            println!("No exactly representable guest frequency in window");
+            true
+        }
+        Err(e) => {
+            eprintln!("could not search for guest frequency: {}", e);
+            false
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn cmd_hop_error(
+    guest_tsc: u64,
+    guest_hz: u64,
+    src_tsc: u64,
+    src_hz: u64,
+    dst_tsc: u64,
+    dst_hz: u64,
+    int_size: u32,
+    frac_size: u32,
+) -> bool {
+    println!("calculating migration tick error for parameters:");
+    println!("\tSource Host:");
+    println!("\t\tmigration TSC: {src_tsc} ({:#x})", src_tsc);
+    println!("\t\tfrequency: {src_hz} Hz");
+    println!("\tDestination Host:");
+    println!("\t\tmigration TSC: {dst_tsc} ({:#x})", dst_tsc);
+    println!("\t\tfrequency: {dst_hz} Hz");
+    println!("\tGuest:");
+    println!("\t\tmigration TSC: {guest_tsc} ({:#x})", guest_tsc);
+    println!("\t\tfrequency: {guest_hz} Hz");
+    println!();
+
+    let src = HostParams {
+        host_tsc: src_tsc,
+        host_hz: src_hz,
+    };
+    let dst = HostParams {
+        host_tsc: dst_tsc,
+        host_hz: dst_hz,
+    };
+    match migration_tick_error(
+        src, dst, guest_hz, guest_tsc, frac_size, int_size,
+    ) {
+        Ok(err) => {
+            println!("Tick error across migration: {}", err);
+            true
+        }
+        Err(e) => {
+            eprintln!("could not calculate migration tick error: {}", e);
+            false
+        }
+    }
+}
+
+// Returns whether the guest TSC is the same on both sides of the migration
+#[allow(clippy::too_many_arguments)]
+fn cmd_verify_migration(
+    src_boot_tsc: u64,
+    src_hz: u64,
+    src_tsc: u64,
+    guest_hz: u64,
+    dst_tsc: u64,
+    dst_hz: u64,
+    int_size: u32,
+    frac_size: u32,
+) -> bool {
+    println!("verifying guest TSC continuity for parameters:");
+    println!("\tSource Host:");
+    println!("\t\tboot TSC: {src_boot_tsc} ({:#x})", src_boot_tsc);
+    println!("\t\tmigration TSC: {src_tsc} ({:#x})", src_tsc);
+    println!("\t\tfrequency: {src_hz} Hz");
+    println!("\tDestination Host:");
+    println!("\t\tmigration TSC: {dst_tsc} ({:#x})", dst_tsc);
+    println!("\t\tfrequency: {dst_hz} Hz");
+    println!("\tGuest Frequency:\t{guest_hz} Hz");
+    println!();
+
+    let (src, dst) = match migration_guest_tscs(
+        src_boot_tsc,
+        src_hz,
+        src_tsc,
+        guest_hz,
+        dst_tsc,
+        dst_hz,
+        int_size,
+        frac_size,
+    ) {
+        Ok(tscs) => tscs,
+        Err(e) => {
+            eprintln!("could not calculate guest tsc: {}", e);
+            return false;
+        }
+    };
+
+    println!("Guest TSC on source:\t\t{} ({:#x})", src, src);
+    println!("Guest TSC on destination:\t{} ({:#x})", dst, dst);
+    println!("Difference:\t\t\t{}", dst as i128 - src as i128);
+
+    if src != dst {
+        println!("MISMATCH: the guest TSC is not continuous");
+        return false;
+    }
+    println!("OK: the guest TSC is continuous");
+
+    true
+}
+
+// Compute the destination host TSC at which a guest TSC deadline falls after
+// migration, and where it would have fallen on the source host
+// Print the guest TSC on one host as a line in the host TSC: the slope is the
+// ratio the multiplier represents, and the intercept is the TSC offset
+fn print_line_equation(
+    multiplier: u64,
+    offset: i64,
+    frac_size: u32,
+    precision: Option<usize>,
+) {
+    let slope = multiplier as f64 / 2f64.powi(frac_size as i32);
+
+    println!("\tguest_tsc = slope * host_tsc + intercept");
+    println!(
+        "\tslope:     {}/2^{} ({:.p$})",
+        multiplier,
+        frac_size,
+        slope,
+        p = digits(precision, 12)
+    );
+    println!("\tintercept: {}", offset);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn cmd_line_equation(
+    initial_host_tsc: u64,
+    initial_guest_tsc: u64,
+    host_hz: u64,
+    guest_hz: u64,
+    migration: Option<(u64, u64)>,
+    dst_hz: u64,
+    int_size: u32,
+    frac_size: u32,
+    precision: Option<usize>,
+) -> bool {
+    println!("calculating guest TSC line equation for parameters:");
+    println!("\tHost:");
+    println!(
+        "\t\tinitial TSC: {initial_host_tsc} ({:#x})",
+        initial_host_tsc
+    );
+    println!("\t\tfrequency: {host_hz} Hz");
+    if let Some((src_tsc, dst_tsc)) = migration {
+        println!("\t\tmigration TSC: {src_tsc} ({:#x})", src_tsc);
+        println!("\tDestination Host:");
+        println!("\t\tmigration TSC: {dst_tsc} ({:#x})", dst_tsc);
+        println!("\t\tfrequency: {dst_hz} Hz");
+    }
+    println!("\tGuest:");
+    println!(
+        "\t\tinitial TSC: {initial_guest_tsc} ({:#x})",
+        initial_guest_tsc
+    );
+    println!("\t\tfrequency: {guest_hz} Hz");
+    println!();
+
+    let (m, offset) = match migration_params(
+        initial_host_tsc,
+        initial_guest_tsc,
+        guest_hz,
+        host_hz,
+        frac_size,
+        int_size,
+    ) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("could not calculate line equation: {}", e);
+            return false;
+        }
+    };
+
+    println!("Host:");
+    print_line_equation(m, offset, frac_size, precision);
+
+    let (src_tsc, dst_tsc) = match migration {
+        Some(mig) => mig,
+        None => return true,
+    };
+
+    // the guest TSC at the migration anchors the line on the destination
+    let mig_guest_tsc = match guest_tsc(
+        initial_host_tsc,
+        initial_guest_tsc,
+        host_hz,
+        guest_hz,
+        src_tsc,
+        frac_size,
+        int_size,
+    ) {
+        Ok(tsc) => tsc,
+        Err(e) => {
+            eprintln!("could not calculate guest TSC at migration: {}", e);
+            return false;
+        }
+    };
+    let (dst_m, dst_offset) = match migration_params(
+        dst_tsc,
+        mig_guest_tsc,
+        guest_hz,
+        dst_hz,
+        frac_size,
+        int_size,
+    ) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("could not calculate destination line equation: {}", e);
+            return false;
+        }
+    };
+
+    println!();
+    println!("Destination Host:");
+    print_line_equation(dst_m, dst_offset, frac_size, precision);
+
+    println!();
+    println!("Change across migration:");
+    println!(
+        "\tslope:     {:+} / 2^{}",
+        dst_m as i128 - m as i128,
+        frac_size
+    );
+    println!("\tintercept: {:+}", dst_offset as i128 - offset as i128);
+
+    true
+}
+
+#[allow(clippy::too_many_arguments)]
+fn cmd_deadline_map(
+    deadline: u64,
+    initial_host_tsc: u64,
+    initial_guest_tsc: u64,
+    host_hz: u64,
+    guest_hz: u64,
+    src_tsc: u64,
+    dst_tsc: u64,
+    dst_hz: u64,
+    int_size: u32,
+    frac_size: u32,
+    both_signs: bool,
+) -> bool {
+    println!("mapping guest TSC deadline across migration for parameters:");
+    println!("\tSource Host:");
+    println!(
+        "\t\tinitial TSC: {initial_host_tsc} ({:#x})",
+        initial_host_tsc
+    );
+    println!("\t\tmigration TSC: {src_tsc} ({:#x})", src_tsc);
+    println!("\t\tfrequency: {host_hz} Hz");
+    println!("\tDestination Host:");
+    println!("\t\tmigration TSC: {dst_tsc} ({:#x})", dst_tsc);
+    println!("\t\tfrequency: {dst_hz} Hz");
+    println!("\tGuest:");
+    println!(
+        "\t\tinitial TSC: {initial_guest_tsc} ({:#x})",
+        initial_guest_tsc
+    );
+    println!("\t\tdeadline TSC: {deadline} ({:#x})", deadline);
+    println!("\t\tfrequency: {guest_hz} Hz");
+    println!();
+
+    // the guest TSC at the migration anchors the guest on the destination
+    let mig_guest_tsc = match guest_tsc(
+        initial_host_tsc,
+        initial_guest_tsc,
+        host_hz,
+        guest_hz,
+        src_tsc,
+        frac_size,
+        int_size,
+    ) {
+        Ok(tsc) => tsc,
+        Err(e) => {
+            eprintln!("could not calculate guest TSC at migration: {}", e);
+            return false;
+        }
+    };
+    println!(
+        "Guest TSC at migration: {} ({:#x}){}",
+        mig_guest_tsc,
+        mig_guest_tsc,
+        signs(mig_guest_tsc, both_signs)
+    );
+
+    if deadline < mig_guest_tsc {
+        println!("Deadline passed before the migration");
+    }
+
+    let dst_deadline = match host_tsc_from_guest(
+        dst_tsc,
+        mig_guest_tsc,
+        dst_hz,
+        guest_hz,
+        deadline,
+        frac_size,
+        int_size,
+    ) {
+        Ok(tsc) => tsc,
+        Err(e) => {
+            eprintln!("could not calculate destination host TSC: {}", e);
+            return false;
+        }
+    };
+
+    // signed nanoseconds from the migration to the deadline on a host
+    let from_migration = |host_tsc: u64, mig_tsc: u64, hz: u64| {
+        (host_tsc as i128 - mig_tsc as i128) * NS_PER_SEC as i128 / hz as i128
+    };
+
+    let dst_ns = from_migration(dst_deadline, dst_tsc, dst_hz);
+    println!(
+        "Destination host TSC at deadline: {} ({:#x}){}",
+        dst_deadline,
+        dst_deadline,
+        signs(dst_deadline, both_signs)
+    );
+    println!("Time from migration to deadline: {} ns", dst_ns);
+
+    // where the deadline would have fallen without the migration
+    if let Ok(src_deadline) = host_tsc_from_guest(
+        initial_host_tsc,
+        initial_guest_tsc,
+        host_hz,
+        guest_hz,
+        deadline,
+        frac_size,
+        int_size,
+    ) {
+        let src_ns = from_migration(src_deadline, src_tsc, host_hz);
+        println!();
+        println!(
+            "Source host TSC at deadline: {} ({:#x}){}",
+            src_deadline,
+            src_deadline,
+            signs(src_deadline, both_signs)
+        );
+        println!("Time from migration to deadline (source): {} ns", src_ns);
+        println!("Difference: {} ns", dst_ns - src_ns);
+    }
+
+    true
+}
+
+#[allow(clippy::too_many_arguments)]
+fn cmd_resume_at(
+    initial_host_tsc: u64,
+    initial_guest_tsc: u64,
+    pause_host_tsc: u64,
+    resume_host_tsc: u64,
+    mode: ResumeMode,
+    paused_sec: Option<u64>,
+    host_hz: u64,
+    guest_hz: u64,
+    int_size: u32,
+    frac_size: u32,
+    both_signs: bool,
+) -> bool {
+    println!("calculating resume TSC offset for parameters:");
+    println!("\tHost:");
+    println!(
+        "\t\tinitial TSC: {initial_host_tsc} ({:#x})",
+        initial_host_tsc
+    );
+    println!("\t\tpause TSC: {pause_host_tsc} ({:#x})", pause_host_tsc);
+    println!("\t\tresume TSC: {resume_host_tsc} ({:#x})", resume_host_tsc);
+    println!("\t\tfrequency: {host_hz} Hz");
+    println!("\tGuest:");
+    println!(
+        "\t\tinitial TSC: {initial_guest_tsc} ({:#x})",
+        initial_guest_tsc
+    );
+    println!("\t\tfrequency: {guest_hz} Hz");
+    println!("\tMode: {:?}", mode);
+    if let Some(secs) = paused_sec {
+        println!("\tPaused for: {secs} seconds");
+    }
+    println!();
+
+    match resume_at_offset(
+        initial_host_tsc,
+        initial_guest_tsc,
+        pause_host_tsc,
+        resume_host_tsc,
+        mode,
+        paused_sec,
+        host_hz,
+        guest_hz,
+        frac_size,
+        int_size,
+    ) {
+        Ok((offset, tsc)) => {
+            println!(
+                "TSC offset: {} ({:#018x}){}",
+                offset,
+                offset,
+                signs(offset_to_raw(offset), both_signs)
+            );
+            println!(
+                "Guest TSC at resume: {} ({:#x}){}",
+                tsc,
+                tsc,
+                signs(tsc, both_signs)
+            );
+            println!(
+                "Guest uptime at resume: {} s",
+                fmt_seconds(tsc, guest_hz)
+            );
+            true
+        }
+        Err(e) => {
+            eprintln!("could not calculate resume TSC offset: {}", e);
+            false
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn cmd_resume_registers(
+    initial_host_tsc: u64,
+    initial_guest_tsc: u64,
+    pause_host_tsc: u64,
+    resume_host_tsc: u64,
+    host_hz: u64,
+    guest_hz: u64,
+    int_size: u32,
+    frac_size: u32,
+    both_signs: bool,
+) -> bool {
+    println!("calculating resume TSC offset for parameters:");
+    println!("\tHost:");
+    println!(
+        "\t\tinitial TSC: {initial_host_tsc} ({:#x})",
+        initial_host_tsc
+    );
+    println!("\t\tpause TSC: {pause_host_tsc} ({:#x})", pause_host_tsc);
+    println!("\t\tresume TSC: {resume_host_tsc} ({:#x})", resume_host_tsc);
+    println!("\t\tfrequency: {host_hz} Hz");
+    println!("\tGuest:");
+    println!(
+        "\t\tinitial TSC: {initial_guest_tsc} ({:#x})",
+        initial_guest_tsc
+    );
+    println!("\t\tfrequency: {guest_hz} Hz");
+    println!();
+
+    let old_offset = match tsc_offset(
+        initial_host_tsc,
+        initial_guest_tsc,
+        guest_hz,
+        host_hz,
+        frac_size,
+        int_size,
+    ) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("could not calculate TSC offset: {}", e);
+            return false;
+        }
+    };
+    let new_offset = match resume_offset(
+        initial_host_tsc,
+        initial_guest_tsc,
+        host_hz,
+        guest_hz,
+        pause_host_tsc,
+        resume_host_tsc,
+        frac_size,
+        int_size,
+    ) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("could not calculate resume TSC offset: {}", e);
+            return false;
+        }
+    };
+
+    println!(
+        "TSC offset (paused):  {} ({:#018x}){}",
+        old_offset,
+        old_offset,
+        signs(offset_to_raw(old_offset), both_signs)
+    );
+    println!(
+        "TSC offset (resumed): {} ({:#018x}){}",
+        new_offset,
+        new_offset,
+        signs(offset_to_raw(new_offset), both_signs)
+    );
+    println!(
+        "Offset delta:         {}",
+        new_offset as i128 - old_offset as i128
+    );
+
+    true
+}
+
+// How far apart the rust and asm guest TSCs may be and still agree: a tick,
+// for a difference in where a partial tick is truncated
+const COMPARE_TOLERANCE_TICKS: u64 = 1;
+
+// The guest TSC as calculated by the rust and then the asm implementation. The
+// asm can trap on inputs the rust rejects, so it's only run if the rust
+// succeeds.
+fn compare_guest_tsc(
+    initial_host_tsc: u64,
+    initial_guest_tsc: u64,
+    host_tsc: u64,
+    host_hz: u64,
+    guest_hz: u64,
+    int_size: u32,
+    frac_size: u32,
+) -> anyhow::Result<(u64, u64)> {
+    let rs = guest_tsc(
+        initial_host_tsc,
+        initial_guest_tsc,
+        host_hz,
+        guest_hz,
+        host_tsc,
+        frac_size,
+        int_size,
+    )
+    .map_err(|e| anyhow!("rust: {}", e))?;
+    if would_freq_multiplier_overflow(guest_hz, host_hz, frac_size, int_size) {
+        return Err(anyhow!("asm: not run, as the multiplier would overflow"));
+    }
+    let asm = asm_math::calc_guest_tsc(
+        initial_host_tsc,
+        initial_guest_tsc,
+        host_hz,
+        guest_hz,
+        host_tsc,
+        frac_size,
+    )
+    .map_err(|e| anyhow!("asm: {}", e))?;
+
+    Ok((rs, asm))
+}
+
+fn cmd_compare(
+    initial_host_tsc: u64,
+    initial_guest_tsc: u64,
+    host_tsc: u64,
+    host_hz: u64,
+    guest_hz: u64,
+    int_size: u32,
+    frac_size: u32,
+) -> bool {
+    println!("comparing guest TSC implementations for parameters:");
+    println!("\tHost:");
+    println!(
+        "\t\tinitial TSC: {initial_host_tsc} ({:#x})",
+        initial_host_tsc
+    );
+    println!("\t\tcurrent TSC: {host_tsc} ({:#x})", host_tsc);
+    println!("\t\tfrequency: {host_hz} Hz");
+    println!("\tGuest:");
+    println!(
+        "\t\tinitial TSC: {initial_guest_tsc} ({:#x})",
+        initial_guest_tsc
+    );
+    println!("\t\tfrequency: {guest_hz} Hz");
+    println!("\tFormat: {int_size}.{frac_size}");
+    println!();
+
+    let (rs, asm) = match compare_guest_tsc(
+        initial_host_tsc,
+        initial_guest_tsc,
+        host_tsc,
+        host_hz,
+        guest_hz,
+        int_size,
+        frac_size,
+    ) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("could not calculate guest TSC: {}", e);
+            return false;
+        }
+    };
+
+    let diff = rs.abs_diff(asm);
+    let within = diff <= COMPARE_TOLERANCE_TICKS;
+    println!("{:<12} {} ({:#x})", "rust:", rs, rs);
+    println!("{:<12} {} ({:#x})", "asm:", asm, asm);
+    println!("{:<12} {} ticks", "difference:", diff);
+    println!(
+        "{:<12} {} ({} tick tolerance)",
+        "within:",
+        if within { "yes" } else { "no" },
+        COMPARE_TOLERANCE_TICKS
+    );
+
+    within
+}
+
+fn cmd_guest_tsc_stats(
+    initial_host_tsc: u64,
+    initial_guest_tsc: u64,
+    host_hz: u64,
+    guest_hz: u64,
+    int_size: u32,
+    frac_size: u32,
+    both_signs: bool,
+) -> bool {
+    // Stream host TSCs from stdin, skipping (and reporting) malformed lines
+    let host_tscs =
+        std::io::stdin().lines().enumerate().filter_map(|(i, l)| {
+            let line = match l {
+                Ok(l) => l,
+                Err(e) => {
+                    eprintln!("line {}: could not read: {}", i + 1, e);
+                    return None;
+                }
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                return None;
+            }
+            match maybe_hex::<u64>(line) {
+                Ok(tsc) => Some(tsc),
+                Err(e) => {
+                    eprintln!(
+                        "line {}: invalid host TSC {:?}: {}",
+                        i + 1,
+                        line,
+                        e
+                    );
+                    None
+                }
+            }
+        });
+
+    let stats = match guest_tsc_batch_stats(
+        initial_host_tsc,
+        initial_guest_tsc,
+        host_hz,
+        guest_hz,
+        host_tscs,
+        frac_size,
+        int_size,
+    ) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("could not calculate guest TSC stats: {}", e);
+            return false;
+        }
+    };
+
+    println!("count:  {}", stats.count);
+    println!("errors: {}", stats.errors);
+    for (name, v) in
+        [("min", stats.min), ("max", stats.max), ("mean", stats.mean)]
+    {
+        match v {
+            Some(v) => println!(
+                "{:<7} {} ({:#x}){}",
+                format!("{name}:"),
+                v,
+                v,
+                signs(v, both_signs)
+            ),
+            None => println!("{:<7} -", format!("{name}:")),
+        }
+    }
+
+    true
+}
+
+fn cmd_guest_tsc_at_trace(
+    initial_host_tsc: u64,
+    initial_guest_tsc: u64,
+    host_hz: u64,
+    guest_hz: u64,
+    int_size: u32,
+    frac_size: u32,
+) -> bool {
+    let (multiplier, tsc_offset) = match migration_params(
+        initial_host_tsc,
+        initial_guest_tsc,
+        guest_hz,
+        host_hz,
+        frac_size,
+        int_size,
+    ) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("could not calculate TSC offset: {}", e);
+            return false;
+        }
+    };
+
+    // One record per nonblank input line, in order: the elapsed time and
+    // guest TSC, or an error with the line number
+    println!("{:>20} {:>20}", "ELAPSED_NS", "GUEST_TSC");
+    for (i, l) in std::io::stdin().lines().enumerate() {
+        let line = match l {
+            Ok(l) => l,
+            Err(e) => {
+                println!("line {}: could not read: {}", i + 1, e);
+                continue;
+            }
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let elapsed_ns = match line.parse::<u64>() {
+            Ok(ns) => ns,
+            Err(e) => {
+                println!(
+                    "line {}: invalid elapsed time {:?}: {}",
+                    i + 1,
+                    line,
+                    e
+                );
+                continue;
+            }
+        };
+
+        match guest_tsc_at_elapsed_ns(
+            initial_host_tsc,
+            host_hz,
+            multiplier,
+            tsc_offset,
+            elapsed_ns,
+            frac_size,
+        ) {
+            Ok(tsc) => println!("{:>20} {:>20}", elapsed_ns, tsc),
+            Err(e) => {
+                println!("line {}: could not calculate guest tsc: {}", i + 1, e)
+            }
+        }
+    }
+
+    true
+}
+
+fn cmd_hrtime(
+    tsc: u64,
+    freq_hz: u64,
+    rounding: Rounding,
+    both_signs: bool,
+) -> bool {
+    println!("calculating hrtime for parameters:");
+    println!("\ttsc: {tsc} ({:#x})", tsc);
+    println!("\tfrequency: {freq_hz} Hz ({:#x} Hz)", freq_hz);
+    println!("\trounding: {:?}", rounding);
+    println!();
+
+    let res = hrtime(tsc, freq_hz, rounding);
+
+    match res {
+        Ok(hrtime) => {
+            println!(
+                "hrtime: {hrtime} ({:#x}){}",
+                hrtime,
+                signs(hrtime, both_signs)
+            );
+            true
+        }
+        Err(e) => {
+            eprintln!("could not calculate hrtime: {}", e);
+            false
+        }
+    }
+}
+
+// Format ns since the Unix epoch as an RFC 3339 UTC timestamp
+fn rfc3339(ns: i64) -> String {
+    chrono::DateTime::from_timestamp_nanos(ns)
+        .to_rfc3339_opts(chrono::SecondsFormat::Nanos, true)
+}
+
+fn cmd_wallclock(tsc: u64, freq_hz: u64, boot_epoch_ns: i64) -> bool {
+    println!("calculating wall-clock time for parameters:");
+    println!("\ttsc: {tsc} ({:#x})", tsc);
+    println!("\tfrequency: {freq_hz} Hz");
+    println!(
+        "\tboot epoch: {boot_epoch_ns} ns ({})",
+        rfc3339(boot_epoch_ns)
+    );
+    println!();
+
+    match wallclock_ns(tsc, freq_hz, boot_epoch_ns) {
+        Ok(ns) => {
+            println!("Wall-clock time: {} ns ({})", ns, rfc3339(ns));
+            true
+        }
+        Err(e) => {
+            eprintln!("could not calculate wall-clock time: {}", e);
+            false
+        }
+    }
+}
+
+fn cmd_tsc(
+    hrtime: u64,
+    freq_hz: u64,
+    rounding: Rounding,
+    both_signs: bool,
+) -> bool {
+    println!("calculating TSC for parameters:");
+    println!("\thrtime: {hrtime} ({:#x})", hrtime);
+    println!("\tfrequency: {freq_hz} Hz ({:#x} Hz)", freq_hz);
+    println!("\trounding: {:?}", rounding);
+    println!();
+
+    let res = tsc(hrtime, freq_hz, rounding);
+
+    match res {
+        Ok(tsc) => {
+            println!("TSC: {tsc} ({:#x}){}", tsc, signs(tsc, both_signs));
+            true
+        }
+        Err(e) => {
+            eprintln!("could not calculate TSC: {}", e);
+            false
+        }
+    }
+}
+
+fn cmd_observed_freq(
+    h1: u64,
+    g1: u64,
+    h2: u64,
+    g2: u64,
+    host_hz: u64,
+    guest_hz: u64,
+    precision: Option<usize>,
+) -> bool {
+    println!("calculating observed guest frequency for parameters:");
+    println!("\tReading 1:");
+    println!("\t\thost TSC: {h1} ({:#x})", h1);
+    println!("\t\tguest TSC: {g1} ({:#x})", g1);
+    println!("\tReading 2:");
+    println!("\t\thost TSC: {h2} ({:#x})", h2);
+    println!("\t\tguest TSC: {g2} ({:#x})", g2);
+    println!("\tHost frequency: {host_hz} Hz");
+    println!("\tGuest frequency: {guest_hz} Hz");
+    println!();
+
+    if guest_hz == 0 {
+        eprintln!("cannot compare against a guest frequency of 0");
+        return false;
+    }
+
+    let freq_hz = match observed_freq(h1, g1, h2, g2, host_hz) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("could not calculate observed guest frequency: {}", e);
+            return false;
+        }
+    };
+
+    let ppm =
+        (freq_hz as f64 - guest_hz as f64) / guest_hz as f64 * 1_000_000.0;
+    let pace = match freq_hz.cmp(&guest_hz) {
+        std::cmp::Ordering::Greater => "fast",
+        std::cmp::Ordering::Less => "slow",
+        std::cmp::Ordering::Equal => "on time",
+    };
+
+    println!("Observed frequency: {freq_hz} Hz");
+    println!(
+        "Deviation: {:.p$} ppm ({})",
+        ppm,
+        pace,
+        p = digits(precision, 3)
+    );
 
-    Ok(res)
+    true
 }
 
-pub fn cmd_offset(
-    initial_host_tsc: u64,
-    initial_guest_tsc: u64,
+fn cmd_host_for_mult(
+    multiplier: u64,
     guest_hz: u64,
-    host_hz: u64,
-    math_impl: MathImpl,
+    int_size: u32,
     frac_size: u32,
+) -> bool {
+    println!("calculating host frequency for parameters:");
+    println!("\tMultiplier: {multiplier} ({:#x})", multiplier);
+    println!("\tGuest:");
+    println!("\t\tfrequency: {guest_hz} Hz");
+    println!();
+    println!("\tMultiplier format:\t{}.{}", int_size, frac_size);
+    println!();
+
+    let host_hz = match host_hz_for_multiplier(guest_hz, multiplier, frac_size)
+    {
+        Ok(h) => h,
+        Err(e) => {
+            eprintln!("could not calculate host frequency: {}", e);
+            return false;
+        }
+    };
+
+    let exact =
+        host_hz as u128 * multiplier as u128 == (guest_hz as u128) << frac_size;
+    if exact {
+        println!("Host frequency: {host_hz} Hz (exact)");
+    } else {
+        println!("Host frequency: {host_hz} Hz (nearest)");
+    }
+
+    // a close host frequency may still truncate to a larger multiplier
+    match freq_multiplier(guest_hz, host_hz, frac_size, int_size) {
+        Ok(m) => {
+            println!("Multiplier on this host: {} ({:#x})", m, m);
+            true
+        }
+        Err(e) => {
+            eprintln!("could not calculate frequency multiplier: {}", e);
+            false
+        }
+    }
+}
+
+fn cmd_aliasing(
+    guest_hz: u64,
+    host_hz: u64,
     int_size: u32,
-) {
-    println!("calculating TSC offset for parameters:");
+    frac_size: u32,
+    precision: Option<usize>,
+) -> bool {
+    println!("calculating multiplier aliasing for parameters:");
     println!("\tHost:");
-    println!(
-        "\t\tinitial TSC: {initial_host_tsc} ({:#x})",
-        initial_host_tsc
-    );
     println!("\t\tfrequency: {host_hz} Hz");
     println!("\tGuest:");
+    println!("\t\tfrequency: {guest_hz} Hz");
+    println!();
+    println!("\tMultiplier format:\t{}.{}", int_size, frac_size);
+    println!();
+
+    let (low, high) = match alias_range(guest_hz, host_hz, frac_size, int_size)
+    {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("could not calculate aliasing: {}", e);
+            return false;
+        }
+    };
+
+    // guest frequency per multiplier LSB
+    let resolution = host_hz as f64 / 2f64.powi(frac_size as i32);
+
     println!(
-        "\t\tinitial TSC: {initial_guest_tsc} ({:#x})",
-        initial_guest_tsc
+        "Frequency resolution: {:.p$} Hz",
+        resolution,
+        p = digits(precision, 6)
+    );
+    println!(
+        "Guest frequencies with the same multiplier: {} - {} Hz ({} total)",
+        low,
+        high,
+        high - low + 1
     );
+    if low == high {
+        println!("{guest_hz} Hz is well-resolved");
+    } else {
+        println!(
+            "{guest_hz} Hz is not well-resolved: it aliases with its neighbors"
+        );
+    }
+
+    true
+}
+
+fn cmd_daily_error(
+    guest_hz: u64,
+    host_hz: u64,
+    int_size: u32,
+    frac_size: u32,
+    precision: Option<usize>,
+) -> bool {
+    println!("calculating daily guest clock error for parameters:");
+    println!("\tHost:");
+    println!("\t\tfrequency: {host_hz} Hz");
+    println!("\tGuest:");
     println!("\t\tfrequency: {guest_hz} Hz");
-    println!("\tImplementation: {:?}", math_impl);
-    println!("");
+    println!();
+    println!("\tMultiplier format:\t{}.{}", int_size, frac_size);
+    println!();
 
-    let rs_res = tsc_offset(
-        initial_host_tsc,
-        initial_guest_tsc,
+    let err_ns = match uptime_error_ns(
+        SECS_PER_DAY,
         guest_hz,
         host_hz,
         frac_size,
         int_size,
+    ) {
+        Ok(e) => e,
+        Err(e) => {
+            eprintln!("could not calculate daily guest clock error: {}", e);
+            return false;
+        }
+    };
+
+    let pace = match err_ns.cmp(&0) {
+        std::cmp::Ordering::Greater => "fast",
+        std::cmp::Ordering::Less => "slow",
+        std::cmp::Ordering::Equal => {
+            println!("After 24 hours the guest clock will be exact");
+            return true;
+        }
+    };
+
+    println!(
+        "After 24 hours the guest clock will be {:.p$} ms {} ({} ns)",
+        err_ns.unsigned_abs() as f64 / 1_000_000.0,
+        pace,
+        err_ns,
+        p = digits(precision, 3)
     );
-    let asm_res = asm_math::calc_tsc_offset(
-        initial_host_tsc,
-        initial_guest_tsc,
-        guest_hz,
-        host_hz,
-        frac_size,
+
+    true
+}
+
+fn cmd_max_drift(
+    duration: u64,
+    guest_hz: u64,
+    host_hz: u64,
+    int_size: u32,
+    frac_size: u32,
+) -> bool {
+    println!("calculating worst-case guest clock drift for parameters:");
+    println!("\tHost:");
+    println!("\t\tfrequency: {host_hz} Hz");
+    println!("\tGuest:");
+    println!("\t\tfrequency: {guest_hz} Hz");
+    println!();
+    println!("\tMultiplier format:\t{}.{}", int_size, frac_size);
+    println!("\tDuration:\t\t{} seconds", duration);
+    println!();
+
+    let (drift_ns, t) =
+        match max_drift_ns(duration, guest_hz, host_hz, frac_size, int_size) {
+            Ok(d) => d,
+            Err(e) => {
+                eprintln!("could not calculate max drift: {}", e);
+                return false;
+            }
+        };
+
+    let pace = match drift_ns.cmp(&0) {
+        std::cmp::Ordering::Greater => "fast",
+        std::cmp::Ordering::Less => "slow",
+        std::cmp::Ordering::Equal => {
+            println!("The guest clock is exact at every second");
+            return true;
+        }
+    };
+
+    println!(
+        "Max drift: {} ns {} ({:?}), at {} seconds",
+        drift_ns.unsigned_abs(),
+        pace,
+        std::time::Duration::from_nanos(drift_ns.unsigned_abs()),
+        t
     );
 
-    match math_impl {
-        MathImpl::Asm => {
-            println!("TSC offset: {} ({:#x})", asm_res, asm_res);
+    true
+}
+
+fn cmd_wrap32(guest_hz: u64, start_tsc: u64, both_signs: bool) -> bool {
+    println!("calculating 32-bit guest TSC wraps for parameters:");
+    println!("\tGuest:");
+    println!("\t\tfrequency: {guest_hz} Hz");
+    println!("\t\tstart TSC: {} ({:#x})", start_tsc, start_tsc);
+    println!();
+
+    let period = 1u64 << 32;
+    let period_time = match ticks_to_duration(period, guest_hz) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("could not calculate wrap period: {}", e);
+            return false;
         }
-        MathImpl::Rust => match rs_res {
-            Ok(offset) => {
-                println!("TSC offset: {} ({:#x})", offset, offset);
+    };
+    println!("Wrap period: {} ticks ({:?})", period, period_time);
+
+    let next = match next_low_bits_wrap(start_tsc, 32) {
+        Ok(n) => n,
+        Err(e) => {
+            eprintln!("could not calculate next wrap: {}", e);
+            return false;
+        }
+    };
+    let next_time = match ticks_to_duration(next - start_tsc, guest_hz) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("could not calculate next wrap: {}", e);
+            return false;
+        }
+    };
+    println!(
+        "Next wrap: {} ({:#x}){}, {} ticks ({:?}) after start",
+        next,
+        next,
+        signs(next, both_signs),
+        next - start_tsc,
+        next_time
+    );
+
+    true
+}
+
+fn cmd_calibrate(
+    t1: u64,
+    tsc1: u64,
+    t2: u64,
+    tsc2: u64,
+    nominal_hz: Option<u64>,
+    precision: Option<usize>,
+) -> bool {
+    println!("calibrating TSC frequency for parameters:");
+    println!("\tSample 1:");
+    println!("\t\ttime: {t1} ns");
+    println!("\t\tTSC: {tsc1} ({:#x})", tsc1);
+    println!("\tSample 2:");
+    println!("\t\ttime: {t2} ns");
+    println!("\t\tTSC: {tsc2} ({:#x})", tsc2);
+    if let Some(n) = nominal_hz {
+        println!("\tNominal frequency: {n} Hz");
+    }
+    println!();
+
+    let freq_hz = match calibrate_freq(t1, tsc1, t2, tsc2) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("could not calibrate TSC frequency: {}", e);
+            return false;
+        }
+    };
+
+    println!("Frequency: {freq_hz} Hz");
+
+    if let Some(n) = nominal_hz {
+        if n == 0 {
+            eprintln!("cannot compute deviation from a nominal frequency of 0");
+            return false;
+        }
+        let ppm = (freq_hz as f64 - n as f64) / n as f64 * 1_000_000.0;
+        println!("Deviation: {:.p$} ppm", ppm, p = digits(precision, 3));
+    }
+
+    true
+}
+
+// Run the embedded test vectors, returning whether all of them passed
+fn cmd_selftest() -> bool {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    println!("running frequency multiplier vectors:");
+    for t in vectors::FREQ_RATIO_TESTS_VALID.iter() {
+        let rs_res = freq_multiplier(t.g, t.h, t.f, 64 - t.f);
+        let asm_res = asm_math::freq_multiplier(t.g, t.h, t.f);
+
+        for (name, res) in [("rust", rs_res), ("asm", asm_res)] {
+            match res {
+                Ok(v) if v == t.v => passed += 1,
+                Ok(v) => {
+                    failed += 1;
+                    println!(
+                        "\tFAIL ({name}): guest_freq={}, host_freq={}, frac_size={}: expected {:#x}, got {:#x}: {}",
+                        t.g, t.h, t.f, t.v, v, divergence(v as i128, t.v as i128)
+                    );
+                }
+                Err(e) => {
+                    failed += 1;
+                    println!(
+                        "\tFAIL ({name}): guest_freq={}, host_freq={}, frac_size={}: {}",
+                        t.g, t.h, t.f, e
+                    );
+                }
             }
-            Err(e) => {
-                eprintln!("could not calculate TSC offset: {}", e);
+        }
+    }
+
+    println!("running scale TSC vectors:");
+    for t in vectors::SCALE_TSC_TESTS_VALID.iter() {
+        let rs_res = scale_tsc(t.t, t.m, t.f);
+        let asm_res = unsafe { asm_math::scale_tsc(t.t, t.m, t.f) };
+
+        for (name, res) in [("rust", rs_res), ("asm", Ok(asm_res))] {
+            match res {
+                Ok(v) if v == t.v => passed += 1,
+                Ok(v) => {
+                    failed += 1;
+                    println!(
+                        "\tFAIL ({name}): tsc={}, mult={:#x}, frac_size={}: expected {}, got {}: {}",
+                        t.t, t.m, t.f, t.v, v, divergence(v as i128, t.v as i128)
+                    );
+                }
+                Err(e) => {
+                    failed += 1;
+                    println!(
+                        "\tFAIL ({name}): tsc={}, mult={:#x}, frac_size={}: {}",
+                        t.t, t.m, t.f, e
+                    );
+                }
+            }
+        }
+    }
+
+    println!();
+    println!("passed: {passed}");
+    println!("failed: {failed}");
+
+    failed == 0
+}
+
+// Run the checks a command's computation would, without computing or printing
+// its output. Commands that work over a whole run only have their inputs
+// checked, not the run. This stops at the first problem found. Every run does
+// this first, so `--dry-run` accepts and rejects the same inputs as a real run.
+fn validate(cmd: &Command) -> anyhow::Result<()> {
+    match cmd {
+        Command::Calc { cmd, .. } => match cmd {
+            CalcCommand::Hrtime {
+                tsc,
+                freq_hz,
+                rounding,
+            } => {
+                check_hz("frequency", *freq_hz)?;
+                hrtime(*tsc, *freq_hz, (*rounding).into())?;
+            }
+            CalcCommand::Tsc {
+                hrtime,
+                freq_hz,
+                rounding,
+            } => {
+                tsc(*hrtime, *freq_hz, (*rounding).into())?;
+            }
+            CalcCommand::Wallclock {
+                tsc,
+                freq_hz,
+                boot_epoch_ns,
+            } => {
+                check_hz("frequency", *freq_hz)?;
+                wallclock_ns(*tsc, *freq_hz, *boot_epoch_ns)?;
+            }
+            CalcCommand::GuestTsc {
+                initial_host_tsc,
+                initial_guest_tsc,
+                initial_guest_uptime_sec,
+                host_tsc,
+                host_hz,
+                guest_hz,
+                int_size,
+                frac_size,
+                ..
+            } => {
+                check_hz("host frequency", *host_hz)?;
+                check_hz("guest frequency", *guest_hz)?;
+                check_format(*int_size, *frac_size)?;
+                let initial_guest_tsc = resolve_initial_guest_tsc(
+                    *initial_guest_tsc,
+                    *initial_guest_uptime_sec,
+                    *guest_hz,
+                )?;
+                guest_tsc(
+                    *initial_host_tsc,
+                    initial_guest_tsc,
+                    *host_hz,
+                    *guest_hz,
+                    *host_tsc,
+                    *frac_size,
+                    *int_size,
+                )?;
+            }
+            CalcCommand::Compare {
+                initial_host_tsc,
+                initial_guest_tsc,
+                initial_guest_uptime_sec,
+                host_tsc,
+                host_hz,
+                guest_hz,
+                int_size,
+                frac_size,
+            } => {
+                check_hz("host frequency", *host_hz)?;
+                check_hz("guest frequency", *guest_hz)?;
+                check_format(*int_size, *frac_size)?;
+                let initial_guest_tsc = resolve_initial_guest_tsc(
+                    *initial_guest_tsc,
+                    *initial_guest_uptime_sec,
+                    *guest_hz,
+                )?;
+                guest_tsc(
+                    *initial_host_tsc,
+                    initial_guest_tsc,
+                    *host_hz,
+                    *guest_hz,
+                    *host_tsc,
+                    *frac_size,
+                    *int_size,
+                )?;
+            }
+            CalcCommand::Offset {
+                initial_host_tsc,
+                initial_guest_tsc,
+                initial_guest_uptime_sec,
+                guest_hz,
+                host_hz,
+                int_size,
+                frac_size,
+                ..
+            } => {
+                check_hz("host frequency", *host_hz)?;
+                check_hz("guest frequency", *guest_hz)?;
+                check_format(*int_size, *frac_size)?;
+                let initial_guest_tsc = resolve_initial_guest_tsc(
+                    *initial_guest_tsc,
+                    *initial_guest_uptime_sec,
+                    *guest_hz,
+                )?;
+                tsc_offset(
+                    *initial_host_tsc,
+                    initial_guest_tsc,
+                    *guest_hz,
+                    *host_hz,
+                    *frac_size,
+                    *int_size,
+                )?;
+            }
+            CalcCommand::ColdMigrate {
+                guest_tsc,
+                dest_host_tsc,
+                guest_hz,
+                host_hz,
+                int_size,
+                frac_size,
+            } => {
+                check_hz("host frequency", *host_hz)?;
+                check_hz("guest frequency", *guest_hz)?;
+                check_format(*int_size, *frac_size)?;
+                cold_migration_offset(
+                    *dest_host_tsc,
+                    *guest_tsc,
+                    *guest_hz,
+                    *host_hz,
+                    *frac_size,
+                    *int_size,
+                )?;
+            }
+            CalcCommand::OffsetBits {
+                initial_host_tsc,
+                initial_guest_tsc,
+                guest_hz,
+                host_hz,
+                int_size,
+                frac_size,
+            }
+            | CalcCommand::GuestTscStats {
+                initial_host_tsc,
+                initial_guest_tsc,
+                host_hz,
+                guest_hz,
+                int_size,
+                frac_size,
+            }
+            | CalcCommand::GuestTscAtTrace {
+                initial_host_tsc,
+                initial_guest_tsc,
+                host_hz,
+                guest_hz,
+                int_size,
+                frac_size,
+            } => {
+                check_hz("host frequency", *host_hz)?;
+                check_hz("guest frequency", *guest_hz)?;
+                check_format(*int_size, *frac_size)?;
+                tsc_offset(
+                    *initial_host_tsc,
+                    *initial_guest_tsc,
+                    *guest_hz,
+                    *host_hz,
+                    *frac_size,
+                    *int_size,
+                )?;
+            }
+            CalcCommand::Freq {
+                host_hz,
+                guest_hz,
+                int_size,
+                frac_size,
+                ..
+            }
+            | CalcCommand::BitContribution {
+                host_hz,
+                guest_hz,
+                int_size,
+                frac_size,
+            } => {
+                check_hz("host frequency", *host_hz)?;
+                check_hz("guest frequency", *guest_hz)?;
+                check_format(*int_size, *frac_size)?;
+                freq_multiplier(*guest_hz, *host_hz, *frac_size, *int_size)?;
+            }
+            CalcCommand::Ratio {
+                int_size,
+                frac_size,
+            } => {
+                check_format(*int_size, *frac_size)?;
+            }
+            CalcCommand::OffsetError {
+                initial_host_tsc,
+                guest_hz,
+                host_hz,
+                int_size,
+                frac_size,
+            } => {
+                check_hz("host frequency", *host_hz)?;
+                check_hz("guest frequency", *guest_hz)?;
+                check_format(*int_size, *frac_size)?;
+                offset_error(
+                    *initial_host_tsc,
+                    *guest_hz,
+                    *host_hz,
+                    *frac_size,
+                    *int_size,
+                )?;
             }
-        },
-        MathImpl::All => {
-            println!("TSC offset (asm):  {} ({:#x})", asm_res, asm_res);
-            match rs_res {
-                Ok(offset) => {
-                    println!("TSC offset (rust): {} ({:#x})", offset, offset);
-                }
-                Err(e) => {
-                    eprintln!("could not calculate TSC offset (rust): {}", e);
+            CalcCommand::CommonExactFreq {
+                host_hz,
+                guest_hz,
+                int_size,
+                frac_size,
+                ..
+            } => {
+                check_hz("guest frequency", *guest_hz)?;
+                for h in host_hz.iter() {
+                    check_hz("host frequency", *h)?;
                 }
+                check_format(*int_size, *frac_size)?;
             }
-        }
-    }
-}
-
-fn cmd_freq_multiplier(
-    guest_hz: u64,
-    host_hz: u64,
-    math_impl: MathImpl,
-    int_size: u32,
-    frac_size: u32,
-) {
-    println!("calculating frequency multiplier for parameters:");
-    println!("\tHost:");
-    println!("\t\tfrequency: {host_hz} Hz");
-    println!("\tGuest:");
-    println!("\t\tfrequency: {guest_hz} Hz");
-    println!("");
-    println!("\tMultiplier format:\t{}.{}", int_size, frac_size);
-    println!("\tImplementation:\t{:?}", math_impl);
-    println!("");
-
-    let rs_res = freq_multiplier(guest_hz, host_hz, frac_size, int_size);
-    let asm_res =
-        unsafe { asm_math::calc_freq_multiplier(guest_hz, host_hz, frac_size) };
-
-    match math_impl {
-        MathImpl::Asm => {
-            println!("Frequency multiplier: {} ({:#x})", asm_res, asm_res);
-        }
-        MathImpl::Rust => match rs_res {
-            Ok(m) => {
-                println!("Frequency multiplier: {} ({:#x})", m, m);
+            CalcCommand::HopError {
+                guest_tsc,
+                guest_hz,
+                src_tsc,
+                src_hz,
+                dst_tsc,
+                dst_hz,
+                int_size,
+                frac_size,
+            } => {
+                check_hz("guest frequency", *guest_hz)?;
+                check_hz("source host frequency", *src_hz)?;
+                check_hz("destination host frequency", *dst_hz)?;
+                check_format(*int_size, *frac_size)?;
+                migration_tick_error(
+                    HostParams {
+                        host_tsc: *src_tsc,
+                        host_hz: *src_hz,
+                    },
+                    HostParams {
+                        host_tsc: *dst_tsc,
+                        host_hz: *dst_hz,
+                    },
+                    *guest_hz,
+                    *guest_tsc,
+                    *frac_size,
+                    *int_size,
+                )?;
             }
-            Err(e) => {
-                eprintln!("could not calculate frequency multiplier: {}", e);
+            CalcCommand::VerifyMigration {
+                src_boot_tsc,
+                src_hz,
+                src_tsc,
+                guest_hz,
+                dst_tsc,
+                dst_hz,
+                int_size,
+                frac_size,
+            } => {
+                check_hz("guest frequency", *guest_hz)?;
+                check_hz("source host frequency", *src_hz)?;
+                check_hz("destination host frequency", *dst_hz)?;
+                check_format(*int_size, *frac_size)?;
+                migration_guest_tscs(
+                    *src_boot_tsc,
+                    *src_hz,
+                    *src_tsc,
+                    *guest_hz,
+                    *dst_tsc,
+                    *dst_hz,
+                    *int_size,
+                    *frac_size,
+                )?;
             }
-        },
-        MathImpl::All => {
-            println!(
-                "Frequency multiplier (asm):  {} ({:#x})",
-                asm_res, asm_res
-            );
-            match rs_res {
-                Ok(m) => {
-                    println!("Frequency multiplier (rust): {} ({:#x})", m, m);
-                }
-                Err(e) => {
-                    eprintln!(
-                        "could not calculate frequency multiplier (rust): {}",
-                        e
-                    );
+            CalcCommand::DeadlineMap {
+                deadline,
+                initial_host_tsc,
+                initial_guest_tsc,
+                host_hz,
+                guest_hz,
+                src_tsc,
+                dst_tsc,
+                dst_hz,
+                int_size,
+                frac_size,
+            } => {
+                check_hz("host frequency", *host_hz)?;
+                check_hz("guest frequency", *guest_hz)?;
+                check_hz("destination host frequency", *dst_hz)?;
+                check_format(*int_size, *frac_size)?;
+                let mig_guest_tsc = guest_tsc(
+                    *initial_host_tsc,
+                    *initial_guest_tsc,
+                    *host_hz,
+                    *guest_hz,
+                    *src_tsc,
+                    *frac_size,
+                    *int_size,
+                )?;
+                host_tsc_from_guest(
+                    *dst_tsc,
+                    mig_guest_tsc,
+                    *dst_hz,
+                    *guest_hz,
+                    *deadline,
+                    *frac_size,
+                    *int_size,
+                )?;
+            }
+            CalcCommand::LineEquation {
+                initial_host_tsc,
+                initial_guest_tsc,
+                host_hz,
+                guest_hz,
+                src_tsc,
+                dst_tsc,
+                dst_hz,
+                int_size,
+                frac_size,
+            } => {
+                check_hz("host frequency", *host_hz)?;
+                check_hz("guest frequency", *guest_hz)?;
+                check_format(*int_size, *frac_size)?;
+                migration_params(
+                    *initial_host_tsc,
+                    *initial_guest_tsc,
+                    *guest_hz,
+                    *host_hz,
+                    *frac_size,
+                    *int_size,
+                )?;
+                if let (Some(src_tsc), Some(dst_tsc)) = (src_tsc, dst_tsc) {
+                    check_hz("destination host frequency", *dst_hz)?;
+                    let mig_guest_tsc = guest_tsc(
+                        *initial_host_tsc,
+                        *initial_guest_tsc,
+                        *host_hz,
+                        *guest_hz,
+                        *src_tsc,
+                        *frac_size,
+                        *int_size,
+                    )?;
+                    migration_params(
+                        *dst_tsc,
+                        mig_guest_tsc,
+                        *guest_hz,
+                        *dst_hz,
+                        *frac_size,
+                        *int_size,
+                    )?;
                 }
             }
-        }
-    }
-}
-
-fn cmd_guest_tsc(
-    initial_host_tsc: u64,
-    initial_guest_tsc: u64,
-    host_tsc: u64,
-    host_hz: u64,
-    guest_hz: u64,
-    math_impl: MathImpl,
-    int_size: u32,
-    frac_size: u32,
-) {
-    println!("calculating guest TSC for parameters:");
-    println!("\tHost:");
-    println!(
-        "\t\tinitial TSC: {initial_host_tsc} ({:#x})",
-        initial_host_tsc
-    );
-    println!("\t\tcurrent TSC: {host_tsc} ({:#x})", host_tsc);
-    println!("\t\tfrequency: {host_hz} Hz");
-    println!("\tGuest:");
-    println!(
-        "\t\tinitial TSC: {initial_guest_tsc} ({:#x})",
-        initial_guest_tsc
-    );
-    println!("\t\tfrequency: {guest_hz} Hz");
-    println!("\tImplementation: {:?}", math_impl);
-    println!("");
-
-    let asm_res = asm_math::calc_guest_tsc(
-        initial_host_tsc,
-        initial_guest_tsc,
-        host_hz,
-        guest_hz,
-        host_tsc,
-        frac_size,
-    );
-    let rs_res = guest_tsc(
-        initial_host_tsc,
-        initial_guest_tsc,
-        host_hz,
-        guest_hz,
-        host_tsc,
-        frac_size,
-        int_size,
-    );
-
-    match math_impl {
-        MathImpl::Asm => {
-            println!("Guest TSC: {} ({:#x})", asm_res, asm_res);
-        }
-        MathImpl::Rust => match rs_res {
-            Ok(tsc) => {
-                println!("Guest TSC: {} ({:#x})", tsc, tsc);
+            CalcCommand::ResumeAt {
+                initial_host_tsc,
+                initial_guest_tsc,
+                pause_host_tsc,
+                resume_host_tsc,
+                mode,
+                paused_sec,
+                host_hz,
+                guest_hz,
+                int_size,
+                frac_size,
+            } => {
+                check_hz("host frequency", *host_hz)?;
+                check_hz("guest frequency", *guest_hz)?;
+                check_format(*int_size, *frac_size)?;
+                resume_at_offset(
+                    *initial_host_tsc,
+                    *initial_guest_tsc,
+                    *pause_host_tsc,
+                    *resume_host_tsc,
+                    (*mode).into(),
+                    *paused_sec,
+                    *host_hz,
+                    *guest_hz,
+                    *frac_size,
+                    *int_size,
+                )?;
             }
-            Err(e) => {
-                eprintln!("could not calculate guest TSC: {}", e);
+            CalcCommand::ResumeRegisters {
+                initial_host_tsc,
+                initial_guest_tsc,
+                pause_host_tsc,
+                resume_host_tsc,
+                host_hz,
+                guest_hz,
+                int_size,
+                frac_size,
+            } => {
+                check_hz("host frequency", *host_hz)?;
+                check_hz("guest frequency", *guest_hz)?;
+                check_format(*int_size, *frac_size)?;
+                resume_offset(
+                    *initial_host_tsc,
+                    *initial_guest_tsc,
+                    *host_hz,
+                    *guest_hz,
+                    *pause_host_tsc,
+                    *resume_host_tsc,
+                    *frac_size,
+                    *int_size,
+                )?;
+            }
+            CalcCommand::ObservedFreq {
+                h1,
+                g1,
+                h2,
+                g2,
+                host_hz,
+                guest_hz,
+            } => {
+                check_hz("guest frequency", *guest_hz)?;
+                observed_freq(*h1, *g1, *h2, *g2, *host_hz)?;
+            }
+            CalcCommand::HostForMult {
+                multiplier,
+                guest_hz,
+                int_size,
+                frac_size,
+            } => {
+                check_hz("guest frequency", *guest_hz)?;
+                check_format(*int_size, *frac_size)?;
+                let host_hz =
+                    host_hz_for_multiplier(*guest_hz, *multiplier, *frac_size)?;
+                check_hz("host frequency", host_hz)?;
+                freq_multiplier(*guest_hz, host_hz, *frac_size, *int_size)?;
+            }
+            CalcCommand::Aliasing {
+                guest_hz,
+                host_hz,
+                int_size,
+                frac_size,
+            } => {
+                check_hz("guest frequency", *guest_hz)?;
+                check_hz("host frequency", *host_hz)?;
+                check_format(*int_size, *frac_size)?;
+                alias_range(*guest_hz, *host_hz, *frac_size, *int_size)?;
+            }
+            CalcCommand::Wrap32 {
+                guest_hz,
+                start_tsc,
+            } => {
+                check_hz("guest frequency", *guest_hz)?;
+                next_low_bits_wrap(*start_tsc, 32)?;
+            }
+            CalcCommand::MaxDrift {
+                duration,
+                guest_hz,
+                host_hz,
+                int_size,
+                frac_size,
+            } => {
+                check_hz("guest frequency", *guest_hz)?;
+                check_hz("host frequency", *host_hz)?;
+                check_format(*int_size, *frac_size)?;
+                freq_multiplier(*guest_hz, *host_hz, *frac_size, *int_size)?;
+                duration.checked_mul(*host_hz).ok_or_else(|| {
+                    anyhow!(
+                        "host TSC overflows at {}s: host_hz={}",
+                        duration,
+                        host_hz
+                    )
+                })?;
+            }
+            CalcCommand::DailyError {
+                guest_hz,
+                host_hz,
+                int_size,
+                frac_size,
+            } => {
+                check_hz("guest frequency", *guest_hz)?;
+                check_hz("host frequency", *host_hz)?;
+                check_format(*int_size, *frac_size)?;
+                freq_multiplier(*guest_hz, *host_hz, *frac_size, *int_size)?;
+            }
+            CalcCommand::Calibrate {
+                t1, tsc1, t2, tsc2, ..
+            } => {
+                calibrate_freq(*t1, *tsc1, *t2, *tsc2)?;
             }
         },
-        MathImpl::All => {
-            println!("Guest TSC (asm):  {} ({:#x})", asm_res, asm_res);
-            match rs_res {
-                Ok(tsc) => {
-                    println!("Guest TSC (rust): {} ({:#x})", tsc, tsc);
+        Command::Selftest => {}
+        Command::Simulate {
+            duration,
+            initial_host_tsc,
+            initial_host_hz,
+            guest_hz,
+            actual_guest_hz,
+            guest_ppm,
+            initial_guest_tsc,
+            initial_guest_uptime_sec,
+            hosts,
+            tsc_hosts,
+            retunes,
+            tsc_events,
+            random_migrations: count,
+            seed,
+            arch,
+            int_size,
+            frac_size,
+            step_ns,
+            metrics_port,
+            real_time,
+            speed,
+            compare_arch,
+            ..
+        } => {
+            check_duration(*duration)?;
+            let guest_hz = &apply_ppm_correction(*guest_hz, *guest_ppm);
+            check_hz("guest frequency", *guest_hz)?;
+            if *step_ns == 0 {
+                return Err(anyhow!("step must be at least 1ns"));
+            }
+            if let Some(actual) = actual_guest_hz {
+                check_hz("actual guest frequency", *actual)?;
+            }
+            check_hz("host frequency", *initial_host_hz)?;
+            if (*real_time || metrics_port.is_some())
+                && !(speed.is_finite() && *speed > 0.0)
+            {
+                return Err(anyhow!(
+                    "speed must be a positive number: {}",
+                    speed
+                ));
+            }
+
+            let (int_size, frac_size) =
+                arch_format((*arch).into(), *int_size, *frac_size)?;
+            let mut start_guest_tsc = resolve_initial_guest_tsc(
+                *initial_guest_tsc,
+                *initial_guest_uptime_sec,
+                *guest_hz,
+            )?;
+            let mut hosts = hosts.clone();
+            if let Some(count) = count {
+                let random = random_migrations(*count, *seed, *duration)?;
+                hosts.extend(random.iter().map(migrate_arg));
+            }
+            let mut host_defs = parse_hosts(
+                *initial_host_tsc,
+                *initial_host_hz,
+                hosts,
+                tsc_hosts.clone(),
+                *duration,
+            )?;
+            add_retunes(&mut host_defs, retunes.clone(), *duration)?;
+            add_tsc_events(&mut host_defs, tsc_events.clone(), *duration)?;
+            for h in host_defs.iter() {
+                check_hz("host frequency", h.host_freq)?;
+                if let Some(freq) = h.guest_freq {
+                    check_hz("guest frequency", freq)?;
                 }
-                Err(e) => {
-                    eprintln!("could not calculate guest TSC (rust): {}", e);
+            }
+            // each format's rows note where it can't run, rather than fail
+            if *compare_arch {
+                return Ok(());
+            }
+            let mut guest_hz = actual_guest_hz.unwrap_or(*guest_hz);
+            check_ratios(guest_hz, &host_defs, int_size, frac_size)?;
+
+            // the guest TSC only grows on each host, so checking the last
+            // step on each covers every step in between
+            let mut anchor_guest_tsc = start_guest_tsc;
+            for (i, h) in host_defs.iter().enumerate() {
+                guest_hz = h.guest_freq.unwrap_or(guest_hz);
+                if h.tsc_event {
+                    start_guest_tsc = guest_tsc(
+                        host_defs[i - 1].host_tsc,
+                        anchor_guest_tsc,
+                        h.host_freq,
+                        guest_hz,
+                        h.host_tsc,
+                        frac_size,
+                        int_size,
+                    )?;
                 }
+                anchor_guest_tsc = start_guest_tsc;
+                let end = host_defs.get(i + 1).map_or(*duration, |n| n.start);
+                let end_host_tsc = ((end - h.start) as u64)
+                    .checked_mul(h.host_freq)
+                    .and_then(|d| d.checked_add(h.host_tsc))
+                    .ok_or_else(|| {
+                        anyhow!("host {} TSC overflows before time {}", i, end)
+                    })?;
+
+                start_guest_tsc = guest_tsc(
+                    h.host_tsc,
+                    start_guest_tsc,
+                    h.host_freq,
+                    guest_hz,
+                    end_host_tsc,
+                    frac_size,
+                    int_size,
+                )?;
             }
         }
     }
-}
 
-fn cmd_hrtime(tsc: u64, freq_hz: u64) {
-    println!("calculating hrtime for parameters:");
-    println!("\ttsc: {tsc} ({:#x})", tsc);
-    println!("\tfrequency: {freq_hz} Hz ({:#x} Hz)", freq_hz);
-    println!("");
+    Ok(())
+}
 
-    let res = hrtime(tsc, freq_hz);
+fn main() {
+    let matches = Opt::command().get_matches();
+    let mut opt = Opt::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
 
-    match res {
-        Ok(hrtime) => {
-            println!("hrtime: {hrtime} ({:#x})", hrtime);
-        }
-        Err(e) => {
-            eprintln!("could not calculate hrtime: {}", e);
+    if let Command::Simulate {
+        scenario: Some(path),
+        ..
+    } = &opt.cmd
+    {
+        let scenario = match scenario::Scenario::load(path) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("could not load scenario {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        };
+        if let Some((_, sub)) = matches.subcommand() {
+            scenario.apply(&mut opt.cmd, sub);
         }
     }
-}
-
-fn cmd_tsc(hrtime: u64, freq_hz: u64) {
-    println!("calculating TSC for parameters:");
-    println!("\thrtime: {hrtime} ({:#x})", hrtime);
-    println!("\tfrequency: {freq_hz} Hz ({:#x} Hz)", freq_hz);
-    println!("");
 
-    let res = tsc(hrtime, freq_hz);
-
-    match res {
-        Ok(tsc) => {
-            println!("TSC: {tsc} ({:#x})", tsc);
-        }
-        Err(e) => {
-            eprintln!("could not calculate TSC: {}", e);
-        }
+    // a document on stdout is left as is, for other tools to read
+    let document = matches!(
+        &opt.cmd,
+        Command::Simulate { format, output: None, .. } if is_document(*format)
+    );
+    if !document {
+        println!();
     }
-}
 
-fn main() {
-    println!("");
+    // a real run rejects exactly what a dry run does, before printing any of
+    // its output
+    if let Err(e) = validate(&opt.cmd) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    if opt.dry_run {
+        println!("OK");
+        return;
+    }
 
-    let opt = Opt::parse();
+    let precision = opt.precision;
 
     match opt.cmd {
-        Command::Calc { cmd } => match cmd {
-            CalcCommand::Hrtime { tsc, freq_hz } => {
-                cmd_hrtime(tsc, freq_hz);
+        Command::Calc { cmd, both_signs } => match cmd {
+            CalcCommand::Hrtime {
+                tsc,
+                freq_hz,
+                rounding,
+            } => {
+                if !cmd_hrtime(tsc, freq_hz, rounding.into(), both_signs) {
+                    std::process::exit(1);
+                }
             }
-            CalcCommand::Tsc { hrtime, freq_hz } => {
-                cmd_tsc(hrtime, freq_hz);
+            CalcCommand::Tsc {
+                hrtime,
+                freq_hz,
+                rounding,
+            } => {
+                if !cmd_tsc(hrtime, freq_hz, rounding.into(), both_signs) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::Wallclock {
+                tsc,
+                freq_hz,
+                boot_epoch_ns,
+            } => {
+                if !cmd_wallclock(tsc, freq_hz, boot_epoch_ns) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::GuestTsc {
+                initial_host_tsc,
+                initial_guest_tsc,
+                initial_guest_uptime_sec,
+                host_tsc,
+                host_hz,
+                guest_hz,
+                math_impl,
+                int_size,
+                frac_size,
+                offset_bits,
+                tsc_width,
+                saturate,
+            } => {
+                let initial_guest_tsc = match resolve_initial_guest_tsc(
+                    initial_guest_tsc,
+                    initial_guest_uptime_sec,
+                    guest_hz,
+                ) {
+                    Ok(tsc) => tsc,
+                    Err(e) => {
+                        eprintln!("invalid initial guest TSC: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+                if !cmd_guest_tsc(
+                    initial_host_tsc,
+                    initial_guest_tsc,
+                    host_tsc,
+                    host_hz,
+                    guest_hz,
+                    math_impl,
+                    int_size,
+                    frac_size,
+                    offset_bits,
+                    tsc_width,
+                    saturate,
+                    both_signs,
+                ) {
+                    std::process::exit(1);
+                }
             }
-            CalcCommand::GuestTsc {
+            CalcCommand::Compare {
                 initial_host_tsc,
                 initial_guest_tsc,
+                initial_guest_uptime_sec,
                 host_tsc,
                 host_hz,
                 guest_hz,
-                math_impl,
                 int_size,
                 frac_size,
             } => {
-                cmd_guest_tsc(
+                let initial_guest_tsc = match resolve_initial_guest_tsc(
+                    initial_guest_tsc,
+                    initial_guest_uptime_sec,
+                    guest_hz,
+                ) {
+                    Ok(tsc) => tsc,
+                    Err(e) => {
+                        eprintln!("invalid initial guest TSC: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+                if !cmd_compare(
                     initial_host_tsc,
                     initial_guest_tsc,
                     host_tsc,
                     host_hz,
                     guest_hz,
-                    math_impl,
                     int_size,
                     frac_size,
-                );
+                ) {
+                    std::process::exit(1);
+                }
             }
             CalcCommand::Offset {
                 initial_host_tsc,
                 initial_guest_tsc,
+                initial_guest_uptime_sec,
                 guest_hz,
                 host_hz,
                 math_impl,
                 int_size,
                 frac_size,
             } => {
-                cmd_offset(
+                let initial_guest_tsc = match resolve_initial_guest_tsc(
+                    initial_guest_tsc,
+                    initial_guest_uptime_sec,
+                    guest_hz,
+                ) {
+                    Ok(tsc) => tsc,
+                    Err(e) => {
+                        eprintln!("invalid initial guest TSC: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+                if !cmd_offset(
                     initial_host_tsc,
                     initial_guest_tsc,
                     guest_hz,
@@ -635,7 +5429,10 @@ fn main() {
                     math_impl,
                     frac_size,
                     int_size,
-                );
+                    both_signs,
+                ) {
+                    std::process::exit(1);
+                }
             }
             CalcCommand::Freq {
                 host_hz,
@@ -643,25 +5440,562 @@ fn main() {
                 math_impl,
                 int_size,
                 frac_size,
+                explain,
             } => {
-                cmd_freq_multiplier(
-                    guest_hz, host_hz, math_impl, int_size, frac_size,
-                );
+                if !cmd_freq_multiplier(
+                    guest_hz, host_hz, math_impl, int_size, frac_size, explain,
+                    precision, both_signs,
+                ) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::BitContribution {
+                host_hz,
+                guest_hz,
+                int_size,
+                frac_size,
+            } => {
+                if !cmd_bit_contribution(
+                    guest_hz, host_hz, int_size, frac_size, precision,
+                    both_signs,
+                ) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::Ratio {
+                int_size,
+                frac_size,
+            } => {
+                if !cmd_ratio_bounds(int_size, frac_size, precision) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::CommonExactFreq {
+                host_hz,
+                guest_hz,
+                window,
+                int_size,
+                frac_size,
+            } => {
+                if !cmd_common_exact_freq(
+                    host_hz, guest_hz, window, int_size, frac_size,
+                ) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::OffsetError {
+                initial_host_tsc,
+                guest_hz,
+                host_hz,
+                int_size,
+                frac_size,
+            } => {
+                if !cmd_offset_error(
+                    initial_host_tsc,
+                    guest_hz,
+                    host_hz,
+                    frac_size,
+                    int_size,
+                    precision,
+                ) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::ColdMigrate {
+                guest_tsc,
+                dest_host_tsc,
+                guest_hz,
+                host_hz,
+                int_size,
+                frac_size,
+            } => {
+                if !cmd_cold_migrate(
+                    guest_tsc,
+                    dest_host_tsc,
+                    guest_hz,
+                    host_hz,
+                    frac_size,
+                    int_size,
+                    both_signs,
+                ) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::OffsetBits {
+                initial_host_tsc,
+                initial_guest_tsc,
+                guest_hz,
+                host_hz,
+                int_size,
+                frac_size,
+            } => {
+                if !cmd_offset_bits(
+                    initial_host_tsc,
+                    initial_guest_tsc,
+                    guest_hz,
+                    host_hz,
+                    frac_size,
+                    int_size,
+                ) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::HopError {
+                guest_tsc,
+                guest_hz,
+                src_tsc,
+                src_hz,
+                dst_tsc,
+                dst_hz,
+                int_size,
+                frac_size,
+            } => {
+                if !cmd_hop_error(
+                    guest_tsc, guest_hz, src_tsc, src_hz, dst_tsc, dst_hz,
+                    int_size, frac_size,
+                ) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::VerifyMigration {
+                src_boot_tsc,
+                src_hz,
+                src_tsc,
+                guest_hz,
+                dst_tsc,
+                dst_hz,
+                int_size,
+                frac_size,
+            } => {
+                if !cmd_verify_migration(
+                    src_boot_tsc,
+                    src_hz,
+                    src_tsc,
+                    guest_hz,
+                    dst_tsc,
+                    dst_hz,
+                    int_size,
+                    frac_size,
+                ) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::DeadlineMap {
+                deadline,
+                initial_host_tsc,
+                initial_guest_tsc,
+                host_hz,
+                guest_hz,
+                src_tsc,
+                dst_tsc,
+                dst_hz,
+                int_size,
+                frac_size,
+            } => {
+                if !cmd_deadline_map(
+                    deadline,
+                    initial_host_tsc,
+                    initial_guest_tsc,
+                    host_hz,
+                    guest_hz,
+                    src_tsc,
+                    dst_tsc,
+                    dst_hz,
+                    int_size,
+                    frac_size,
+                    both_signs,
+                ) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::LineEquation {
+                initial_host_tsc,
+                initial_guest_tsc,
+                host_hz,
+                guest_hz,
+                src_tsc,
+                dst_tsc,
+                dst_hz,
+                int_size,
+                frac_size,
+            } => {
+                if !cmd_line_equation(
+                    initial_host_tsc,
+                    initial_guest_tsc,
+                    host_hz,
+                    guest_hz,
+                    src_tsc.zip(dst_tsc),
+                    dst_hz,
+                    int_size,
+                    frac_size,
+                    precision,
+                ) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::ResumeAt {
+                initial_host_tsc,
+                initial_guest_tsc,
+                pause_host_tsc,
+                resume_host_tsc,
+                mode,
+                paused_sec,
+                host_hz,
+                guest_hz,
+                int_size,
+                frac_size,
+            } => {
+                if !cmd_resume_at(
+                    initial_host_tsc,
+                    initial_guest_tsc,
+                    pause_host_tsc,
+                    resume_host_tsc,
+                    mode.into(),
+                    paused_sec,
+                    host_hz,
+                    guest_hz,
+                    int_size,
+                    frac_size,
+                    both_signs,
+                ) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::ResumeRegisters {
+                initial_host_tsc,
+                initial_guest_tsc,
+                pause_host_tsc,
+                resume_host_tsc,
+                host_hz,
+                guest_hz,
+                int_size,
+                frac_size,
+            } => {
+                if !cmd_resume_registers(
+                    initial_host_tsc,
+                    initial_guest_tsc,
+                    pause_host_tsc,
+                    resume_host_tsc,
+                    host_hz,
+                    guest_hz,
+                    int_size,
+                    frac_size,
+                    both_signs,
+                ) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::GuestTscStats {
+                initial_host_tsc,
+                initial_guest_tsc,
+                host_hz,
+                guest_hz,
+                int_size,
+                frac_size,
+            } => {
+                if !cmd_guest_tsc_stats(
+                    initial_host_tsc,
+                    initial_guest_tsc,
+                    host_hz,
+                    guest_hz,
+                    int_size,
+                    frac_size,
+                    both_signs,
+                ) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::GuestTscAtTrace {
+                initial_host_tsc,
+                initial_guest_tsc,
+                host_hz,
+                guest_hz,
+                int_size,
+                frac_size,
+            } => {
+                if !cmd_guest_tsc_at_trace(
+                    initial_host_tsc,
+                    initial_guest_tsc,
+                    host_hz,
+                    guest_hz,
+                    int_size,
+                    frac_size,
+                ) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::ObservedFreq {
+                h1,
+                g1,
+                h2,
+                g2,
+                host_hz,
+                guest_hz,
+            } => {
+                if !cmd_observed_freq(
+                    h1, g1, h2, g2, host_hz, guest_hz, precision,
+                ) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::HostForMult {
+                multiplier,
+                guest_hz,
+                int_size,
+                frac_size,
+            } => {
+                if !cmd_host_for_mult(multiplier, guest_hz, int_size, frac_size)
+                {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::Aliasing {
+                guest_hz,
+                host_hz,
+                int_size,
+                frac_size,
+            } => {
+                if !cmd_aliasing(
+                    guest_hz, host_hz, int_size, frac_size, precision,
+                ) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::DailyError {
+                guest_hz,
+                host_hz,
+                int_size,
+                frac_size,
+            } => {
+                if !cmd_daily_error(
+                    guest_hz, host_hz, int_size, frac_size, precision,
+                ) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::MaxDrift {
+                duration,
+                guest_hz,
+                host_hz,
+                int_size,
+                frac_size,
+            } => {
+                if !cmd_max_drift(
+                    duration, guest_hz, host_hz, int_size, frac_size,
+                ) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::Wrap32 {
+                guest_hz,
+                start_tsc,
+            } => {
+                if !cmd_wrap32(guest_hz, start_tsc, both_signs) {
+                    std::process::exit(1);
+                }
+            }
+            CalcCommand::Calibrate {
+                t1,
+                tsc1,
+                t2,
+                tsc2,
+                nominal_hz,
+            } => {
+                if !cmd_calibrate(t1, tsc1, t2, tsc2, nominal_hz, precision) {
+                    std::process::exit(1);
+                }
             }
         },
+        Command::Selftest => {
+            if !cmd_selftest() {
+                std::process::exit(1);
+            }
+        }
         Command::Simulate {
             duration,
             initial_host_tsc,
             initial_host_hz,
             guest_hz,
-            hosts,
+            actual_guest_hz,
+            guest_ppm,
+            initial_guest_tsc,
+            initial_guest_uptime_sec,
+            mut hosts,
+            tsc_hosts,
+            retunes,
+            tsc_events,
+            random_migrations: count,
+            seed,
             arch,
+            int_size,
+            frac_size,
             hex,
+            as_seconds,
+            pre_boot_sec,
+            step_ns,
+            metrics_port,
+            real_time,
+            speed,
+            boundary,
+            drift_histogram,
+            show_drift,
+            show_hrtime,
+            human,
+            plot,
+            format,
+            output,
+            compare_arch,
+            check_realtime,
+            tolerance_ppm,
+            scenario: _,
         } => {
-            let host_defs =
-                parse_hosts(initial_host_tsc, initial_host_hz, hosts, duration)
-                    .unwrap();
-            cmd_simulate(duration, guest_hz, host_defs, arch, hex);
+            if let Err(e) = check_duration(duration) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            let guest_hz = apply_ppm_correction(guest_hz, guest_ppm);
+            if let Err(e) = check_hz("guest frequency", guest_hz) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+
+            // serving metrics implies real-time pacing
+            let pacing = if real_time || metrics_port.is_some() {
+                if !(speed.is_finite() && speed > 0.0) {
+                    eprintln!("speed must be a positive number: {}", speed);
+                    std::process::exit(1);
+                }
+                Some(std::time::Duration::from_secs_f64(1.0 / speed))
+            } else {
+                None
+            };
+            let initial_guest_tsc = match resolve_initial_guest_tsc(
+                initial_guest_tsc,
+                initial_guest_uptime_sec,
+                guest_hz,
+            ) {
+                Ok(tsc) => tsc,
+                Err(e) => {
+                    eprintln!("invalid initial guest TSC: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            if let Some(count) = count {
+                let random = match random_migrations(count, seed, duration) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        eprintln!("invalid migration: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+
+                // keep a document on stdout clean
+                let replay = replay_args(seed, &random);
+                if is_document(format) && output.is_none() {
+                    eprintln!("{}", replay);
+                } else {
+                    println!("{}", replay);
+                }
+                hosts.extend(random.iter().map(migrate_arg));
+            }
+            let mut host_defs = match parse_hosts(
+                initial_host_tsc,
+                initial_host_hz,
+                hosts,
+                tsc_hosts,
+                duration,
+            ) {
+                Ok(h) => h,
+                Err(e) => {
+                    eprintln!("invalid migration: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            if let Err(e) = add_retunes(&mut host_defs, retunes, duration) {
+                eprintln!("invalid retune: {}", e);
+                std::process::exit(1);
+            }
+            if let Err(e) = add_tsc_events(&mut host_defs, tsc_events, duration)
+            {
+                eprintln!("invalid TSC event: {}", e);
+                std::process::exit(1);
+            }
+            if compare_arch {
+                if !cmd_simulate_compare_arch(
+                    duration,
+                    guest_hz,
+                    actual_guest_hz,
+                    initial_guest_tsc,
+                    host_defs,
+                    step_ns,
+                    boundary,
+                    hex,
+                ) {
+                    std::process::exit(1);
+                }
+                return;
+            }
+            let (int_size, frac_size) =
+                match arch_format(arch.into(), int_size, frac_size) {
+                    Ok(f) => f,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    }
+                };
+            if let Err(e) = check_ratios(
+                actual_guest_hz.unwrap_or(guest_hz),
+                &host_defs,
+                int_size,
+                frac_size,
+            ) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            let realtime_tolerance_ppm = match (check_realtime, tolerance_ppm) {
+                (false, _) => None,
+                (true, Some(t)) => Some(t),
+                (true, None) => match realtime_tolerance_ppm(
+                    duration as u64,
+                    guest_hz,
+                    &host_defs.iter().map(|h| h.host_freq).collect::<Vec<_>>(),
+                    frac_size,
+                    int_size,
+                ) {
+                    Ok(t) => Some(t),
+                    Err(e) => {
+                        eprintln!("could not calculate drift bound: {}", e);
+                        std::process::exit(1);
+                    }
+                },
+            };
+            if !cmd_simulate(SimulateOptions {
+                duration,
+                guest_hz,
+                actual_guest_hz,
+                initial_guest_tsc,
+                hosts: host_defs,
+                int_size,
+                frac_size,
+                print_hex: hex,
+                as_seconds,
+                pre_boot_sec,
+                step_ns,
+                boundary,
+                metrics_port,
+                pacing,
+                drift_histogram,
+                show_drift,
+                show_hrtime,
+                human,
+                plot,
+                format,
+                output,
+                realtime_tolerance_ppm,
+                precision,
+            }) {
+                std::process::exit(1);
+            }
         }
     }
 }
@@ -0,0 +1,102 @@
+// Serve the state of a running simulation as Prometheus text-format metrics
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// Simulation state at the most recent step
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Sample {
+    pub time_sec: usize,
+    pub host_index: usize,
+    pub guest_tsc: u64,
+    pub host_tsc: u64,
+    pub drift_ns: i128,
+}
+
+pub struct MetricsServer {
+    sample: Arc<Mutex<Sample>>,
+}
+
+impl MetricsServer {
+    // Start responding to HTTP requests on localhost:`port` from a background
+    // thread. Every request, regardless of path, gets the current metrics.
+    pub fn start(port: u16) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        let sample = Arc::new(Mutex::new(Sample::default()));
+        let shared = Arc::clone(&sample);
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(s) => s,
+                    Err(_) => continue,
+                };
+
+                // The request itself doesn't matter, but read it so the
+                // client isn't reset before it sees the response
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf);
+
+                let body = render(&shared.lock().unwrap());
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\n\
+                     Content-Type: text/plain; version=0.0.4\r\n\
+                     Content-Length: {}\r\n\
+                     Connection: close\r\n\
+                     \r\n\
+                     {}",
+                    body.len(),
+                    body
+                );
+            }
+        });
+
+        Ok(Self { sample })
+    }
+
+    pub fn update(&self, sample: Sample) {
+        *self.sample.lock().unwrap() = sample;
+    }
+}
+
+fn render(s: &Sample) -> String {
+    let metrics: [(&str, &str, String); 5] = [
+        (
+            "tsc_sim_time_seconds",
+            "Simulated time since guest boot",
+            s.time_sec.to_string(),
+        ),
+        (
+            "tsc_sim_host_index",
+            "Index of the host the guest is running on",
+            s.host_index.to_string(),
+        ),
+        (
+            "tsc_sim_guest_tsc",
+            "Current guest TSC",
+            s.guest_tsc.to_string(),
+        ),
+        (
+            "tsc_sim_host_tsc",
+            "Current host TSC",
+            s.host_tsc.to_string(),
+        ),
+        (
+            "tsc_sim_drift_nanoseconds",
+            "Guest elapsed time minus real elapsed time",
+            s.drift_ns.to_string(),
+        ),
+    ];
+
+    let mut out = String::new();
+    for (name, help, value) in metrics.iter() {
+        out += &format!("# HELP {name} {help}\n");
+        out += &format!("# TYPE {name} gauge\n");
+        out += &format!("{name} {value}\n");
+    }
+
+    out
+}
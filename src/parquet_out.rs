@@ -0,0 +1,100 @@
+// Write simulation rows to a Parquet file
+//
+// Schema, one row per step on each host:
+// - time: Int64, seconds since guest boot
+// - guest_tsc: UInt64
+// - host_tsc: UInt64
+// - host_index: UInt32, index of the host the guest is running on (0 is the
+//   boot host, then one per migration)
+
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow_array::{
+    ArrayRef, Int64Array, RecordBatch, UInt32Array, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+
+// Rows are buffered and written out as a record batch this many at a time
+const BATCH_ROWS: usize = 64 * 1024;
+
+pub struct RowWriter {
+    writer: ArrowWriter<File>,
+    schema: Arc<Schema>,
+    time: Vec<i64>,
+    guest_tsc: Vec<u64>,
+    host_tsc: Vec<u64>,
+    host_index: Vec<u32>,
+    rows: usize,
+}
+
+impl RowWriter {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("time", DataType::Int64, false),
+            Field::new("guest_tsc", DataType::UInt64, false),
+            Field::new("host_tsc", DataType::UInt64, false),
+            Field::new("host_index", DataType::UInt32, false),
+        ]));
+        let file = File::create(path)?;
+        let writer = ArrowWriter::try_new(file, Arc::clone(&schema), None)?;
+
+        Ok(Self {
+            writer,
+            schema,
+            time: Vec::with_capacity(BATCH_ROWS),
+            guest_tsc: Vec::with_capacity(BATCH_ROWS),
+            host_tsc: Vec::with_capacity(BATCH_ROWS),
+            host_index: Vec::with_capacity(BATCH_ROWS),
+            rows: 0,
+        })
+    }
+
+    pub fn push(
+        &mut self,
+        time: i64,
+        guest_tsc: u64,
+        host_tsc: u64,
+        host_index: u32,
+    ) -> anyhow::Result<()> {
+        self.time.push(time);
+        self.guest_tsc.push(guest_tsc);
+        self.host_tsc.push(host_tsc);
+        self.host_index.push(host_index);
+        self.rows += 1;
+
+        if self.time.len() >= BATCH_ROWS {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        if self.time.is_empty() {
+            return Ok(());
+        }
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(Int64Array::from(std::mem::take(&mut self.time))),
+            Arc::new(UInt64Array::from(std::mem::take(&mut self.guest_tsc))),
+            Arc::new(UInt64Array::from(std::mem::take(&mut self.host_tsc))),
+            Arc::new(UInt32Array::from(std::mem::take(&mut self.host_index))),
+        ];
+        let batch = RecordBatch::try_new(Arc::clone(&self.schema), columns)?;
+        self.writer.write(&batch)?;
+
+        Ok(())
+    }
+
+    // Write out any buffered rows and the file footer, returning the number of
+    // rows written
+    pub fn finish(mut self) -> anyhow::Result<usize> {
+        self.flush()?;
+        self.writer.close()?;
+
+        Ok(self.rows)
+    }
+}
@@ -0,0 +1,42 @@
+//! Simulation rows, and writing them out as a document for other tools to
+//! read.
+
+/// What the guest is doing at the time of a simulation row
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    PreBoot,
+    Boot,
+    Migration,
+    Retune,
+    TscEvent,
+}
+
+impl Phase {
+    /// The name of the phase, as written in a document
+    pub fn label(self) -> &'static str {
+        match self {
+            Phase::PreBoot => "pre_boot",
+            Phase::Boot => "boot",
+            Phase::Migration => "migration",
+            Phase::Retune => "retune",
+            Phase::TscEvent => "tsc_event",
+        }
+    }
+}
+
+/// Writes simulation rows as a text document, to a file or in place of the
+/// table on stdout
+pub trait RowSink {
+    fn push(
+        &mut self,
+        t: i64,
+        guest_tsc: u64,
+        host_tsc: u64,
+        host_index: u32,
+        phase: Phase,
+    ) -> anyhow::Result<()>;
+
+    /// Finish the document, returning the number of rows written
+    fn finish(self: Box<Self>) -> anyhow::Result<usize>;
+}
@@ -0,0 +1,104 @@
+// Load simulation inputs from a TOML scenario file
+//
+// The file's keys mirror the simulate flags, e.g.:
+//
+//   duration = 3600
+//   initial_host_tsc = 1000000000
+//   initial_host_hz = 1000000000
+//   guest_hz = 1000000000
+//   arch = "intel"
+//
+//   [[migrate]]
+//   t = 60
+//   host_tsc = 5000000000
+//   host_hz = 2000000000
+//
+// Every key is optional; flags given on the command line override the file.
+
+use std::path::Path;
+
+use clap::{ArgMatches, ValueSource};
+use serde::Deserialize;
+
+use crate::{ArchArg, Command};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    duration: Option<usize>,
+    initial_host_tsc: Option<u64>,
+    initial_host_hz: Option<u64>,
+    guest_hz: Option<u64>,
+    arch: Option<ArchArg>,
+    #[serde(default)]
+    migrate: Vec<Migration>,
+}
+
+// A migration, as given to --migrate
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Migration {
+    t: usize,
+    host_tsc: u64,
+    host_hz: u64,
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&text)?)
+    }
+
+    // Fill in the simulate inputs from the file, except those given on the
+    // command line
+    pub fn apply(self, cmd: &mut Command, matches: &ArgMatches) {
+        let Command::Simulate {
+            duration,
+            initial_host_tsc,
+            initial_host_hz,
+            guest_hz,
+            hosts,
+            arch,
+            ..
+        } = cmd
+        else {
+            return;
+        };
+
+        let from_cli = |id: &str| {
+            matches.value_source(id) == Some(ValueSource::CommandLine)
+        };
+
+        if let (Some(v), false) = (self.duration, from_cli("duration")) {
+            *duration = v;
+        }
+        if let (Some(v), false) =
+            (self.initial_host_tsc, from_cli("initial-host-tsc"))
+        {
+            *initial_host_tsc = v;
+        }
+        if let (Some(v), false) =
+            (self.initial_host_hz, from_cli("initial-host-hz"))
+        {
+            *initial_host_hz = v;
+        }
+        if let (Some(v), false) = (self.guest_hz, from_cli("guest-hz")) {
+            *guest_hz = v;
+        }
+        if let (Some(v), false) = (self.arch, from_cli("arch")) {
+            *arch = v;
+        }
+
+        // any migrations on the command line replace the file's
+        if !["hosts", "tsc-hosts", "random-migrations"]
+            .iter()
+            .any(|id| from_cli(id))
+        {
+            *hosts = self
+                .migrate
+                .iter()
+                .map(|m| format!("{} {} {}", m.t, m.host_tsc, m.host_hz))
+                .collect();
+        }
+    }
+}
@@ -1,150 +1,18 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
-
-    use super::{FRAC_SIZE_AMD, FRAC_SIZE_INTEL};
-
-    struct Frt {
-        pub g: u64,
-        pub h: u64,
-        pub f: u32,
-        pub v: u64,
-    }
-
-    #[rustfmt::skip]
-const FREQ_RATIO_TESTS_VALID: &'static [Frt] = &[
-
-    // Smaller frequencies (~KHz)
-
-    // 0.5 = 2^-1
-    Frt { g: 1000, h: 2000, f: 2,                v: 1 << 1 },
-    Frt { g: 1000, h: 2000, f: 8,                v: 1 << 7 },
-    Frt { g: 1000, h: 2000, f: FRAC_SIZE_AMD,    v: 1 << 31 },
-    Frt { g: 1000, h: 2000, f: FRAC_SIZE_INTEL,  v: 1 << 47 },
-    Frt { g: 1000, h: 2000, f: 63,               v: 1 << 62 },
-
-    // 2/3 = 2^-1 + 2^-3 + 2^-5 + 2^-7 ...
-    Frt { g: 2000, h: 3000, f: 2,                v: 0b10 },
-    Frt { g: 2000, h: 3000, f: 8,                v: 0b10101010 },
-    Frt { g: 2000, h: 3000, f: FRAC_SIZE_AMD,    v: 0xaaaa_aaaa },
-    Frt { g: 2000, h: 3000, f: FRAC_SIZE_INTEL,  v: 0xaaaa_aaaa_aaaa },
-    Frt { g: 2000, h: 3000, f: 63,               v: 0x5555_5555_5555_5555 },
-
-    // 1.5 = 2^0 + 2^-1
-    Frt { g: 3000, h: 2000, f: 2,                v: 1 << 2 | 1 << 1 },
-    Frt { g: 3000, h: 2000, f: 8,                v: 1 << 8 | 1 << 7 },
-    Frt { g: 3000, h: 2000, f: FRAC_SIZE_AMD,    v: 1 << 32 | 1 << 31 },
-    Frt { g: 3000, h: 2000, f: FRAC_SIZE_INTEL,  v: 1 << 48 | 1 << 47 },
-    Frt { g: 3000, h: 2000, f: 63,               v: 1 << 63 | 1 << 62 },
-
-
-    // Larger frequencies (~GHz)
-
-    // 0.5 = 2^-1
-    Frt { g: 1000000000, h: 2000000000, f: 2,                v: 1 << 1 },
-    Frt { g: 1000000000, h: 2000000000, f: 8,                v: 1 << 7 },
-    Frt { g: 1000000000, h: 2000000000, f: FRAC_SIZE_AMD,    v: 1 << 31 },
-    Frt { g: 1000000000, h: 2000000000, f: FRAC_SIZE_INTEL,  v: 1 << 47 },
-    Frt { g: 1000000000, h: 2000000000, f: 63,               v: 1 << 62 },
-
-    // 2/3 = 2^-1 + 2^-3 + 2^-5 + 2^-7 ...
-    Frt { g: 2000000000, h: 3000000000, f: 2,                v: 0b10 },
-    Frt { g: 2000000000, h: 3000000000, f: 8,                v: 0b10101010 },
-    Frt { g: 2000000000, h: 3000000000, f: FRAC_SIZE_AMD,    v: 0xaaaa_aaaa },
-    Frt { g: 2000000000, h: 3000000000, f: FRAC_SIZE_INTEL,  v: 0xaaaa_aaaa_aaaa },
-    Frt { g: 2000000000, h: 3000000000, f: 63,               v: 0x5555_5555_5555_5555 },
-
-    // 1.5 = 2^0 + 2^-1
-    Frt { g: 3000000000, h: 2000000000, f: 2,                v: 1 << 2 | 1 << 1 },
-    Frt { g: 3000000000, h: 2000000000, f: 8,                v: 1 << 8 | 1 << 7 },
-    Frt { g: 3000000000, h: 2000000000, f: FRAC_SIZE_AMD,    v: 1 << 32 | 1 << 31 },
-    Frt { g: 3000000000, h: 2000000000, f: FRAC_SIZE_INTEL,  v: 1 << 48 | 1 << 47 },
-    Frt { g: 3000000000, h: 2000000000, f: 63,               v: 1 << 63 | 1 << 62 },
-];
-
-    struct Frti {
-        pub g: u64,
-        pub h: u64,
-        pub f: u32,
-    }
-
-    #[rustfmt::skip]
-const FREQ_RATIO_TESTS_INVALID: &'static [Frti] = &[
-    // values that overflow the int portion, generating an error for rust and a
-    // #DE for the assembly version
-
-    // can't fit ratio 2.0 in 1-bit integer
-    Frti { g: 2000, h: 1000, f: 63, },
-    Frti { g: 2000000000, h: 1000000000, f: 63, },
-
-    // can't fit ratio 2^32 in 32-bits
-    Frti { g: 4294967296, h: 1, f: FRAC_SIZE_AMD, },
-
-    // can't fit ratio 2^16 in 16-bits
-    Frti { g: 65536, h: 1, f: FRAC_SIZE_INTEL, },
-];
-
-    struct Stt {
-        pub t: u64,
-        pub m: u64,
-        pub f: u32,
-        pub v: u64,
-    }
-
-    #[rustfmt::skip]
-const SCALE_TSC_TESTS_VALID: &'static [Stt] = &[
-    // Ratio = 1.0
-    Stt { t: 1, m: 1 << 1, f: 1, v: 1 },
-    Stt { t: 1000000000, m: 1 << FRAC_SIZE_AMD,     f: FRAC_SIZE_AMD,   v: 1000000000, },
-    Stt { t: 1000000000, m: 1 << FRAC_SIZE_INTEL,   f: FRAC_SIZE_INTEL, v: 1000000000, },
-    Stt { t: 5890513020, m: 1 << FRAC_SIZE_AMD,     f: FRAC_SIZE_AMD,   v: 5890513020, },
-    Stt { t: 5890513020, m: 1 << FRAC_SIZE_INTEL,   f: FRAC_SIZE_INTEL, v: 5890513020, },
-
-    // Ratio = 0.5
-    Stt { t: 1, m: 1 << 0, f: 1, v: 0 },
-    Stt { t: 1000000000, m: 1 << 31, f: FRAC_SIZE_AMD,      v: 500000000, },
-    Stt { t: 1000000000, m: 1 << 47, f: FRAC_SIZE_INTEL,    v: 500000000, },
-    Stt { t: 5890513020, m: 1 << 31, f: FRAC_SIZE_AMD,      v: 5890513020 / 2, },
-    Stt { t: 5890513020, m: 1 << 47, f: FRAC_SIZE_INTEL,    v: 5890513020 / 2, },
-
-    // Ratio = 1.5
-    Stt { t: 1, m: 1 << 1 | 1 << 0, f: 1, v: 1 },
-    Stt { t: 1000000000, m: 1 << 32 | 1 << 31, f: FRAC_SIZE_AMD,      v: 1500000000, },
-    Stt { t: 1000000000, m: 1 << 48 | 1 << 47, f: FRAC_SIZE_INTEL,    v: 1500000000, },
-    Stt { t: 5890513020, m: 1 << 32 | 1 << 31, f: FRAC_SIZE_AMD,      v: 5890513020 + 5890513020 / 2, },
-    Stt { t: 5890513020, m: 1 << 48 | 1 << 47, f: FRAC_SIZE_INTEL,    v: 5890513020 + 5890513020 / 2, },
-
-    // Edge cases
-    Stt { t: u64::MAX, m: 1 << 1, f: 1, v: u64::MAX, },
-    Stt { t: u64::MAX, m: 1 << 32, f: FRAC_SIZE_AMD, v: u64::MAX, },
-    Stt { t: u64::MAX, m: 1 << 48, f: FRAC_SIZE_INTEL, v: u64::MAX, },
-];
-
-    struct Stti {
-        pub t: u64,
-        pub m: u64,
-        pub f: u32,
-    }
-
-    #[rustfmt::skip]
-const SCALE_TSC_TESTS_INVALID: &'static [Stti] = &[
-    // values that overflow: (tsc * multiplier) >> frac
-    Stti { t: u64::MAX, m: 1 << 1 | 1 << 0, f: 1 },
-    Stti { t: u64::MAX, m: 1 << 32 | 1 << 31, f: FRAC_SIZE_AMD },
-    Stti { t: u64::MAX, m: 1 << 48 | 1 << 47, f: FRAC_SIZE_INTEL },
-];
-
-    use super::{
-        FREQ_RATIO_TESTS_INVALID, FREQ_RATIO_TESTS_VALID,
+    use crate::vectors::{
+        FREQ_RATIO_ROUNDED_TESTS, FREQ_RATIO_TESTS_INVALID,
+        FREQ_RATIO_TESTS_VALID, GUEST_TSC_REGRESSIONS, HRTIME_TESTS,
         SCALE_TSC_TESTS_INVALID, SCALE_TSC_TESTS_VALID,
     };
-    use crate::asm_math;
-    use crate::math;
+    use tsc_simulator::asm_math;
+    use tsc_simulator::input;
+    use tsc_simulator::math;
 
     #[test]
     fn test_freq_ratio() {
-        for i in 0..FREQ_RATIO_TESTS_VALID.len() {
-            let t = &FREQ_RATIO_TESTS_VALID[i];
-
+        for t in FREQ_RATIO_TESTS_VALID.iter() {
             let msg = format!(
                 "guest_freq={}, host_freq={}, frac_size={}, expected_val={}",
                 t.g, t.h, t.f, t.v
@@ -176,9 +44,7 @@ const SCALE_TSC_TESTS_INVALID: &'static [Stti] = &[
 
     #[test]
     fn test_freq_ratio_invalid() {
-        for i in 0..FREQ_RATIO_TESTS_INVALID.len() {
-            let t = &FREQ_RATIO_TESTS_INVALID[i];
-
+        for t in FREQ_RATIO_TESTS_INVALID.iter() {
             let msg = format!(
                 "guest_freq={}, host_freq={}, frac_size={}",
                 t.g, t.h, t.f
@@ -199,9 +65,7 @@ const SCALE_TSC_TESTS_INVALID: &'static [Stti] = &[
 
     #[test]
     fn test_scale_tsc() {
-        for i in 0..SCALE_TSC_TESTS_VALID.len() {
-            let t = &SCALE_TSC_TESTS_VALID[i];
-
+        for t in SCALE_TSC_TESTS_VALID.iter() {
             let msg = format!(
                 "tsc={}, mult={}, frac_size={}, expected_val={}",
                 t.t, t.m, t.f, t.v
@@ -233,9 +97,7 @@ const SCALE_TSC_TESTS_INVALID: &'static [Stti] = &[
 
     #[test]
     fn test_scale_tsc_invalid() {
-        for i in 0..SCALE_TSC_TESTS_INVALID.len() {
-            let t = &SCALE_TSC_TESTS_INVALID[i];
-
+        for t in SCALE_TSC_TESTS_INVALID.iter() {
             let msg = format!("tsc={}, mult={}, frac_size={}", t.t, t.m, t.f);
 
             // Check that rust implementation throws an error
@@ -251,4 +113,2562 @@ const SCALE_TSC_TESTS_INVALID: &'static [Stti] = &[
             unsafe { asm_math::scale_tsc(t.t, t.m, t.f) };
         }
     }
+
+    #[test]
+    fn test_guest_tsc_regressions() {
+        for t in GUEST_TSC_REGRESSIONS.iter() {
+            let msg = format!(
+                "initial_host_tsc={}, initial_guest_tsc={}, host_freq={}, \
+                guest_freq={}, host_tsc={}, frac_size={}, int_size={}",
+                t.ih, t.ig, t.h, t.g, t.t, t.f, t.i
+            );
+
+            let res = math::guest_tsc(t.ih, t.ig, t.h, t.g, t.t, t.f, t.i);
+            match (res, t.v) {
+                (Ok(v), Some(expected)) => {
+                    assert_eq!(v, expected, "{}", msg);
+                }
+                (Ok(v), None) => {
+                    panic!("got value {} instead of error: {}", v, msg);
+                }
+                (Err(e), Some(expected)) => {
+                    panic!(
+                        "got err {} instead of value {}: {}",
+                        e, expected, msg
+                    );
+                }
+                (Err(_), None) => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_calibrate_freq() {
+        // 1 GHz, samples one second apart
+        let f = math::calibrate_freq(0, 0, 1_000_000_000, 1_000_000_000);
+        assert_eq!(f.unwrap(), 1_000_000_000);
+
+        // 2.5 GHz, samples 10ms apart from a nonzero anchor
+        let f = math::calibrate_freq(
+            3_000_000_000,
+            7_500_000_000,
+            3_010_000_000,
+            7_525_000_000,
+        );
+        assert_eq!(f.unwrap(), 2_500_000_000);
+
+        // large TSC delta relies on the 128-bit intermediate
+        let f = math::calibrate_freq(0, 0, 1 << 40, 1 << 50);
+        assert_eq!(f.unwrap(), (1u64 << 10) * 1_000_000_000);
+
+        // samples taken at the same time, or out of order
+        assert!(math::calibrate_freq(5, 100, 5, 200).is_err());
+        assert!(math::calibrate_freq(10, 100, 5, 200).is_err());
+        assert!(math::calibrate_freq(5, 200, 10, 100).is_err());
+    }
+
+    #[test]
+    fn test_observed_freq() {
+        // guest at 1.5 GHz on a 3 GHz host, readings one second apart
+        let f = math::observed_freq(
+            3_000_000_000,
+            0,
+            6_000_000_000,
+            1_500_000_000,
+            3_000_000_000,
+        );
+        assert_eq!(f.unwrap(), 1_500_000_000);
+
+        // a guest running 1 ppm fast
+        let f = math::observed_freq(
+            0,
+            0,
+            1_000_000_000,
+            1_000_001_000,
+            1_000_000_000,
+        );
+        assert_eq!(f.unwrap(), 1_000_001_000);
+
+        // readings at the same host TSC, or out of order
+        assert!(math::observed_freq(5, 100, 5, 200, 1000).is_err());
+        assert!(math::observed_freq(10, 100, 5, 200, 1000).is_err());
+        assert!(math::observed_freq(5, 200, 10, 100, 1000).is_err());
+    }
+
+    #[test]
+    fn test_asm_frac_size_invalid() {
+        // frac_size values the asm shifts can't handle are rejected before
+        // calling into asm
+        for f in [0, 64, 65, u32::MAX] {
+            assert!(
+                asm_math::freq_multiplier(1000, 1000, f).is_err(),
+                "asm freq_multiplier accepted frac_size={}",
+                f
+            );
+            assert!(
+                asm_math::calc_tsc_offset(1000, 0, 1000, 1000, f).is_err(),
+                "asm calc_tsc_offset accepted frac_size={}",
+                f
+            );
+            assert!(
+                asm_math::calc_guest_tsc(1000, 0, 1000, 1000, 2000, f).is_err(),
+                "asm calc_guest_tsc accepted frac_size={}",
+                f
+            );
+        }
+
+        // boundaries of the supported range still work
+        assert!(asm_math::freq_multiplier(1000, 1000, 1).is_ok());
+        assert!(asm_math::freq_multiplier(1000, 2000, 63).is_ok());
+    }
+
+    #[test]
+    fn test_asm_divide_invalid() {
+        use math::MathError;
+
+        // inputs the asm divide would fault on are rejected before calling
+        // into asm
+        assert_eq!(
+            asm_math::freq_multiplier(1000, 0, 32),
+            Err(MathError::InvalidFrequency {
+                name: "host frequency"
+            })
+        );
+        for t in FREQ_RATIO_TESTS_INVALID.iter() {
+            let msg = format!(
+                "guest_freq={}, host_freq={}, frac_size={}",
+                t.g, t.h, t.f
+            );
+            assert!(
+                matches!(
+                    asm_math::freq_multiplier(t.g, t.h, t.f),
+                    Err(MathError::RatioTooLarge { .. })
+                ),
+                "{}",
+                msg
+            );
+            assert!(
+                asm_math::migration_params(0, 0, t.g, t.h, t.f).is_err(),
+                "{}",
+                msg
+            );
+            assert!(
+                asm_math::calc_guest_tsc(0, 0, t.h, t.g, 0, t.f).is_err(),
+                "{}",
+                msg
+            );
+        }
+    }
+
+    #[test]
+    fn test_truncate_offset() {
+        // full width is a no-op
+        assert_eq!(
+            math::truncate_offset(-300000000000, 64).unwrap(),
+            -300000000000
+        );
+        assert_eq!(math::truncate_offset(i64::MIN, 64).unwrap(), i64::MIN);
+
+        // offsets that fit survive truncation, including their sign
+        assert_eq!(
+            math::truncate_offset(-300000000000, 48).unwrap(),
+            -300000000000
+        );
+        assert_eq!(
+            math::truncate_offset(300000000000, 48).unwrap(),
+            300000000000
+        );
+
+        // offsets that don't fit lose their upper bits
+        assert_eq!(
+            math::truncate_offset(-300000000000, 32).unwrap(),
+            0x269b4800
+        );
+        assert_eq!(math::truncate_offset(1 << 47, 48).unwrap(), -(1 << 47));
+
+        // there's no field narrower than a bit or wider than 64
+        assert!(matches!(
+            math::truncate_offset(1, 0),
+            Err(math::MathError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            math::truncate_offset(1, 65),
+            Err(math::MathError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_uptime_to_tsc() {
+        assert_eq!(math::uptime_to_tsc(0, 1_000_000_000).unwrap(), 0);
+        assert_eq!(
+            math::uptime_to_tsc(3600, 2_500_000_000).unwrap(),
+            9_000_000_000_000
+        );
+
+        // ~584 years at 1 GHz is the most a 64-bit TSC can hold
+        assert!(math::uptime_to_tsc(u64::MAX / 1_000_000_000, 1_000_000_000)
+            .is_ok());
+        assert!(math::uptime_to_tsc(
+            u64::MAX / 1_000_000_000 + 1,
+            1_000_000_000
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_ratio_is_power_of_two() {
+        // 0.25, 0.5, 1, 2, 4
+        assert_eq!(math::ratio_is_power_of_two(500, 2000), Some(-2));
+        assert_eq!(math::ratio_is_power_of_two(1000, 2000), Some(-1));
+        assert_eq!(math::ratio_is_power_of_two(1000, 1000), Some(0));
+        assert_eq!(math::ratio_is_power_of_two(2000, 1000), Some(1));
+        assert_eq!(math::ratio_is_power_of_two(4000, 1000), Some(2));
+        assert_eq!(
+            math::ratio_is_power_of_two(1_000_000_000, 4_000_000_000),
+            Some(-2)
+        );
+
+        // 2/3, 3, 1.5
+        assert_eq!(math::ratio_is_power_of_two(2000, 3000), None);
+        assert_eq!(math::ratio_is_power_of_two(3000, 1000), None);
+        assert_eq!(math::ratio_is_power_of_two(3000, 2000), None);
+    }
+
+    #[test]
+    fn test_common_exact_freq() {
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        let exact = |g: u64, h: u64| {
+            ((g as u128) << FRAC_SIZE_AMD).is_multiple_of(h as u128)
+        };
+
+        // a target that's already exact for every host is returned as is
+        let hosts = [1_000_000_000, 2_000_000_000, 4_000_000_000];
+        let g = math::common_exact_freq(
+            1_000_000_000,
+            &hosts,
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD,
+            0,
+        );
+        assert_eq!(g.unwrap(), Some(1_000_000_000));
+
+        // 3 GHz (2^9 * 3 * 5^9) needs guest multiples of 3 * 5^9 = 5859375,
+        // which covers 2 GHz's 5^9; the nearest one to 2.4 GHz is 2402343750
+        let hosts = [2_000_000_000, 3_000_000_000];
+        let g = math::common_exact_freq(
+            2_400_000_000,
+            &hosts,
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD,
+            10_000_000,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(g, 2_402_343_750);
+        assert!(hosts.iter().all(|&h| exact(g, h)));
+
+        // no multiple of the step within the window
+        let g = math::common_exact_freq(
+            2_400_000_000,
+            &hosts,
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD,
+            1000,
+        );
+        assert_eq!(g.unwrap(), None);
+
+        // zero host frequency is an error
+        assert!(math::common_exact_freq(
+            1000,
+            &[0],
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD,
+            0
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_guest_tsc_batch_stats_deterministic() {
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        // 2/3 ratio, so the scaling actually truncates
+        let (ihtsc, igtsc, hf, gf) = (300000000000, 5, 3000000000, 2000000000);
+        let chtsc = 305000000000;
+
+        let scalar = math::guest_tsc(
+            ihtsc,
+            igtsc,
+            hf,
+            gf,
+            chtsc,
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD,
+        )
+        .unwrap();
+
+        // the same host TSC repeatedly yields the same guest TSC every time
+        let stats = math::guest_tsc_batch_stats(
+            ihtsc,
+            igtsc,
+            hf,
+            gf,
+            std::iter::repeat_n(chtsc, 1000),
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD,
+        )
+        .unwrap();
+        assert_eq!(stats.count, 1000);
+        assert_eq!(stats.errors, 0);
+        assert_eq!(stats.min, Some(scalar));
+        assert_eq!(stats.max, Some(scalar));
+        assert_eq!(stats.mean, Some(scalar));
+
+        // a single-element batch matches the scalar computation
+        let stats = math::guest_tsc_batch_stats(
+            ihtsc,
+            igtsc,
+            hf,
+            gf,
+            [chtsc],
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD,
+        )
+        .unwrap();
+        assert_eq!(stats.count, 1);
+        assert_eq!(stats.min, Some(scalar));
+    }
+
+    #[test]
+    fn test_resume_offset() {
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        // 1.5 GHz guest on a 1 GHz host, paused for 60 seconds
+        let (ihtsc, hf, gf) = (300000000000, 1000000000, 1500000000);
+        let (pause, resume) = (305000000000, 365000000000);
+
+        let paused = math::guest_tsc(
+            ihtsc,
+            0,
+            hf,
+            gf,
+            pause,
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD,
+        )
+        .unwrap();
+        let offset = math::resume_offset(
+            ihtsc,
+            0,
+            hf,
+            gf,
+            pause,
+            resume,
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD,
+        )
+        .unwrap();
+
+        // the resumed guest picks up where it was paused
+        let m =
+            math::freq_multiplier(gf, hf, FRAC_SIZE_AMD, INT_SIZE_AMD).unwrap();
+        let scaled = math::scale_tsc(resume, m, FRAC_SIZE_AMD).unwrap();
+        assert_eq!(scaled as i64 + offset, paused as i64);
+
+        // and the offset moved back by the 60 seconds of guest ticks
+        let old_offset =
+            math::tsc_offset(ihtsc, 0, gf, hf, FRAC_SIZE_AMD, INT_SIZE_AMD)
+                .unwrap();
+        assert_eq!(offset - old_offset, -60 * 1500000000);
+    }
+
+    #[test]
+    fn test_guest_tsc_ceiling() {
+        use math::CeilingMode;
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        assert_eq!(math::tsc_width_ceiling(64).unwrap(), u64::MAX);
+        assert_eq!(math::tsc_width_ceiling(48).unwrap(), (1 << 48) - 1);
+        assert_eq!(math::tsc_width_ceiling(1).unwrap(), 1);
+        for width in [0, 65] {
+            assert!(matches!(
+                math::tsc_width_ceiling(width),
+                Err(math::MathError::InvalidInput(_))
+            ));
+        }
+
+        let ceiling = math::tsc_width_ceiling(8).unwrap();
+        let calc = |cur_host_tsc, mode| {
+            math::guest_tsc_ceiling(
+                0,
+                0,
+                1000,
+                1000,
+                cur_host_tsc,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD,
+                ceiling,
+                mode,
+            )
+        };
+
+        // values within an 8-bit TSC are unaffected by either mode
+        assert_eq!(calc(200, CeilingMode::Error).unwrap(), 200);
+        assert_eq!(calc(255, CeilingMode::Saturate).unwrap(), 255);
+
+        // values beyond it are rejected or clamped
+        assert!(calc(256, CeilingMode::Error).is_err());
+        assert_eq!(calc(256, CeilingMode::Saturate).unwrap(), 255);
+        assert_eq!(calc(100000, CeilingMode::Saturate).unwrap(), 255);
+    }
+
+    #[test]
+    fn test_hrtime_guest_tsc_round_trip() {
+        use math::Rounding;
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        const NS_PER_SEC: u64 = math::NS_PER_SEC as u64;
+
+        // wall-clock duration -> host ticks -> guest ticks -> duration
+        let round_trip = |ns: u64, host_hz: u64, guest_hz: u64| {
+            let host_tsc = math::tsc(ns, host_hz, Rounding::Truncate).unwrap();
+            let guest_tsc = math::guest_tsc(
+                0,
+                0,
+                host_hz,
+                guest_hz,
+                host_tsc,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD,
+            )
+            .unwrap();
+            math::hrtime(guest_tsc, guest_hz, Rounding::Truncate).unwrap()
+        };
+
+        // including fractional seconds, which are whole ticks at each of
+        // these frequencies
+        for (secs, frac_ns) in [
+            (0, 0),
+            (1, 0),
+            (1, 500_000_000),
+            (60, 250_000_000),
+            (3600, 0),
+            (86400, 750_000_000),
+            (365 * 86400, 0),
+        ] {
+            let ns = secs * NS_PER_SEC + frac_ns;
+
+            // equal frequencies round-trip exactly
+            for hz in [1_000_000_000, 2_500_000_000, 3_000_000_000] {
+                assert_eq!(
+                    round_trip(ns, hz, hz),
+                    ns,
+                    "{} ns at {} Hz",
+                    ns,
+                    hz
+                );
+            }
+
+            // so do ratios the multiplier represents exactly
+            for (host_hz, guest_hz) in [
+                (2_000_000_000, 1_000_000_000),
+                (1_000_000_000, 1_500_000_000),
+                (2_000_000_000, 2_500_000_000),
+            ] {
+                assert_eq!(
+                    round_trip(ns, host_hz, guest_hz),
+                    ns,
+                    "{} ns, host {} Hz, guest {} Hz",
+                    ns,
+                    host_hz,
+                    guest_hz
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_offset_raw() {
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        // booting a guest on a host that's been up for 5 minutes gives a
+        // negative offset, with the sign bit set in the raw field
+        let offset = math::tsc_offset(
+            300000000000,
+            0,
+            1000000000,
+            1000000000,
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD,
+        )
+        .unwrap();
+        assert_eq!(offset, -300000000000);
+        let raw = math::offset_to_raw(offset);
+        assert_eq!(raw, 0xffffffba269b4800);
+        assert_eq!(math::offset_from_raw(raw), offset);
+
+        // a guest that's been up longer than the host has a positive offset
+        let offset = math::tsc_offset(
+            1000,
+            300000000000,
+            1000000000,
+            1000000000,
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD,
+        )
+        .unwrap();
+        assert_eq!(offset, 299999999000);
+        let raw = math::offset_to_raw(offset);
+        assert_eq!(raw >> 63, 0);
+        assert_eq!(math::offset_from_raw(raw), offset);
+
+        // extremes
+        for o in [i64::MIN, -1, 0, 1, i64::MAX] {
+            assert_eq!(math::offset_from_raw(math::offset_to_raw(o)), o);
+        }
+        assert_eq!(math::offset_to_raw(-1), u64::MAX);
+        assert_eq!(math::offset_to_raw(i64::MIN), 1 << 63);
+    }
+
+    #[test]
+    fn test_parse_hosts_migrate_at_tsc() {
+        let hosts = input::parse_hosts(
+            1000000000,
+            1000000000,
+            vec![],
+            vec![
+                // 2.5s after boot, rounded down to a whole second
+                "3500000000 9000000000 2000000000".to_string(),
+                // 3s into running on the 2 GHz host
+                "15000000000 1 1000000000".to_string(),
+            ],
+            20,
+        )
+        .unwrap();
+        let starts: Vec<usize> = hosts.iter().map(|h| h.start).collect();
+        assert_eq!(starts, vec![0, 2, 5]);
+        assert_eq!(hosts[2].host_tsc, 1);
+
+        // before the source host started running the guest
+        assert!(input::parse_hosts(
+            1000000000,
+            1000000000,
+            vec![],
+            vec!["500000000 1 1000000000".to_string()],
+            20,
+        )
+        .is_err());
+
+        // past the end of the simulation
+        assert!(input::parse_hosts(
+            1000000000,
+            1000000000,
+            vec![],
+            vec!["22000000000 1 1000000000".to_string()],
+            20,
+        )
+        .is_err());
+
+        // fields are parsed as for --migrate
+        let hosts = input::parse_hosts(
+            1000000000,
+            1000000000,
+            vec![],
+            vec!["  0xd09dc300\t1   0x3b9aca00 ".to_string()],
+            20,
+        )
+        .unwrap();
+        assert_eq!((hosts[1].start, hosts[1].host_freq), (2, 1000000000));
+
+        for bad in [
+            "3500000000 1",
+            "3500000000 1 1000000000 5",
+            "3500000000 1 0",
+            "3500000000 1 fast",
+        ] {
+            assert!(
+                input::parse_hosts(
+                    1000000000,
+                    1000000000,
+                    vec![],
+                    vec![bad.to_string()],
+                    20,
+                )
+                .is_err(),
+                "\"{}\" parsed",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_duration() {
+        let max = (u64::MAX / math::NS_PER_SEC as u64) as usize;
+        assert!(input::check_duration(max).is_ok());
+        assert!(input::check_duration(max + 1).is_err());
+
+        assert_eq!(
+            crate::steps_ns(&(2..=max)).unwrap(),
+            (2_000_000_000, max as u64 * math::NS_PER_SEC as u64)
+        );
+        assert!(crate::steps_ns(&(2..=max + 1)).is_err());
+    }
+
+    #[test]
+    fn test_min_int_size() {
+        assert_eq!(math::min_int_size(1, 1000).unwrap(), 0);
+        assert_eq!(math::min_int_size(1000, 1000).unwrap(), 1);
+        assert_eq!(math::min_int_size(255_000, 1000).unwrap(), 8);
+        assert_eq!(math::min_int_size(256_000, 1000).unwrap(), 9);
+        assert_eq!(math::min_int_size(300_000, 1000).unwrap(), 9);
+        assert_eq!(math::min_int_size(u64::MAX, 1).unwrap(), 64);
+        assert!(matches!(
+            math::min_int_size(1, 0),
+            Err(math::MathError::InvalidFrequency { .. })
+        ));
+
+        // the suggested size is enough to represent the ratio
+        assert!(math::freq_multiplier(300_000, 1000, 32, 8).is_err());
+        assert!(math::freq_multiplier(300_000, 1000, 32, 9).is_ok());
+
+        // and the error explains that
+        let e = math::freq_multiplier(300_000, 1000, 32, 8).unwrap_err();
+        assert!(
+            e.to_string()
+                .contains("ratio 300 needs at least 9 integer bits"),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn test_dry_run_validate() {
+        use clap::Parser;
+
+        let validate = |args: &[&str]| {
+            let opt = crate::Opt::try_parse_from(
+                ["tsc-simulator", "--dry-run"].iter().chain(args.iter()),
+            )
+            .unwrap();
+            crate::validate(&opt.cmd)
+        };
+
+        assert!(validate(&["simulate"]).is_ok());
+        assert!(validate(&["simulate", "--migrate", "10 1 3000000000"]).is_ok());
+        assert!(validate(&["calc", "guest-tsc", "-i", "0", "5"]).is_ok());
+        assert!(validate(&["selftest"]).is_ok());
+
+        // zero frequencies, which a flag rejects as it's parsed
+        assert!(crate::Opt::try_parse_from([
+            "tsc-simulator",
+            "--dry-run",
+            "calc",
+            "freq",
+            "-g",
+            "0",
+            "-f",
+            "1"
+        ])
+        .is_err());
+        assert!(validate(&["simulate", "--migrate", "10 1 0"]).is_err());
+
+        // unrepresentable ratio, or format
+        assert!(validate(&["simulate", "-g", "300000000000"]).is_err());
+        assert!(validate(&[
+            "calc",
+            "offset",
+            "0",
+            "--int-size",
+            "16",
+            "--frac-size",
+            "64"
+        ])
+        .is_err());
+
+        // a guest TSC that overflows later in the simulation
+        assert!(validate(&[
+            "simulate",
+            "--initial-guest-tsc",
+            "18446744073000000000",
+        ])
+        .is_err());
+
+        // a format the command itself would reject
+        assert!(validate(&[
+            "calc",
+            "host-for-mult",
+            "1",
+            "--frac-size",
+            "200"
+        ])
+        .is_err());
+        assert!(validate(&["simulate", "-d", "18446744074"]).is_err());
+
+        // a run that's only checked, not computed
+        assert!(validate(&["calc", "max-drift", "-d", "18446744073"]).is_ok());
+        assert!(validate(&["calc", "max-drift", "-d", "18446744074"]).is_err());
+    }
+
+    #[test]
+    fn test_offset_error() {
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        // exact ratios have no error
+        let e = math::offset_error(
+            300000000000,
+            1500000000,
+            1000000000,
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD,
+        );
+        assert_eq!(e.unwrap(), 0.0);
+
+        // 1/3 is truncated: 2^32 / 3 = 1431655765.33, so each host tick loses
+        // 1/3 / 2^32 guest ticks
+        let err = |ihtsc| {
+            math::offset_error(
+                ihtsc,
+                1000000000,
+                3000000000,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD,
+            )
+            .unwrap()
+        };
+        assert!(err(0) == 0.0);
+        assert!(err(3 << 32) > 0.9 && err(3 << 32) < 1.1, "{}", err(3 << 32));
+
+        // and the error grows with the host TSC
+        let day = 86400 * 3000000000;
+        assert!(err(day) < err(365 * day));
+        assert!((err(365 * day) / err(day) - 365.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_guest_elapsed() {
+        use std::time::Duration;
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        let elapsed = |host: Duration, guest_hz, host_hz| {
+            math::guest_elapsed(
+                host,
+                guest_hz,
+                host_hz,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD,
+            )
+            .unwrap()
+        };
+
+        // a 2.0 ratio doubles the time that elapses
+        assert_eq!(
+            elapsed(Duration::from_secs(1), 2000000000, 1000000000),
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            elapsed(Duration::from_millis(1500), 1500000000, 3000000000),
+            Duration::from_millis(750)
+        );
+
+        // a truncated ratio loses a little time, but not much
+        let d = elapsed(Duration::from_secs(3600), 1000000000, 3000000000);
+        assert!(d < Duration::from_secs(1200));
+        assert!(Duration::from_secs(1200) - d < Duration::from_micros(1));
+
+        assert_eq!(
+            math::ticks_to_duration(2500000001, 1000000000).unwrap(),
+            Duration::new(2, 500000001)
+        );
+        assert!(matches!(
+            math::ticks_to_duration(1, 0),
+            Err(math::MathError::InvalidFrequency { .. })
+        ));
+        assert_eq!(
+            math::duration_to_ticks(Duration::new(2, 500000001), 2000000000)
+                .unwrap(),
+            5000000002
+        );
+        assert!(math::duration_to_ticks(Duration::MAX, 1000000000).is_err());
+        assert!(matches!(
+            math::duration_to_ticks(Duration::MAX, 20000000000),
+            Err(math::MathError::Overflow(_))
+        ));
+    }
+
+    #[test]
+    fn test_host_tsc_from_guest() {
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        // 1.5 GHz guest booted on a 1 GHz host 5 minutes into its uptime
+        let (ihtsc, hf, gf) = (300000000000, 1000000000, 1500000000);
+        let h = math::host_tsc_from_guest(
+            ihtsc,
+            0,
+            hf,
+            gf,
+            15000000000,
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD,
+        );
+        assert_eq!(h.unwrap(), ihtsc + 10000000000);
+
+        // with a truncated 2/3 ratio, the earliest host tick is returned
+        let (ihtsc, hf, gf) = (0, 3000000000, 2000000000);
+        let h = math::host_tsc_from_guest(
+            ihtsc,
+            0,
+            hf,
+            gf,
+            2000000000,
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD,
+        )
+        .unwrap();
+        let g = |h| {
+            math::guest_tsc(ihtsc, 0, hf, gf, h, FRAC_SIZE_AMD, INT_SIZE_AMD)
+                .unwrap()
+        };
+        assert!(g(h) >= 2000000000);
+        assert!(g(h - 1) < 2000000000);
+
+        // a guest TSC before the guest's TSC at host TSC 0
+        assert!(math::host_tsc_from_guest(
+            0,
+            1000,
+            hf,
+            gf,
+            999,
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD
+        )
+        .is_err());
+
+        // a guest TSC only reached at a host TSC past u64::MAX: at half the
+        // host frequency, the guest reaches 2^63 when the host wraps
+        let (hf, gf) = (2000000000, 1000000000);
+        let h = |target| {
+            math::host_tsc_from_guest(
+                0,
+                0,
+                hf,
+                gf,
+                target,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD,
+            )
+        };
+        assert_eq!(h((1 << 63) - 1).unwrap(), u64::MAX - 1);
+        assert!(h(1 << 63).is_err());
+        assert!(h(u64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_drift_bound_ppm() {
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        let bound = |g, h| {
+            math::drift_bound_ppm(g, h, FRAC_SIZE_AMD, INT_SIZE_AMD).unwrap()
+        };
+
+        // exact ratios don't drift
+        assert_eq!(bound(1000000000, 1000000000), 0.0);
+        assert_eq!(bound(1500000000, 1000000000), 0.0);
+
+        // 1/3 drops a third of a unit in the last place of 2^32 / 3
+        let b = bound(1000000000, 3000000000);
+        let expected = (1.0 / 3.0) / (4294967296.0 / 3.0) * 1_000_000.0;
+        assert!((b - expected).abs() < 1e-12, "{} != {}", b, expected);
+
+        // and that's how far a guest falls behind over an hour
+        let d = math::guest_elapsed(
+            std::time::Duration::from_secs(3600),
+            1000000000,
+            3000000000,
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD,
+        )
+        .unwrap();
+        let behind_ppm = (1200e9 - d.as_nanos() as f64) / 1200e9 * 1e6;
+        assert!(behind_ppm <= b + 1e-6);
+    }
+
+    #[test]
+    fn test_migration_params() {
+        use tsc_simulator::{
+            FRAC_SIZE_AMD, FRAC_SIZE_INTEL, INT_SIZE_AMD, INT_SIZE_INTEL,
+        };
+
+        for (frac, int) in [
+            (FRAC_SIZE_AMD, INT_SIZE_AMD),
+            (FRAC_SIZE_INTEL, INT_SIZE_INTEL),
+        ] {
+            for (htsc, gtsc, gf, hf) in [
+                (300000000000, 0, 1000000000, 1000000000),
+                (300000000000, 0, 2000000000, 3000000000),
+                (1000, 300000000000, 2500000000, 1000000000),
+                (86400 * 3000000000, 17, 1000000000, 3000000000),
+            ] {
+                let rs = math::migration_params(htsc, gtsc, gf, hf, frac, int)
+                    .unwrap();
+                let asm = asm_math::migration_params(htsc, gtsc, gf, hf, frac)
+                    .unwrap();
+                assert_eq!(
+                    rs, asm,
+                    "host_tsc={}, guest_tsc={}, guest_hz={}, host_hz={}, \
+                     frac_size={}",
+                    htsc, gtsc, gf, hf, frac
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_migration_tick_error() {
+        use math::HostParams;
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        let err = |src_hz, dst_hz, guest_hz, dst_tsc| {
+            math::migration_tick_error(
+                HostParams {
+                    host_tsc: 300000000000,
+                    host_hz: src_hz,
+                },
+                HostParams {
+                    host_tsc: dst_tsc,
+                    host_hz: dst_hz,
+                },
+                guest_hz,
+                5000000000,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD,
+            )
+            .unwrap()
+        };
+
+        // exactly representable ratios on both sides, wherever the
+        // destination host's TSC is
+        for dst_tsc in [0, 7000000000, 7000000001, 7000000002] {
+            assert_eq!(err(1000000000, 2000000000, 1000000000, dst_tsc), 0);
+            assert_eq!(err(1000000000, 1000000000, 1500000000, dst_tsc), 0);
+            assert_eq!(err(2000000000, 4000000000, 2500000000, dst_tsc), 0);
+        }
+
+        // 1 GHz -> 3 GHz: the destination's 1/3 multiplier truncates, so
+        // depending on where its scaling lands the guest may get a tick less
+        // in the second after the hop
+        assert_eq!(err(1000000000, 3000000000, 1000000000, 7000000000), 0);
+        assert_eq!(err(1000000000, 3000000000, 1000000000, 7000000001), -1);
+    }
+
+    #[test]
+    fn test_uptime_error_ns() {
+        use tsc_simulator::{
+            FRAC_SIZE_AMD, FRAC_SIZE_INTEL, INT_SIZE_AMD, INT_SIZE_INTEL,
+        };
+
+        // exact ratios don't drift
+        assert_eq!(
+            math::uptime_error_ns(
+                86400,
+                1000000000,
+                2000000000,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD
+            )
+            .unwrap(),
+            0
+        );
+
+        // 1/3 loses 20117 guest ticks over a day: ~20us at 1 GHz
+        assert_eq!(
+            math::uptime_error_ns(
+                86400,
+                1000000000,
+                3000000000,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD
+            )
+            .unwrap(),
+            -20117
+        );
+
+        // the same ticks are fewer nanoseconds for a faster guest
+        assert_eq!(
+            math::uptime_error_ns(
+                86400,
+                2500000000,
+                3000000000,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD
+            )
+            .unwrap(),
+            -8046
+        );
+
+        // Intel's extra fractional bits bring it down to a single tick
+        assert_eq!(
+            math::uptime_error_ns(
+                86400,
+                1000000000,
+                3000000000,
+                FRAC_SIZE_INTEL,
+                INT_SIZE_INTEL
+            )
+            .unwrap(),
+            -1
+        );
+
+        // with a single fractional bit, the ticks lost over a long span don't
+        // fit in nanoseconds
+        assert!(matches!(
+            math::uptime_error_ns(u64::MAX, u64::MAX / 3 * 2, u64::MAX, 1, 63),
+            Err(math::MathError::Overflow(_))
+        ));
+    }
+
+    #[test]
+    fn test_host_steps_boundary() {
+        use crate::{host_steps, Boundary};
+        use input::parse_hosts;
+
+        let duration = 15;
+        let hosts = parse_hosts(
+            1000000000,
+            1000000000,
+            vec![
+                "5 300000000000 2000000000".to_string(),
+                "10 100000000000 1500000000".to_string(),
+            ],
+            vec![],
+            duration,
+        )
+        .unwrap();
+
+        // the times shown across the whole run, in order
+        let shown = |boundary| {
+            let mut times = vec![];
+            for h in 0..hosts.len() {
+                let (steps, show_last) =
+                    host_steps(&hosts, h, duration, boundary);
+                let end = *steps.end();
+                times.extend(steps.filter(|t| *t != end || show_last));
+            }
+            times
+        };
+
+        // each time is shown exactly once
+        assert_eq!(
+            shown(Boundary::Exclusive),
+            (0..=duration).collect::<Vec<_>>()
+        );
+
+        // inclusive boundaries show each migration time twice
+        let times = shown(Boundary::Inclusive);
+        assert_eq!(times.len(), duration + 1 + 2);
+        assert_eq!(times.iter().filter(|t| **t == 5).count(), 2);
+        assert_eq!(times.iter().filter(|t| **t == 10).count(), 2);
+    }
+
+    #[test]
+    fn test_cold_migration_offset() {
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        let offset = |dest_host_tsc, guest_tsc, host_hz| {
+            math::cold_migration_offset(
+                dest_host_tsc,
+                guest_tsc,
+                1000000000,
+                host_hz,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD,
+            )
+        };
+
+        // a guest up for a year lands on a host up for 10 seconds
+        let year: u64 = 365 * 86400 * 1000000000;
+        let expected = (year - 10000000000) as i64;
+        assert_eq!(offset(10000000000, year, 1000000000).unwrap(), expected);
+        assert_eq!(offset(20000000000, year, 2000000000).unwrap(), expected);
+
+        // the largest offset is i64::MAX; past that it's an error, not a wrap
+        assert_eq!(offset(0, i64::MAX as u64, 1000000000).unwrap(), i64::MAX);
+        assert!(offset(0, i64::MAX as u64 + 1, 1000000000).is_err());
+        assert!(offset(0, u64::MAX, 1000000000).is_err());
+        assert_eq!(offset(1 << 63, u64::MAX, 1000000000).unwrap(), i64::MAX);
+
+        // a destination host ahead of the guest is a warm migration
+        assert!(offset(year + 1, year, 1000000000).is_err());
+        assert_eq!(offset(year, year, 1000000000).unwrap(), 0);
+    }
+
+    #[cfg(feature = "rational")]
+    #[test]
+    fn test_freq_multiplier_ratio() {
+        use tsc_simulator::{
+            FRAC_SIZE_AMD, FRAC_SIZE_INTEL, INT_SIZE_AMD, INT_SIZE_INTEL,
+        };
+
+        for t in FREQ_RATIO_TESTS_VALID.iter() {
+            let r = math::multiplier_as_ratio(t.v, t.f);
+            assert_eq!(r * (1u128 << t.f), (t.v as u128).into());
+        }
+
+        for (g, h, f, i) in [
+            (1000000000, 3000000000, FRAC_SIZE_AMD, INT_SIZE_AMD),
+            (2500000000, 3000000000, FRAC_SIZE_AMD, INT_SIZE_AMD),
+            (3000000000, 2000000000, FRAC_SIZE_INTEL, INT_SIZE_INTEL),
+            (2000000000, 3000000000, FRAC_SIZE_INTEL, INT_SIZE_INTEL),
+        ] {
+            let m = math::freq_multiplier(g, h, f, i).unwrap();
+            let r = math::freq_multiplier_ratio(g, h, f, i).unwrap();
+
+            // the ratio times the host frequency rounds (down, like scaling)
+            // to the guest frequency the multiplier realizes
+            let realized = (m as u128 * h as u128) >> f;
+            assert_eq!((r * h as u128).to_integer(), realized);
+            assert!(realized <= g as u128);
+        }
+    }
+
+    #[test]
+    fn test_guest_tsc_at_elapsed_ns() {
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        // partial ticks are truncated
+        assert_eq!(math::tsc_incr_ns(100, 1, 1000000000).unwrap(), 101);
+        assert_eq!(math::tsc_incr_ns(100, 1, 2500000000).unwrap(), 102);
+        assert_eq!(math::tsc_incr_ns(100, 1, 999999999).unwrap(), 100);
+        assert!(math::tsc_incr_ns(u64::MAX, 1, 1000000000).is_err());
+
+        // a 2 GHz guest booted on a 3 GHz host at 1s of host uptime
+        let (m, offset) = math::migration_params(
+            3000000000,
+            0,
+            2000000000,
+            3000000000,
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD,
+        )
+        .unwrap();
+        let at = |ns| {
+            math::guest_tsc_at_elapsed_ns(
+                3000000000,
+                3000000000,
+                m,
+                offset,
+                ns,
+                FRAC_SIZE_AMD,
+            )
+            .unwrap()
+        };
+
+        // matches guest_tsc at the same host TSC
+        for ns in [0, 1, 500, 999999999, 1000000000, 3600000000000] {
+            let host_tsc = math::tsc_incr_ns(3000000000, ns, 3000000000);
+            let expected = math::guest_tsc(
+                3000000000,
+                0,
+                3000000000,
+                2000000000,
+                host_tsc.unwrap(),
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD,
+            );
+            assert_eq!(at(ns), expected.unwrap(), "elapsed_ns={}", ns);
+        }
+        assert_eq!(at(0), 0);
+    }
+
+    #[test]
+    fn test_arch_format() {
+        use input::{arch_format, Arch};
+
+        // the arch's own format by default
+        assert_eq!(arch_format(Arch::Amd, None, None).unwrap(), (8, 32));
+        assert_eq!(arch_format(Arch::Intel, None, None).unwrap(), (16, 48));
+
+        // either part can be overridden independently
+        assert_eq!(arch_format(Arch::Amd, None, Some(40)).unwrap(), (8, 40));
+        assert_eq!(arch_format(Arch::Amd, Some(4), None).unwrap(), (4, 32));
+        assert_eq!(
+            arch_format(Arch::Intel, Some(8), Some(56)).unwrap(),
+            (8, 56)
+        );
+
+        // but the result still has to fit in the 64-bit multiplier
+        assert!(arch_format(Arch::Intel, None, Some(56)).is_err());
+        assert!(arch_format(Arch::Amd, Some(33), None).is_err());
+        assert!(arch_format(Arch::Amd, None, Some(0)).is_err());
+        assert!(arch_format(Arch::Amd, Some(0), None).is_err());
+
+        // any split that fits can be used, like 4.28 or 32.32
+        assert_eq!(arch_format(Arch::Amd, Some(4), Some(28)).unwrap(), (4, 28));
+        assert_eq!(
+            arch_format(Arch::Intel, Some(32), Some(32)).unwrap(),
+            (32, 32)
+        );
+    }
+
+    #[test]
+    fn test_check_ratios() {
+        use input::{check_ratios, HostDef};
+
+        let host = |host_freq, guest_freq| HostDef {
+            start: 0,
+            host_tsc: 0,
+            host_freq,
+            guest_freq,
+            tsc_event: false,
+        };
+
+        // a 4.28 multiplier can't hold a guest 16 times faster than its host
+        let hosts = [host(1_000_000_000, None)];
+        assert!(check_ratios(15_000_000_000, &hosts, 4, 28).is_ok());
+        let e = check_ratios(16_000_000_000, &hosts, 4, 28).unwrap_err();
+        assert!(e.to_string().contains("4.28"), "{}", e);
+
+        // a retune changes the ratio for the hosts after it
+        let hosts = [
+            host(1_000_000_000, None),
+            host(1_000_000_000, Some(1 << 40)),
+        ];
+        assert!(check_ratios(1_000_000_000, &hosts, 8, 32).is_err());
+    }
+
+    #[test]
+    fn test_alias_range() {
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        // at 1 GHz, an AMD multiplier LSB is ~0.23 Hz, so every Hz is distinct
+        assert_eq!(
+            math::alias_range(
+                1000000000,
+                1000000000,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD
+            )
+            .unwrap(),
+            (1000000000, 1000000000)
+        );
+
+        // with 8 fractional bits an LSB is ~3.9 MHz
+        assert_eq!(
+            math::alias_range(1000000000, 1000000000, 8, 56).unwrap(),
+            (1000000000, 1003906249)
+        );
+        assert_eq!(
+            math::alias_range(1003906249, 1000000000, 8, 56).unwrap(),
+            (1000000000, 1003906249)
+        );
+        assert_eq!(
+            math::alias_range(1003906250, 1000000000, 8, 56).unwrap(),
+            (1003906250, 1007812499)
+        );
+
+        // the ends of the range have the same multiplier, and just outside
+        // them it differs
+        let (low, high) =
+            math::alias_range(2500000000, 3000000000, 20, 44).unwrap();
+        let m = |g| math::freq_multiplier(g, 3000000000, 20, 44).unwrap();
+        assert_eq!(m(low), m(2500000000));
+        assert_eq!(m(high), m(2500000000));
+        assert_ne!(m(low - 1), m(2500000000));
+        assert_ne!(m(high + 1), m(2500000000));
+    }
+
+    #[test]
+    fn test_guest_tsc_context() {
+        use tsc_simulator::{
+            FRAC_SIZE_AMD, FRAC_SIZE_INTEL, INT_SIZE_AMD, INT_SIZE_INTEL,
+        };
+
+        for (ihtsc, igtsc, h, g, f, i) in [
+            (
+                1000000000,
+                0,
+                1000000000,
+                1000000000,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD,
+            ),
+            (
+                300000000000,
+                5000000000,
+                2000000000,
+                1000000000,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD,
+            ),
+            (
+                3000000000,
+                7,
+                3000000000,
+                2000000000,
+                FRAC_SIZE_INTEL,
+                INT_SIZE_INTEL,
+            ),
+            (
+                1 << 40,
+                1 << 50,
+                1500000000,
+                2500000000,
+                FRAC_SIZE_INTEL,
+                INT_SIZE_INTEL,
+            ),
+        ] {
+            let ctx =
+                math::GuestTscContext::new(ihtsc, igtsc, h, g, f, i).unwrap();
+
+            // including host TSCs where the guest TSC under- or overflows
+            for x in [
+                0,
+                1,
+                ihtsc - 1,
+                ihtsc,
+                ihtsc + 1,
+                ihtsc + h,
+                1 << 62,
+                u64::MAX,
+            ] {
+                let expected = math::guest_tsc(ihtsc, igtsc, h, g, x, f, i);
+                assert_eq!(
+                    ctx.at(x).ok(),
+                    expected.ok(),
+                    "initial_host_tsc={}, host_tsc={}",
+                    ihtsc,
+                    x
+                );
+            }
+        }
+
+        // the context fails to build where guest_tsc would always fail
+        assert!(math::GuestTscContext::new(0, 0, 1, 1 << 32, 32, 8).is_err());
+    }
+
+    #[test]
+    fn test_host_hz_for_multiplier() {
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        // 0.5 is exact for a 1 GHz guest on a 2 GHz host
+        assert_eq!(
+            math::host_hz_for_multiplier(1000000000, 1 << 31, FRAC_SIZE_AMD)
+                .unwrap(),
+            2000000000
+        );
+
+        // 2/3 is not exact, but 3 GHz realizes its truncated multiplier
+        for (g, m) in [(2000000000, 0xaaaa_aaaa), (1000000000, 0x5555_5555)] {
+            let h = math::host_hz_for_multiplier(g, m, FRAC_SIZE_AMD).unwrap();
+            assert_eq!(h, 3000000000);
+            assert_eq!(
+                math::freq_multiplier(g, h, FRAC_SIZE_AMD, INT_SIZE_AMD)
+                    .unwrap(),
+                m
+            );
+        }
+
+        assert!(math::host_hz_for_multiplier(1000000000, 0, 32).is_err());
+        assert!(math::host_hz_for_multiplier(u64::MAX, 1, 32).is_err());
+
+        // a multiplier has at most 63 fractional bits
+        for frac_size in [64, 127, 200] {
+            assert!(matches!(
+                math::host_hz_for_multiplier(1, 1, frac_size),
+                Err(math::MathError::InvalidInput(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_retune_continuity() {
+        use input::{add_retunes, parse_hosts};
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        let duration = 20;
+        let mut hosts = parse_hosts(
+            1000000000,
+            1000000000,
+            vec!["10 100000000000 2000000000".to_string()],
+            vec![],
+            duration,
+        )
+        .unwrap();
+        // retune halfway through a second on each host
+        add_retunes(
+            &mut hosts,
+            vec![
+                "5500000000 2500000000".to_string(),
+                "109000000000 333333333".to_string(),
+            ],
+            duration,
+        )
+        .unwrap();
+
+        let starts: Vec<_> = hosts
+            .iter()
+            .map(|h| (h.start, h.host_tsc, h.guest_freq))
+            .collect();
+        assert_eq!(
+            starts,
+            vec![
+                (0, 1000000000, None),
+                (4, 5000000000, Some(2500000000)),
+                (10, 100000000000, None),
+                (14, 108000000000, Some(333333333)),
+            ]
+        );
+
+        let mut guest_hz = 1000000000;
+        let mut guest_tsc = 0;
+        for (i, h) in hosts.iter().enumerate() {
+            guest_hz = h.guest_freq.unwrap_or(guest_hz);
+            let ctx = math::GuestTscContext::new(
+                h.host_tsc,
+                guest_tsc,
+                h.host_freq,
+                guest_hz,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD,
+            )
+            .unwrap();
+
+            // the guest TSC picks up where the previous segment left off
+            assert_eq!(ctx.at(h.host_tsc).unwrap(), guest_tsc);
+
+            // and each second afterward advances at the new frequency, to
+            // within a tick of truncation
+            let step = ctx.at(h.host_tsc + h.host_freq).unwrap() - guest_tsc;
+            assert!(step.abs_diff(guest_hz) <= 1, "step {} on {}", step, i);
+
+            let end = hosts.get(i + 1).map_or(duration, |n| n.start);
+            let end_host_tsc =
+                h.host_tsc + (end - h.start) as u64 * h.host_freq;
+            guest_tsc = ctx.at(end_host_tsc).unwrap();
+        }
+
+        // a host TSC the guest never sees can't be retuned at
+        let mut hosts =
+            parse_hosts(1000000000, 1000000000, vec![], vec![], duration)
+                .unwrap();
+        assert!(add_retunes(
+            &mut hosts,
+            vec!["500000000 2000000000".to_string()],
+            duration
+        )
+        .is_err());
+
+        // fields are parsed as for --migrate, and there must be two
+        assert!(add_retunes(
+            &mut hosts,
+            vec!["\t0x77359400   2GHz ".to_string()],
+            duration
+        )
+        .is_ok());
+        for bad in [
+            "2000000000",
+            "2000000000 2000000000 5",
+            "2000000000 0",
+            "2000000000 fast",
+        ] {
+            let mut hosts =
+                parse_hosts(1000000000, 1000000000, vec![], vec![], duration)
+                    .unwrap();
+            assert!(
+                add_retunes(&mut hosts, vec![bad.to_string()], duration)
+                    .is_err(),
+                "\"{}\" parsed",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_divergence() {
+        use crate::divergence;
+
+        assert_eq!(divergence(1000, 1000), "identical");
+        assert_eq!(divergence(0, 0), "identical");
+        assert_eq!(divergence(1001, 1000), "differ by 1 (1.000e-1% of 1000)");
+        assert_eq!(divergence(-3, -4), "differ by 1 (2.500e1% of -4)");
+        assert_eq!(divergence(5, 0), "differ by 5 (reference is 0)");
+    }
+
+    #[test]
+    fn test_next_low_bits_wrap() {
+        let wrap = 1u64 << 32;
+
+        assert_eq!(math::next_low_bits_wrap(0, 32).unwrap(), wrap);
+        assert_eq!(math::next_low_bits_wrap(wrap - 1, 32).unwrap(), wrap);
+        // landing exactly on a wrap finds the one after it
+        assert_eq!(math::next_low_bits_wrap(wrap, 32).unwrap(), 2 * wrap);
+        assert_eq!(
+            math::next_low_bits_wrap(5 * wrap + 12345, 32).unwrap(),
+            6 * wrap
+        );
+
+        // the last 32-bit window wraps along with the full TSC
+        assert!(math::next_low_bits_wrap(u64::MAX - wrap + 1, 32).is_err());
+        assert!(math::next_low_bits_wrap(u64::MAX, 32).is_err());
+
+        // a view of no bits or all of them never wraps on its own
+        assert!(matches!(
+            math::next_low_bits_wrap(0, 0),
+            Err(math::MathError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            math::next_low_bits_wrap(0, 64),
+            Err(math::MathError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_hrtime() {
+        use math::Rounding;
+
+        for t in HRTIME_TESTS.iter() {
+            let msg = format!("tsc={}, freq_hz={}", t.t, t.h);
+
+            match (math::hrtime(t.t, t.h, Rounding::Truncate), t.v) {
+                (Ok(ns), Some(v)) => assert_eq!(ns, v, "{}", msg),
+                (Err(_), None) => {}
+                (Ok(ns), None) => panic!("expected error, got {}: {}", ns, msg),
+                (Err(e), Some(v)) => {
+                    panic!("expected {}, got err {}: {}", v, e, msg)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rounding() {
+        use math::Rounding::{Ceil, Nearest, Truncate};
+
+        // 1 tick at 3 GHz is 0.333 ns, 2 ticks 0.667 ns
+        assert_eq!(math::hrtime(1, 3000000000, Truncate).unwrap(), 0);
+        assert_eq!(math::hrtime(1, 3000000000, Nearest).unwrap(), 0);
+        assert_eq!(math::hrtime(1, 3000000000, Ceil).unwrap(), 1);
+        assert_eq!(math::hrtime(2, 3000000000, Truncate).unwrap(), 0);
+        assert_eq!(math::hrtime(2, 3000000000, Nearest).unwrap(), 1);
+        assert_eq!(math::hrtime(2, 3000000000, Ceil).unwrap(), 1);
+
+        // 1 ns at 2.5 GHz is exactly 2.5 ticks: halves round up
+        assert_eq!(math::tsc(1, 2500000000, Truncate).unwrap(), 2);
+        assert_eq!(math::tsc(1, 2500000000, Nearest).unwrap(), 3);
+        assert_eq!(math::tsc(1, 2500000000, Ceil).unwrap(), 3);
+
+        // exact values are the same however they're rounded
+        for r in [Truncate, Nearest, Ceil] {
+            assert_eq!(
+                math::hrtime(3000000000, 3000000000, r).unwrap(),
+                1e9 as u64
+            );
+            assert_eq!(math::tsc(2, 2500000000, r).unwrap(), 5);
+        }
+
+        // rounding up can push a value that fits past u64::MAX
+        let ns = 18446744055262807560;
+        assert_eq!(math::tsc(ns, 1000000001, Truncate).unwrap(), u64::MAX);
+        assert!(math::tsc(ns, 1000000001, Ceil).is_err());
+    }
+
+    #[test]
+    fn test_parse_host_def() {
+        use input::parse_host_def;
+
+        let h = parse_host_def("10 10000000000 2000000000").unwrap();
+        assert_eq!(
+            (h.start, h.host_tsc, h.host_freq, h.guest_freq),
+            (10, 10000000000, 2000000000, None)
+        );
+
+        // hex, and any amount of whitespace between fields
+        let h = parse_host_def("  0xa\t0x2540be400   2000000000 ").unwrap();
+        assert_eq!(
+            (h.start, h.host_tsc, h.host_freq),
+            (10, 10000000000, 2000000000)
+        );
+
+        // a frequency with a suffix
+        let h = parse_host_def("10 0 2.4GHz").unwrap();
+        assert_eq!(h.host_freq, 2400000000);
+
+        for bad in [
+            "",
+            "   ",
+            "10",
+            "10 10000000000",
+            "10 10000000000 2000000000 5",
+            "ten 10000000000 2000000000",
+            "10 -1 2000000000",
+            "10 10000000000 0xzz",
+            "10 10000000000 0",
+            "10 10000000000 0GHz",
+        ] {
+            assert!(parse_host_def(bad).is_err(), "\"{}\" parsed", bad);
+        }
+    }
+
+    #[test]
+    fn test_parse_hosts_start_order() {
+        let parse = |hosts: &[&str], tsc_hosts: &[&str]| {
+            input::parse_hosts(
+                1000000000,
+                1000000000,
+                hosts.iter().map(|s| s.to_string()).collect(),
+                tsc_hosts.iter().map(|s| s.to_string()).collect(),
+                20,
+            )
+        };
+
+        // given out of order, migrations are sorted by time
+        let hosts = parse(&["10 1 1000000000", "5 1 1000000000"], &[]).unwrap();
+        let starts: Vec<usize> = hosts.iter().map(|h| h.start).collect();
+        assert_eq!(starts, vec![0, 5, 10]);
+
+        // but two can't happen at once
+        let e = parse(&["5 1 1000000000", "10 1 1000000000", "10 2 1"], &[])
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "migration 3 at 10s must be after migration 2 at 10s"
+        );
+
+        // including with the boot host at t=0
+        let e = parse(&["0 1 1000000000"], &[]).unwrap_err();
+        assert_eq!(e.to_string(), "migration 1 at 0s must be after boot at 0s");
+
+        // or within the same second of the source host's TSC
+        assert!(parse(&[], &["1100000000 1 1"]).is_err());
+        assert!(parse(&[], &["3500000000 1 1000000000", "1 2 1"]).is_err());
+
+        // the run can end with a migration, but not before one
+        assert!(parse(&["20 1 1000000000"], &[]).is_ok());
+        let e = parse(&["5 1 1000000000", "21 1 1000000000"], &[]).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "migration 2 at 21s is past the duration of 20s"
+        );
+    }
+
+    #[test]
+    fn test_math_impl_all_agrees() {
+        use crate::cross_check;
+
+        for t in FREQ_RATIO_TESTS_VALID.iter() {
+            assert!(
+                cross_check(
+                    &asm_math::freq_multiplier(t.g, t.h, t.f),
+                    &math::freq_multiplier(t.g, t.h, t.f, 64 - t.f),
+                ),
+                "freq: guest_freq={}, host_freq={}, frac_size={}",
+                t.g,
+                t.h,
+                t.f
+            );
+        }
+
+        // only inputs the rust accepts are run through the asm
+        for t in GUEST_TSC_REGRESSIONS.iter().filter(|t| t.v.is_some()) {
+            let msg = format!(
+                "initial_host_tsc={}, initial_guest_tsc={}, host_freq={}, \
+                 guest_freq={}, host_tsc={}, format={}.{}",
+                t.ih, t.ig, t.h, t.g, t.t, t.i, t.f
+            );
+
+            assert!(
+                cross_check(
+                    &asm_math::calc_guest_tsc(t.ih, t.ig, t.h, t.g, t.t, t.f),
+                    &math::guest_tsc(t.ih, t.ig, t.h, t.g, t.t, t.f, t.i),
+                ),
+                "guest TSC: {}",
+                msg
+            );
+            assert!(
+                cross_check(
+                    &asm_math::calc_tsc_offset(t.ih, t.ig, t.g, t.h, t.f),
+                    &math::tsc_offset(t.ih, t.ig, t.g, t.h, t.f, t.i),
+                ),
+                "offset: {}",
+                msg
+            );
+        }
+    }
+
+    #[test]
+    fn test_freq_ratio_rounded() {
+        for t in FREQ_RATIO_ROUNDED_TESTS.iter() {
+            let msg = format!(
+                "guest_freq={}, host_freq={}, format={}.{}",
+                t.g, t.h, t.i, t.f
+            );
+
+            let truncated = math::freq_multiplier(t.g, t.h, t.f, t.i);
+            if t.t == 0 {
+                assert!(
+                    matches!(
+                        truncated,
+                        Err(math::MathError::RatioUnderflow { .. })
+                    ),
+                    "truncated: {}",
+                    msg
+                );
+            } else {
+                assert_eq!(truncated.unwrap(), t.t, "truncated: {}", msg);
+            }
+            assert_eq!(
+                math::freq_multiplier_rounded(t.g, t.h, t.f, t.i).unwrap(),
+                t.r,
+                "rounded: {}",
+                msg
+            );
+        }
+
+        // 2^8 - 1/2^33 is half an LSB under 2^8, so it rounds up to a ratio
+        // that doesn't fit in 8.32
+        let (g, h) = ((1 << 41) - 1, 1 << 33);
+        assert_eq!(math::freq_multiplier(g, h, 32, 8).unwrap(), (1 << 40) - 1);
+        assert!(math::freq_multiplier_rounded(g, h, 32, 8).is_err());
+    }
+
+    #[test]
+    fn test_compare_guest_tsc() {
+        use crate::compare_guest_tsc;
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        // a 2/3 guest-to-host ratio isn't exact in 8.32, but both
+        // implementations truncate it the same way
+        for host_tsc in [0, 1_500_000_000, 3_000_000_001, 1 << 50] {
+            let (rs, asm) = compare_guest_tsc(
+                0,
+                0,
+                host_tsc,
+                3_000_000_000,
+                2_000_000_000,
+                INT_SIZE_AMD,
+                FRAC_SIZE_AMD,
+            )
+            .unwrap();
+            assert_eq!(rs, asm, "host_tsc={}", host_tsc);
+        }
+
+        // the asm isn't run when the rust rejects the inputs
+        let e = compare_guest_tsc(0, 0, 1, 1, 1 << 40, 8, 32).unwrap_err();
+        assert!(e.to_string().starts_with("rust:"), "{}", e);
+    }
+
+    #[test]
+    fn test_multiplier_breakdown() {
+        use crate::{fixed_point_decimal, hex_grouped};
+
+        // 1.5 in 8.32
+        let m = math::freq_multiplier(3, 2, 32, 8).unwrap();
+        assert_eq!(hex_grouped(m), "0x1_8000_0000");
+        assert_eq!(fixed_point_decimal(m, 32, 4), "1.5000");
+
+        // 2/3 is truncated, never rounded up
+        let m = math::freq_multiplier(2, 3, 32, 8).unwrap();
+        assert_eq!(fixed_point_decimal(m, 32, 4), "0.6666");
+        assert_eq!(fixed_point_decimal(m, 32, 12), "0.666666666511");
+
+        assert_eq!(hex_grouped(0), "0x0");
+        assert_eq!(hex_grouped(0xabcd), "0xabcd");
+        assert_eq!(hex_grouped(u64::MAX), "0xffff_ffff_ffff_ffff");
+    }
+
+    #[test]
+    fn test_math_error_kinds() {
+        use math::MathError;
+
+        assert_eq!(
+            math::freq_multiplier(300_000, 1000, 32, 8),
+            Err(MathError::RatioTooLarge {
+                guest_hz: 300_000,
+                host_hz: 1000,
+                int_size: 8,
+                frac_size: 32
+            })
+        );
+        assert_eq!(
+            math::scale_tsc(u64::MAX, 2 << 32, 32),
+            Err(MathError::ScaleOverflow {
+                tsc: u64::MAX,
+                multiplier: 2 << 32,
+                frac_size: 32
+            })
+        );
+        assert_eq!(
+            math::cold_migration_offset(0, u64::MAX, 1000, 1000, 32, 8),
+            Err(MathError::OffsetOverflow {
+                host_tsc_scaled: 0,
+                guest_tsc: u64::MAX
+            })
+        );
+        assert!(matches!(
+            math::hrtime(u64::MAX, 1, math::Rounding::Truncate),
+            Err(MathError::Overflow(_))
+        ));
+        assert!(matches!(
+            math::calibrate_freq(5, 100, 5, 200),
+            Err(MathError::InvalidInput(_))
+        ));
+        assert_eq!(
+            math::freq_multiplier(0, 1000, 32, 8),
+            Err(MathError::InvalidFrequency {
+                name: "guest frequency"
+            })
+        );
+        assert_eq!(
+            math::freq_multiplier(1000, 0, 32, 8),
+            Err(MathError::InvalidFrequency {
+                name: "host frequency"
+            })
+        );
+        for rounding in [
+            math::Rounding::Truncate,
+            math::Rounding::Nearest,
+            math::Rounding::Ceil,
+        ] {
+            assert!(matches!(
+                math::hrtime(1, 0, rounding),
+                Err(MathError::InvalidFrequency { .. })
+            ));
+            assert!(matches!(
+                math::tsc(1, 0, rounding),
+                Err(MathError::InvalidFrequency { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_freq_multiplier_with_error() {
+        // power of two ratios are exact
+        for (g, h) in [(1, 1), (2, 1), (1, 4), (4_000_000_000, 1_000_000_000)] {
+            let (m, e) = math::freq_multiplier_with_error(g, h, 32, 8).unwrap();
+            assert_eq!(m, math::freq_multiplier(g, h, 32, 8).unwrap());
+            assert_eq!(e, 0.0, "{}/{}", g, h);
+        }
+
+        // 2/3 is truncated to 0xaaaaaaaa / 2^32, which is short by two thirds
+        // of an LSB: (2/3 * 2^-32) / (2/3) = 2^-32
+        let (m, e) = math::freq_multiplier_with_error(2, 3, 32, 8).unwrap();
+        assert_eq!(m, 0xaaaa_aaaa);
+        assert!(e > 0.0);
+        assert!((e - 2f64.powi(-32)).abs() < 1e-20, "{}", e);
+
+        // more fractional bits shrink the error
+        let (_, e48) = math::freq_multiplier_with_error(2, 3, 48, 16).unwrap();
+        assert!(e48 < e);
+
+        assert!(math::freq_multiplier_with_error(300_000, 1000, 32, 8).is_err());
+    }
+
+    #[test]
+    fn test_min_frac_size_for_tolerance() {
+        let min =
+            |tolerance| math::min_frac_size_for_tolerance(2, 3, 8, tolerance);
+
+        // 2/3 with f frac bits is short by 2^-(f + 1) of itself for odd f,
+        // and 2^-f for even f
+        assert_eq!(min(0.5), Ok(1));
+        assert_eq!(min(0.1), Ok(3));
+        assert_eq!(min(0.01), Ok(7));
+        assert_eq!(min(1e-6), Ok(19));
+        assert_eq!(min(2f64.powi(-32)), Ok(31));
+
+        // but it's never exact
+        assert!(matches!(min(0.0), Err(math::MathError::InvalidInput(_))));
+
+        // a power of two ratio is exact with a single bit
+        assert_eq!(math::min_frac_size_for_tolerance(1, 2, 8, 0.0), Ok(1));
+
+        // the int part has to fit regardless
+        assert!(matches!(
+            math::min_frac_size_for_tolerance(300_000, 1000, 8, 0.1),
+            Err(math::MathError::RatioTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_tsc_offset_extremes() {
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        let hz = 1_000_000_000;
+        let offset = |host_tsc, guest_tsc| {
+            math::tsc_offset(
+                host_tsc,
+                guest_tsc,
+                hz,
+                hz,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD,
+            )
+        };
+
+        // i64::MIN is a valid offset, even though its magnitude needs bit 63
+        assert_eq!(offset(1 << 63, 0), Ok(i64::MIN));
+        assert_eq!(offset((1 << 63) + 5, 5), Ok(i64::MIN));
+        assert_eq!(offset((1 << 63) - 1, 0), Ok(i64::MIN + 1));
+        assert!(offset((1 << 63) + 1, 0).is_err());
+
+        assert_eq!(offset(0, i64::MAX as u64), Ok(i64::MAX));
+        assert_eq!(offset(5, i64::MAX as u64 + 5), Ok(i64::MAX));
+        assert!(offset(0, 1 << 63).is_err());
+
+        // and the guest TSC starts where it should with it
+        assert_eq!(
+            math::guest_tsc(
+                1 << 63,
+                0,
+                hz,
+                hz,
+                (1 << 63) + 10,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD
+            ),
+            Ok(10)
+        );
+    }
+
+    #[test]
+    fn test_apply_ppm_correction() {
+        assert_eq!(
+            math::apply_ppm_correction(1_000_000_000, 500),
+            1_000_500_000
+        );
+        assert_eq!(
+            math::apply_ppm_correction(1_000_000_000, -500),
+            999_500_000
+        );
+        assert_eq!(math::apply_ppm_correction(1_000_000_000, 0), 1_000_000_000);
+
+        // saturates at both ends rather than wrapping
+        assert_eq!(math::apply_ppm_correction(u64::MAX, 1), u64::MAX);
+        assert_eq!(math::apply_ppm_correction(1_000_000_000, -1_000_001), 0);
+        assert_eq!(math::apply_ppm_correction(1_000_000_000, i32::MIN), 0);
+    }
+
+    #[test]
+    fn test_max_ratio_multiplier() {
+        use crate::{fixed_point_decimal, max_ratio_multiplier};
+
+        // just under 256 for 8.32, one ulp (2^-32) short
+        let m = max_ratio_multiplier(8, 32).unwrap();
+        assert_eq!(m >> 32, 255);
+        assert_eq!(m as u128 + 1, 256u128 << 32);
+        assert_eq!(fixed_point_decimal(m, 32, 6), "255.999999");
+
+        // and just under 65536 for 16.48
+        let m = max_ratio_multiplier(16, 48).unwrap();
+        assert_eq!(m, u64::MAX);
+        assert_eq!(m as u128 + 1, 65536u128 << 48);
+        assert_eq!(fixed_point_decimal(m, 48, 6), "65535.999999");
+
+        assert_eq!(
+            fixed_point_decimal(1, 32, 32),
+            "0.00000000023283064365386962890625"
+        );
+        assert!(max_ratio_multiplier(0, 32).is_err());
+        assert!(max_ratio_multiplier(16, 49).is_err());
+    }
+
+    #[test]
+    fn test_boot_offset() {
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        let (g, h) = (1_000_000_000, 1_500_000_000);
+        for host_tsc in [0, 1, 1_000_000_007, 1 << 40] {
+            let scaled = math::guest_tsc_at_boot(
+                host_tsc,
+                h,
+                g,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD,
+            )
+            .unwrap();
+            let m = math::freq_multiplier(g, h, FRAC_SIZE_AMD, INT_SIZE_AMD)
+                .unwrap();
+            assert_eq!(
+                scaled,
+                math::scale_tsc(host_tsc, m, FRAC_SIZE_AMD).unwrap()
+            );
+
+            // the offset cancels the scaled host TSC, so the guest starts at 0
+            let offset =
+                math::boot_offset(host_tsc, h, g, FRAC_SIZE_AMD, INT_SIZE_AMD)
+                    .unwrap();
+            assert_eq!(offset, -(scaled as i64));
+            assert_eq!(
+                math::guest_tsc(
+                    host_tsc,
+                    0,
+                    h,
+                    g,
+                    host_tsc,
+                    FRAC_SIZE_AMD,
+                    INT_SIZE_AMD
+                ),
+                Ok(0)
+            );
+        }
+
+        // and the ratio still has to fit the format
+        assert!(
+            math::boot_offset(0, 1, g, FRAC_SIZE_AMD, INT_SIZE_AMD).is_err()
+        );
+    }
+
+    #[test]
+    fn test_ratio_underflow() {
+        use math::MathError;
+
+        // 2 * 1 / 1_000_000 truncates to a multiplier of 0
+        let err = MathError::RatioUnderflow {
+            guest_hz: 1,
+            host_hz: 1_000_000,
+            frac_size: 1,
+        };
+        assert_eq!(math::freq_multiplier(1, 1_000_000, 1, 8), Err(err.clone()));
+        assert!(err.to_string().contains("at least 20 fractional bits"));
+        assert_eq!(math::freq_multiplier(1, 1_000_000, 20, 8), Ok(1));
+
+        // as does anything built on it
+        assert_eq!(
+            math::guest_tsc(0, 0, 1_000_000, 1, 1_000_000, 1, 8),
+            Err(err)
+        );
+
+        // rounding can lift a multiplier of 0 to 1, but not below a half
+        assert_eq!(math::freq_multiplier_rounded(2, 3, 1, 8), Ok(1));
+        assert!(matches!(
+            math::freq_multiplier_rounded(1, 5, 1, 8),
+            Err(MathError::RatioUnderflow { .. })
+        ));
+
+        // the hint is exact at a power of two, and there's none for a guest
+        // frequency of 0
+        let err = |guest_hz, host_hz| {
+            MathError::RatioUnderflow {
+                guest_hz,
+                host_hz,
+                frac_size: 1,
+            }
+            .to_string()
+        };
+        assert!(err(1, 1 << 20).contains("at least 20 fractional bits"));
+        assert!(err(1, (1 << 20) + 1).contains("at least 21 fractional bits"));
+        assert!(err(3, u64::MAX).contains("at least 63 fractional bits"));
+        assert!(err(0, 1_000_000).contains("would never advance)"));
+    }
+
+    #[test]
+    fn test_random_migrations() {
+        use crate::replay_args;
+        use input::{parse_hosts, random_migrations};
+
+        // the same seed gives the same scenario, and another seed a new one
+        let a = random_migrations(5, 42, 60).unwrap();
+        assert_eq!(a, random_migrations(5, 42, 60).unwrap());
+        assert_eq!(replay_args(42, &a), replay_args(42, &a));
+        assert_ne!(a, random_migrations(5, 43, 60).unwrap());
+
+        // the migrations are in order, within the run, and parse as --migrate
+        // flags would
+        assert_eq!(a.len(), 5);
+        assert!(a.windows(2).all(|w| w[0].start < w[1].start));
+        assert!(a.iter().all(|h| (1..60).contains(&h.start)));
+        let args = a.iter().map(input::migrate_arg).collect();
+        let hosts = parse_hosts(0, 1_000_000_000, args, vec![], 60).unwrap();
+        assert_eq!(&hosts[1..], &a[..]);
+
+        // every second after boot can be used, but no more
+        assert_eq!(random_migrations(9, 1, 10).unwrap().len(), 9);
+        assert!(random_migrations(10, 1, 10).is_err());
+    }
+
+    #[test]
+    fn test_equal_freq_is_exact() {
+        use tsc_simulator::{
+            FRAC_SIZE_AMD, FRAC_SIZE_INTEL, INT_SIZE_AMD, INT_SIZE_INTEL,
+        };
+
+        let cases: &[(u64, u64, u64)] = &[
+            (0, 0, u64::MAX),
+            (1_000_000_007, 123_456_789, 1 << 62),
+            ((1 << 63) + 5, 1 << 62, u64::MAX),
+            (u64::MAX - 1_000, u64::MAX - 2_000, u64::MAX - 1),
+            (1 << 40, (1 << 40) + (1 << 62), (1 << 41) + 3),
+        ];
+        for &hz in &[1, 1_000_000_000, 3_333_333_333, u64::MAX] {
+            for &(ih, ig, ch) in cases {
+                let expected = ch - ih + ig;
+                for (f, i) in [
+                    (FRAC_SIZE_AMD, INT_SIZE_AMD),
+                    (FRAC_SIZE_INTEL, INT_SIZE_INTEL),
+                ] {
+                    assert_eq!(
+                        math::tsc_offset(ih, ig, hz, hz, f, i),
+                        Ok((ig as i128 - ih as i128) as i64),
+                        "hz={}, initial_host_tsc={}, {}.{}",
+                        hz,
+                        ih,
+                        i,
+                        f
+                    );
+                    assert_eq!(
+                        math::guest_tsc(ih, ig, hz, hz, ch, f, i),
+                        Ok(expected),
+                        "hz={}, initial_host_tsc={}, cur_host_tsc={}, {}.{}",
+                        hz,
+                        ih,
+                        ch,
+                        i,
+                        f
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_fixed_point() {
+        use math::FixedPoint;
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        // 1.5 in 8.32
+        let m = FixedPoint::from_ratio(3_000_000_000, 2_000_000_000, 8, 32)
+            .unwrap();
+        assert_eq!(m.value(), 3 << 31);
+        assert_eq!((m.int_size(), m.frac_size()), (8, 32));
+        assert_eq!(m.as_f64(), 1.5);
+        assert_eq!(m.scale(1_000), Ok(1_500));
+        assert_eq!(FixedPoint::new(3 << 31, 8, 32), Ok(m));
+
+        // the free functions are the same calculation
+        let (g, h) = (1_000_000_000, 3_000_000_000);
+        let m =
+            FixedPoint::from_ratio(g, h, INT_SIZE_AMD, FRAC_SIZE_AMD).unwrap();
+        let offset = m.tsc_offset(1 << 40, 12_345).unwrap();
+        assert_eq!(
+            math::tsc_offset(
+                1 << 40,
+                12_345,
+                g,
+                h,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD
+            ),
+            Ok(offset)
+        );
+        assert_eq!(
+            math::guest_tsc(
+                1 << 40,
+                12_345,
+                h,
+                g,
+                1 << 41,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD
+            ),
+            m.guest_tsc(1 << 41, offset)
+        );
+
+        // the value has to fit the format, and the format 64 bits
+        assert!(FixedPoint::new(1 << 40, 8, 32).is_err());
+        assert!(FixedPoint::new(1, 0, 32).is_err());
+        assert!(FixedPoint::new(1, 16, 49).is_err());
+        assert!(FixedPoint::from_ratio(300, 1, 8, 32).is_err());
+    }
+
+    #[test]
+    fn test_max_drift_ns() {
+        use tsc_simulator::{
+            FRAC_SIZE_AMD, FRAC_SIZE_INTEL, INT_SIZE_AMD, INT_SIZE_INTEL,
+        };
+
+        // power-of-two ratios are exact, so the guest never drifts
+        for (g, h) in [
+            (1_000_000_000, 1_000_000_000),
+            (2_000_000_000, 1_000_000_000),
+            (1_000_000_000, 4_000_000_000),
+        ] {
+            assert_eq!(
+                math::max_drift_ns(86_400, g, h, FRAC_SIZE_AMD, INT_SIZE_AMD),
+                Ok((0, 0))
+            );
+            assert_eq!(
+                math::max_drift_ns(
+                    86_400,
+                    g,
+                    h,
+                    FRAC_SIZE_INTEL,
+                    INT_SIZE_INTEL
+                ),
+                Ok((0, 0))
+            );
+        }
+
+        // otherwise truncation only ever makes the guest slow, and the drift
+        // grows to the end of the run, where it's the uptime error
+        let (g, h) = (1_000_000_000, 3_000_000_000);
+        let (drift, t) =
+            math::max_drift_ns(86_400, g, h, FRAC_SIZE_AMD, INT_SIZE_AMD)
+                .unwrap();
+        let daily =
+            math::uptime_error_ns(86_400, g, h, FRAC_SIZE_AMD, INT_SIZE_AMD)
+                .unwrap();
+        assert!(drift < 0);
+        assert_eq!(t, 86_400);
+        assert!((drift - daily).abs() <= 1, "{} vs {}", drift, daily);
+
+        // a run the host TSC can't count to is an error, not a long loop
+        assert!(matches!(
+            math::max_drift_ns(u64::MAX, g, h, FRAC_SIZE_AMD, INT_SIZE_AMD),
+            Err(math::MathError::Overflow(_))
+        ));
+    }
+
+    #[test]
+    fn test_tsc_incr_checked() {
+        assert_eq!(math::tsc_incr_checked(100, 1000), Some(1100));
+        assert_eq!(
+            math::tsc_incr_checked(u64::MAX - 1000, 1000),
+            Some(u64::MAX)
+        );
+        assert_eq!(math::tsc_incr_checked(u64::MAX - 999, 1000), None);
+
+        // the unchecked version saturates rather than wrapping
+        assert_eq!(math::tsc_incr(u64::MAX - 999, 1000), u64::MAX);
+    }
+
+    #[test]
+    fn test_would_freq_multiplier_overflow() {
+        // the asm faults on these, and the rust rejects them
+        for t in FREQ_RATIO_TESTS_INVALID.iter() {
+            assert!(
+                math::would_freq_multiplier_overflow(t.g, t.h, t.f, 64 - t.f),
+                "guest_freq={}, host_freq={}, frac_size={}",
+                t.g,
+                t.h,
+                t.f
+            );
+            assert!(math::freq_multiplier(t.g, t.h, t.f, 64 - t.f).is_err());
+        }
+
+        for t in FREQ_RATIO_TESTS_VALID.iter() {
+            assert!(
+                !math::would_freq_multiplier_overflow(t.g, t.h, t.f, 64 - t.f),
+                "guest_freq={}, host_freq={}, frac_size={}",
+                t.g,
+                t.h,
+                t.f
+            );
+        }
+
+        // a narrower format than the asm's overflows sooner
+        assert!(!math::would_freq_multiplier_overflow(255, 1, 32, 8));
+        assert!(math::would_freq_multiplier_overflow(256, 1, 32, 8));
+        assert!(math::would_freq_multiplier_overflow(1, 0, 32, 8));
+    }
+
+    #[test]
+    fn test_parse_frequency() {
+        use input::parse_frequency;
+
+        assert_eq!(parse_frequency("2.4GHz").unwrap(), 2_400_000_000);
+        assert_eq!(parse_frequency("1MHz").unwrap(), 1_000_000);
+        assert_eq!(parse_frequency("2400 mhz").unwrap(), 2_400_000_000);
+        assert_eq!(parse_frequency("32.768kHz").unwrap(), 32_768);
+        assert_eq!(parse_frequency("1.000Hz").unwrap(), 1);
+        assert_eq!(parse_frequency("1000000000").unwrap(), 1_000_000_000);
+        assert_eq!(parse_frequency("0x3b9aca00").unwrap(), 1_000_000_000);
+
+        for bad in [
+            "-1GHz",
+            "-1",
+            "1.5Hz",
+            "2.4",
+            "GHz",
+            ".5GHz",
+            "2.GHz",
+            "1.2.3GHz",
+            "1e9Hz",
+            "20000000000GHz",
+            "0",
+            "0GHz",
+            "0.0MHz",
+            "0x0",
+        ] {
+            assert!(parse_frequency(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        use crate::rfc3339;
+
+        // 3.5s after 2023-11-14T22:13:20Z
+        assert_eq!(
+            rfc3339(1_700_000_003_500_000_000),
+            "2023-11-14T22:13:23.500000000Z"
+        );
+
+        // before the Unix epoch
+        assert_eq!(rfc3339(-1), "1969-12-31T23:59:59.999999999Z");
+    }
+
+    #[test]
+    fn test_scale_tsc_boundary() {
+        // one ulp above 1.0 in 8.32: the largest TSC that scales is
+        // ((1 << 96) - 1) / multiplier
+        let m = (1u64 << 32) + 1;
+        let max_tsc = (((1u128 << 96) - 1) / m as u128) as u64;
+
+        let scaled = math::scale_tsc(max_tsc, m, 32).unwrap();
+        assert_eq!(math::scale_tsc_saturating(max_tsc, m, 32), scaled);
+        assert!(scaled > u64::MAX - m);
+
+        let e = math::scale_tsc(max_tsc + 1, m, 32).unwrap_err();
+        assert!(e.to_string().contains("value occupies 65 bits, max 64"));
+        assert_eq!(math::scale_tsc_saturating(max_tsc + 1, m, 32), u64::MAX);
+
+        // a ratio of 1.0 scales any TSC, including u64::MAX
+        assert_eq!(math::scale_tsc_saturating(u64::MAX, 1 << 32, 32), u64::MAX);
+        assert!(math::scale_tsc(u64::MAX, 1 << 32, 32).is_ok());
+
+        // the largest multiplier, a ratio of nearly 2^32, by 32 bits
+        let e = math::scale_tsc(u64::MAX, u64::MAX, 32).unwrap_err();
+        assert!(e.to_string().contains("value occupies 96 bits, max 64"));
+    }
+
+    #[test]
+    fn test_verify_migration_chain() {
+        use math::{HostParams, MathError};
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        // a 2 GHz guest booted on a 3 GHz host, then moved onto a 2.5 GHz
+        // host whose TSC is behind, and a 1.7 GHz one whose TSC is ahead
+        let boot = HostParams {
+            host_tsc: 1_000_000_000,
+            host_hz: 3_000_000_000,
+        };
+        let migrations = [
+            (
+                10,
+                HostParams {
+                    host_tsc: 5_000,
+                    host_hz: 2_500_000_000,
+                },
+            ),
+            (
+                25,
+                HostParams {
+                    host_tsc: 900_000_000_000,
+                    host_hz: 1_700_000_000,
+                },
+            ),
+        ];
+        let observed = math::verify_migration_chain(
+            boot,
+            2_000_000_000,
+            &migrations,
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD,
+        )
+        .unwrap();
+
+        // each hop sees the guest frequency's worth of ticks per second since
+        // the last, never more, and short by at most the truncated
+        // multiplier's loss over the source host's ticks
+        assert_eq!(observed.len(), 2);
+        let (mut prev_t, mut prev_tsc, mut src_hz) = (0, 0, boot.host_hz);
+        for (&tsc, &(t, dst)) in observed.iter().zip(migrations.iter()) {
+            let ideal = (t - prev_t) * 2_000_000_000;
+            let host_ticks = (t - prev_t) * src_hz;
+            assert!(tsc > prev_tsc);
+            assert!(tsc - prev_tsc <= ideal);
+            assert!(
+                ideal - (tsc - prev_tsc) <= (host_ticks >> FRAC_SIZE_AMD) + 1,
+                "guest TSC {} after {}s",
+                tsc,
+                t
+            );
+            (prev_t, prev_tsc, src_hz) = (t, tsc, dst.host_hz);
+        }
+
+        // migrations must be in order
+        let reversed = [migrations[1], migrations[0]];
+        assert!(matches!(
+            math::verify_migration_chain(
+                boot,
+                2_000_000_000,
+                &reversed,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD,
+            ),
+            Err(MathError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_sparkline() {
+        use crate::sparkline;
+
+        // one character per step, from the lowest block to the highest
+        let drift: Vec<i128> = (0..21).map(|t| -t * 7).collect();
+        let line = sparkline(&drift, 80);
+        assert_eq!(line.chars().count(), drift.len());
+        assert!(line.starts_with('█'));
+        assert!(line.ends_with('▁'));
+
+        // flat drift is a flat line
+        assert_eq!(sparkline(&[5; 4], 80), "▁▁▁▁");
+        assert_eq!(sparkline(&[], 80), "");
+
+        // longer runs are fit to the width, keeping spikes
+        let mut drift = vec![0i128; 1000];
+        drift[500] = 100;
+        let line = sparkline(&drift, 80);
+        assert_eq!(line.chars().count(), 80);
+        assert_eq!(line.chars().filter(|&c| c == '█').count(), 1);
+    }
+
+    #[test]
+    fn test_host_steps_fractional_ticks() {
+        use math::HostSteps;
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        // 1.5 ticks per ns: the host TSC alternates 1 and 2 ticks a step,
+        // rather than losing half a tick every step
+        let steps: Vec<(u64, u64, u64)> = HostSteps::new(
+            0,
+            0,
+            1_500_000_000,
+            1_500_000_000,
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD,
+            0,
+            10,
+            1,
+        )
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+        let host_tscs: Vec<u64> = steps.iter().map(|s| s.1).collect();
+        assert_eq!(host_tscs, vec![0, 1, 3, 4, 6, 7, 9, 10, 12, 13, 15]);
+        for (t_ns, host_tsc, guest_tsc) in steps {
+            assert_eq!(host_tsc, t_ns * 3 / 2);
+            assert_eq!(guest_tsc, host_tsc);
+        }
+
+        // a shortened last step lands on the host TSC for the end time
+        let last = HostSteps::new(
+            100,
+            0,
+            1_500_000_000,
+            1_000_000_000,
+            FRAC_SIZE_AMD,
+            INT_SIZE_AMD,
+            0,
+            1_000_000_000,
+            333_333_333,
+        )
+        .unwrap()
+        .map(Result::unwrap)
+        .last()
+        .unwrap();
+        assert_eq!((last.0, last.1), (1_000_000_000, 1_500_000_100));
+
+        // no steps, or steps that run backwards, are rejected
+        let steps = |start_ns, end_ns, step_ns| {
+            HostSteps::new(
+                0,
+                0,
+                1_000_000_000,
+                1_000_000_000,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD,
+                start_ns,
+                end_ns,
+                step_ns,
+            )
+        };
+        assert!(matches!(
+            steps(0, 10, 0),
+            Err(math::MathError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            steps(10, 0, 1),
+            Err(math::MathError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_pre_boot_limit() {
+        use tsc_simulator::{FRAC_SIZE_AMD, INT_SIZE_AMD};
+
+        for (host_tsc, host_hz, guest_tsc, guest_hz, limit) in [
+            // the host TSC reaches 0 first
+            (
+                5_000_000_000,
+                1_000_000_000,
+                9_000_000_000,
+                1_000_000_000,
+                5,
+            ),
+            (
+                5_000_000_000,
+                3_000_000_000,
+                3_000_000_000,
+                2_000_000_000,
+                1,
+            ),
+            // the guest TSC does
+            (
+                5_000_000_000,
+                1_000_000_000,
+                1_500_000_000,
+                1_000_000_000,
+                1,
+            ),
+            (1_000_000_000, 1_000_000_000, 0, 1_000_000_000, 0),
+            (
+                90_000_000_000,
+                3_000_000_000,
+                7_000_000_000,
+                2_000_000_000,
+                3,
+            ),
+        ] {
+            let boot = (host_tsc, host_hz, guest_tsc, guest_hz);
+            let l = math::pre_boot_limit(
+                host_tsc,
+                host_hz,
+                guest_tsc,
+                guest_hz,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD,
+            )
+            .unwrap();
+            assert_eq!(l, limit, "{:?}", boot);
+
+            // the limit itself extrapolates to a guest TSC of at least 0
+            let g = math::guest_tsc_signed(
+                host_tsc,
+                guest_tsc,
+                host_hz,
+                guest_hz,
+                host_tsc - l * host_hz,
+                FRAC_SIZE_AMD,
+                INT_SIZE_AMD,
+            )
+            .unwrap();
+            assert!(g >= 0, "{:?}", boot);
+        }
+
+        assert!(matches!(
+            math::pre_boot_limit(0, 0, 0, 1, FRAC_SIZE_AMD, INT_SIZE_AMD),
+            Err(math::MathError::InvalidFrequency { .. })
+        ));
+    }
 }
@@ -0,0 +1,285 @@
+// Test vectors for the fixed point math, shared by the unit tests and the
+// `selftest` command
+
+use tsc_simulator::{FRAC_SIZE_AMD, FRAC_SIZE_INTEL};
+#[cfg(test)]
+use tsc_simulator::{INT_SIZE_AMD, INT_SIZE_INTEL};
+
+pub struct Frt {
+    pub g: u64,
+    pub h: u64,
+    pub f: u32,
+    pub v: u64,
+}
+
+#[rustfmt::skip]
+pub const FREQ_RATIO_TESTS_VALID: &[Frt] = &[
+
+    // Smaller frequencies (~KHz)
+
+    // 0.5 = 2^-1
+    Frt { g: 1000, h: 2000, f: 2,                v: 1 << 1 },
+    Frt { g: 1000, h: 2000, f: 8,                v: 1 << 7 },
+    Frt { g: 1000, h: 2000, f: FRAC_SIZE_AMD,    v: 1 << 31 },
+    Frt { g: 1000, h: 2000, f: FRAC_SIZE_INTEL,  v: 1 << 47 },
+    Frt { g: 1000, h: 2000, f: 63,               v: 1 << 62 },
+
+    // 2/3 = 2^-1 + 2^-3 + 2^-5 + 2^-7 ...
+    Frt { g: 2000, h: 3000, f: 2,                v: 0b10 },
+    Frt { g: 2000, h: 3000, f: 8,                v: 0b10101010 },
+    Frt { g: 2000, h: 3000, f: FRAC_SIZE_AMD,    v: 0xaaaa_aaaa },
+    Frt { g: 2000, h: 3000, f: FRAC_SIZE_INTEL,  v: 0xaaaa_aaaa_aaaa },
+    Frt { g: 2000, h: 3000, f: 63,               v: 0x5555_5555_5555_5555 },
+
+    // 1.5 = 2^0 + 2^-1
+    Frt { g: 3000, h: 2000, f: 2,                v: 1 << 2 | 1 << 1 },
+    Frt { g: 3000, h: 2000, f: 8,                v: 1 << 8 | 1 << 7 },
+    Frt { g: 3000, h: 2000, f: FRAC_SIZE_AMD,    v: 1 << 32 | 1 << 31 },
+    Frt { g: 3000, h: 2000, f: FRAC_SIZE_INTEL,  v: 1 << 48 | 1 << 47 },
+    Frt { g: 3000, h: 2000, f: 63,               v: 1 << 63 | 1 << 62 },
+
+
+    // Larger frequencies (~GHz)
+
+    // 0.5 = 2^-1
+    Frt { g: 1000000000, h: 2000000000, f: 2,                v: 1 << 1 },
+    Frt { g: 1000000000, h: 2000000000, f: 8,                v: 1 << 7 },
+    Frt { g: 1000000000, h: 2000000000, f: FRAC_SIZE_AMD,    v: 1 << 31 },
+    Frt { g: 1000000000, h: 2000000000, f: FRAC_SIZE_INTEL,  v: 1 << 47 },
+    Frt { g: 1000000000, h: 2000000000, f: 63,               v: 1 << 62 },
+
+    // 2/3 = 2^-1 + 2^-3 + 2^-5 + 2^-7 ...
+    Frt { g: 2000000000, h: 3000000000, f: 2,                v: 0b10 },
+    Frt { g: 2000000000, h: 3000000000, f: 8,                v: 0b10101010 },
+    Frt { g: 2000000000, h: 3000000000, f: FRAC_SIZE_AMD,    v: 0xaaaa_aaaa },
+    Frt { g: 2000000000, h: 3000000000, f: FRAC_SIZE_INTEL,  v: 0xaaaa_aaaa_aaaa },
+    Frt { g: 2000000000, h: 3000000000, f: 63,               v: 0x5555_5555_5555_5555 },
+
+    // 1.5 = 2^0 + 2^-1
+    Frt { g: 3000000000, h: 2000000000, f: 2,                v: 1 << 2 | 1 << 1 },
+    Frt { g: 3000000000, h: 2000000000, f: 8,                v: 1 << 8 | 1 << 7 },
+    Frt { g: 3000000000, h: 2000000000, f: FRAC_SIZE_AMD,    v: 1 << 32 | 1 << 31 },
+    Frt { g: 3000000000, h: 2000000000, f: FRAC_SIZE_INTEL,  v: 1 << 48 | 1 << 47 },
+    Frt { g: 3000000000, h: 2000000000, f: 63,               v: 1 << 63 | 1 << 62 },
+];
+
+#[cfg(test)]
+pub struct Frti {
+    pub g: u64,
+    pub h: u64,
+    pub f: u32,
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+pub const FREQ_RATIO_TESTS_INVALID: &[Frti] = &[
+    // values that overflow the int portion, generating an error for rust and a
+    // #DE for the assembly version
+
+    // can't fit ratio 2.0 in 1-bit integer
+    Frti { g: 2000, h: 1000, f: 63, },
+    Frti { g: 2000000000, h: 1000000000, f: 63, },
+
+    // can't fit ratio 2^32 in 32-bits
+    Frti { g: 4294967296, h: 1, f: FRAC_SIZE_AMD, },
+
+    // can't fit ratio 2^16 in 16-bits
+    Frti { g: 65536, h: 1, f: FRAC_SIZE_INTEL, },
+];
+
+pub struct Stt {
+    pub t: u64,
+    pub m: u64,
+    pub f: u32,
+    pub v: u64,
+}
+
+#[rustfmt::skip]
+pub const SCALE_TSC_TESTS_VALID: &[Stt] = &[
+    // Ratio = 1.0
+    Stt { t: 1, m: 1 << 1, f: 1, v: 1 },
+    Stt { t: 1000000000, m: 1 << FRAC_SIZE_AMD,     f: FRAC_SIZE_AMD,   v: 1000000000, },
+    Stt { t: 1000000000, m: 1 << FRAC_SIZE_INTEL,   f: FRAC_SIZE_INTEL, v: 1000000000, },
+    Stt { t: 5890513020, m: 1 << FRAC_SIZE_AMD,     f: FRAC_SIZE_AMD,   v: 5890513020, },
+    Stt { t: 5890513020, m: 1 << FRAC_SIZE_INTEL,   f: FRAC_SIZE_INTEL, v: 5890513020, },
+
+    // Ratio = 0.5
+    Stt { t: 1, m: 1 << 0, f: 1, v: 0 },
+    Stt { t: 1000000000, m: 1 << 31, f: FRAC_SIZE_AMD,      v: 500000000, },
+    Stt { t: 1000000000, m: 1 << 47, f: FRAC_SIZE_INTEL,    v: 500000000, },
+    Stt { t: 5890513020, m: 1 << 31, f: FRAC_SIZE_AMD,      v: 5890513020 / 2, },
+    Stt { t: 5890513020, m: 1 << 47, f: FRAC_SIZE_INTEL,    v: 5890513020 / 2, },
+
+    // Ratio = 1.5
+    Stt { t: 1, m: 1 << 1 | 1 << 0, f: 1, v: 1 },
+    Stt { t: 1000000000, m: 1 << 32 | 1 << 31, f: FRAC_SIZE_AMD,      v: 1500000000, },
+    Stt { t: 1000000000, m: 1 << 48 | 1 << 47, f: FRAC_SIZE_INTEL,    v: 1500000000, },
+    Stt { t: 5890513020, m: 1 << 32 | 1 << 31, f: FRAC_SIZE_AMD,      v: 5890513020 + 5890513020 / 2, },
+    Stt { t: 5890513020, m: 1 << 48 | 1 << 47, f: FRAC_SIZE_INTEL,    v: 5890513020 + 5890513020 / 2, },
+
+    // Edge cases
+    Stt { t: u64::MAX, m: 1 << 1, f: 1, v: u64::MAX, },
+    Stt { t: u64::MAX, m: 1 << 32, f: FRAC_SIZE_AMD, v: u64::MAX, },
+    Stt { t: u64::MAX, m: 1 << 48, f: FRAC_SIZE_INTEL, v: u64::MAX, },
+
+    // High frequency (~5 GHz) hosts, with large TSC values
+
+    // ~1 year of uptime at 5 GHz, ratio = 1.5 (7.5 GHz guest)
+    Stt { t: 157680000000000000, m: 1 << 32 | 1 << 31, f: FRAC_SIZE_AMD,      v: 236520000000000000, },
+    Stt { t: 157680000000000000, m: 1 << 48 | 1 << 47, f: FRAC_SIZE_INTEL,    v: 236520000000000000, },
+
+    // ratio = 2/3 (5 GHz guest on a 7.5 GHz host)
+    Stt { t: u64::MAX, m: 0xaaaa_aaaa, f: FRAC_SIZE_AMD, v: 12297829379609722879, },
+
+    // largest TSC which can be scaled by 1.5 without overflow
+    Stt { t: 12297829382473034410, m: 1 << 32 | 1 << 31, f: FRAC_SIZE_AMD,    v: u64::MAX, },
+    Stt { t: 12297829382473034410, m: 1 << 48 | 1 << 47, f: FRAC_SIZE_INTEL,  v: u64::MAX, },
+
+    // largest TSC which can be scaled by 1.25 (6.25 GHz guest on a 5 GHz host)
+    Stt { t: 14757395258967641292, m: 1 << 32 | 1 << 30, f: FRAC_SIZE_AMD,    v: u64::MAX, },
+];
+
+#[cfg(test)]
+pub struct Frr {
+    pub g: u64,
+    pub h: u64,
+    pub f: u32,
+    pub i: u32,
+    // truncated and rounded multipliers; a truncated multiplier of 0 is a
+    // RatioUnderflow error
+    pub t: u64,
+    pub r: u64,
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+pub const FREQ_RATIO_ROUNDED_TESTS: &[Frr] = &[
+    // 2/3 = 0.1010...: the first dropped bit is 1, so rounding goes up
+    Frr { g: 2000000000, h: 3000000000, f: FRAC_SIZE_AMD, i: INT_SIZE_AMD,     t: 0xaaaa_aaaa,      r: 0xaaaa_aaab },
+    Frr { g: 2000000000, h: 3000000000, f: FRAC_SIZE_INTEL, i: INT_SIZE_INTEL, t: 0xaaaa_aaaa_aaaa, r: 0xaaaa_aaaa_aaab },
+
+    // 1/3 = 0.0101...: the first dropped bit is 0, so it stays truncated
+    Frr { g: 1000000000, h: 3000000000, f: FRAC_SIZE_AMD, i: INT_SIZE_AMD,     t: 0x5555_5555,      r: 0x5555_5555 },
+    Frr { g: 1000000000, h: 3000000000, f: FRAC_SIZE_INTEL, i: INT_SIZE_INTEL, t: 0x5555_5555_5555, r: 0x5555_5555_5555 },
+
+    // exact ratios are the same either way
+    Frr { g: 1000000000, h: 2000000000, f: FRAC_SIZE_AMD, i: INT_SIZE_AMD,     t: 1 << 31,          r: 1 << 31 },
+    Frr { g: 3000000000, h: 2000000000, f: FRAC_SIZE_INTEL, i: INT_SIZE_INTEL, t: 1 << 48 | 1 << 47, r: 1 << 48 | 1 << 47 },
+
+    // an exact half rounds up: 1/2^33 is half an 8.32 LSB
+    Frr { g: 1, h: 1 << 33, f: FRAC_SIZE_AMD, i: INT_SIZE_AMD,                 t: 0,                r: 1 },
+];
+
+#[cfg(test)]
+pub struct Stti {
+    pub t: u64,
+    pub m: u64,
+    pub f: u32,
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+pub const SCALE_TSC_TESTS_INVALID: &[Stti] = &[
+    // values that overflow: (tsc * multiplier) >> frac
+    Stti { t: u64::MAX, m: 1 << 1 | 1 << 0, f: 1 },
+    Stti { t: u64::MAX, m: 1 << 32 | 1 << 31, f: FRAC_SIZE_AMD },
+    Stti { t: u64::MAX, m: 1 << 48 | 1 << 47, f: FRAC_SIZE_INTEL },
+
+    // one past the largest TSC which can be scaled by 1.5 and 1.25
+    Stti { t: 12297829382473034411, m: 1 << 32 | 1 << 31, f: FRAC_SIZE_AMD },
+    Stti { t: 12297829382473034411, m: 1 << 48 | 1 << 47, f: FRAC_SIZE_INTEL },
+    Stti { t: 14757395258967641293, m: 1 << 32 | 1 << 30, f: FRAC_SIZE_AMD },
+];
+
+#[cfg(test)]
+pub struct Gtr {
+    pub ih: u64,
+    pub ig: u64,
+    pub h: u64,
+    pub g: u64,
+    pub t: u64,
+    pub f: u32,
+    pub i: u32,
+    pub v: Option<u64>,
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+pub const GUEST_TSC_REGRESSIONS: &[Gtr] = &[
+    // Known-tricky inputs for `guest_tsc`, kept so they never regress. A value
+    // of `None` means the calculation must return an error.
+
+    // host TSC ~2^62 with ratio 2: the scaled host TSC reaches 2^63, so the
+    // offset is i64::MIN, and any further it can't be represented (see
+    // `calc_tsc_offset_max_ratio`)
+    Gtr { ih: (1 << 62) + 1, ig: 0, h: 1000000000, g: 2000000000, t: (1 << 62) + 1, f: FRAC_SIZE_AMD, i: INT_SIZE_AMD, v: None },
+    Gtr { ih: 1 << 62, ig: 0, h: 1000000000, g: 2000000000, t: 1 << 62, f: FRAC_SIZE_AMD, i: INT_SIZE_AMD, v: Some(0) },
+    Gtr { ih: (1 << 62) - 1, ig: 0, h: 1000000000, g: 2000000000, t: (1 << 62) - 1, f: FRAC_SIZE_AMD, i: INT_SIZE_AMD, v: Some(0) },
+
+    // ... but at ratio 1 the same host TSC is fine
+    Gtr { ih: 1 << 62, ig: 0, h: 1000000000, g: 1000000000, t: 1 << 62, f: FRAC_SIZE_AMD, i: INT_SIZE_AMD, v: Some(0) },
+
+    // largest and smallest initial guest TSC the offset can represent
+    Gtr { ih: 0, ig: (1 << 63) - 1, h: 1000000000, g: 1000000000, t: 0, f: FRAC_SIZE_AMD, i: INT_SIZE_AMD, v: Some((1 << 63) - 1) },
+    Gtr { ih: 0, ig: 1 << 63, h: 1000000000, g: 1000000000, t: 0, f: FRAC_SIZE_AMD, i: INT_SIZE_AMD, v: None },
+
+    // sign flip: a host TSC before the initial one gives a negative guest TSC
+    Gtr { ih: 1000, ig: 0, h: 1000000000, g: 1000000000, t: 999, f: FRAC_SIZE_AMD, i: INT_SIZE_AMD, v: None },
+    Gtr { ih: 1000, ig: 1, h: 1000000000, g: 1000000000, t: 999, f: FRAC_SIZE_AMD, i: INT_SIZE_AMD, v: Some(0) },
+
+    // largest host TSC which can be scaled by 1.5, and one past it (see
+    // `SCALE_TSC_TESTS_INVALID`)
+    Gtr { ih: 0, ig: 0, h: 2000000000, g: 3000000000, t: 12297829382473034410, f: FRAC_SIZE_AMD, i: INT_SIZE_AMD, v: Some(u64::MAX) },
+    Gtr { ih: 0, ig: 0, h: 2000000000, g: 3000000000, t: 12297829382473034411, f: FRAC_SIZE_AMD, i: INT_SIZE_AMD, v: None },
+
+    // scaling fits, but adding a positive offset overflows
+    Gtr { ih: 0, ig: 0, h: 1000000000, g: 1000000000, t: u64::MAX, f: FRAC_SIZE_AMD, i: INT_SIZE_AMD, v: Some(u64::MAX) },
+    Gtr { ih: 0, ig: 1, h: 1000000000, g: 1000000000, t: u64::MAX, f: FRAC_SIZE_AMD, i: INT_SIZE_AMD, v: None },
+
+    // rounding: 3 host ticks at ratio 2/3 are exactly 2 guest ticks, but the
+    // truncated multiplier gives 1
+    Gtr { ih: 0, ig: 0, h: 3000000000, g: 2000000000, t: 3, f: FRAC_SIZE_AMD, i: INT_SIZE_AMD, v: Some(1) },
+    Gtr { ih: 0, ig: 0, h: 3000000000, g: 2000000000, t: 3, f: FRAC_SIZE_INTEL, i: INT_SIZE_INTEL, v: Some(1) },
+
+    // ratio 2^32 doesn't fit in the AMD integer bits
+    Gtr { ih: 0, ig: 0, h: 1, g: 4294967296, t: 0, f: FRAC_SIZE_AMD, i: INT_SIZE_AMD, v: None },
+];
+
+#[cfg(test)]
+pub struct Hrt {
+    pub t: u64,
+    pub h: u64,
+    pub v: Option<u64>,
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+pub const HRTIME_TESTS: &[Hrt] = &[
+    // TSC -> nanoseconds at `h`, where `None` means the calculation must
+    // return an error
+
+    // whole seconds
+    Hrt { t: 0,             h: 1000000000, v: Some(0) },
+    Hrt { t: 3000000000,    h: 1000000000, v: Some(3000000000) },
+    Hrt { t: 7500000000,    h: 2500000000, v: Some(3000000000) },
+
+    // fractional seconds
+    Hrt { t: 1500000000,    h: 1000000000, v: Some(1500000000) },
+    Hrt { t: 1,             h: 1000000000, v: Some(1) },
+    Hrt { t: 1,             h: 2500000000, v: Some(0) },
+    Hrt { t: 3,             h: 2500000000, v: Some(1) },
+    Hrt { t: 3750000001,    h: 2500000000, v: Some(1500000000) },
+    Hrt { t: 1000000000,    h: 3000000000, v: Some(333333333) },
+
+    // the full TSC range fits at or above 1 GHz
+    Hrt { t: u64::MAX,      h: 1000000000, v: Some(u64::MAX) },
+    Hrt { t: u64::MAX,      h: 3000000000, v: Some(u64::MAX / 3) },
+
+    // below 1 GHz the nanoseconds can overflow, even though the product can't
+    // overflow 128 bits
+    Hrt { t: 18446744073709551, h: 1000000, v: Some(18446744073709551000) },
+    Hrt { t: 18446744073709552, h: 1000000, v: None },
+    Hrt { t: 18446744073,   h: 1,          v: Some(18446744073000000000) },
+    Hrt { t: 18446744074,   h: 1,          v: None },
+    Hrt { t: u64::MAX,      h: 1,          v: None },
+];
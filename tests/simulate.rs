@@ -0,0 +1,351 @@
+// Run the simulator binary end to end
+
+use std::process::Command;
+
+fn simulate(args: &[&str]) -> String {
+    let out = Command::new(env!("CARGO_BIN_EXE_tsc-simulator"))
+        .arg("simulate")
+        .args(args)
+        .output()
+        .expect("could not run tsc-simulator");
+    assert!(out.status.success(), "simulate {:?} failed", args);
+
+    String::from_utf8(out.stdout).unwrap()
+}
+
+// The data rows of simulation output: those starting with a time
+fn rows(out: &str) -> Vec<&str> {
+    out.lines()
+        .filter(|l| {
+            l.split_whitespace()
+                .next()
+                .is_some_and(|t| t.parse::<i64>().is_ok())
+        })
+        .collect()
+}
+
+#[test]
+fn single_host_terminates() {
+    let out = simulate(&["-d", "5"]);
+    let rows = rows(&out);
+
+    // one row per second, from boot through the end of the run
+    assert_eq!(rows.len(), 6, "{}", out);
+    assert!(rows[0].starts_with("0 "));
+    assert!(rows[5].starts_with("5 "));
+}
+
+#[test]
+fn json_rows_round_trip() {
+    let out = simulate(&["-d", "5", "--format", "json", "--hex"]);
+    let doc: serde_json::Value =
+        serde_json::from_str(&out).expect("output is not json");
+
+    let rows = doc["rows"].as_array().unwrap();
+    assert_eq!(rows.len(), 6, "{}", out);
+    assert_eq!(doc["header"]["duration"], 5);
+    assert_eq!(rows[0]["t"], 0);
+    assert_eq!(rows[0]["phase"], "boot");
+    assert_eq!(rows[5]["t"], 5);
+}
+
+#[test]
+fn csv_rows() {
+    let out = simulate(&["-d", "5", "--format", "csv", "--hex"]);
+    let lines: Vec<&str> = out.lines().collect();
+
+    // a header, then one row per second from boot through the end of the run
+    assert_eq!(lines.len(), 5 + 2, "{}", out);
+    assert_eq!(lines[0], "t,guest_tsc,host_tsc,host_index,phase");
+    for line in &lines[1..] {
+        let fields: Vec<&str> = line.split(',').collect();
+        assert_eq!(fields.len(), 5, "{}", line);
+        fields[0].parse::<i64>().unwrap();
+        fields[1].parse::<u64>().unwrap();
+        fields[2].parse::<u64>().unwrap();
+        fields[3].parse::<u32>().unwrap();
+        assert_eq!(fields[4], "boot");
+    }
+}
+
+#[test]
+fn sub_second_steps_align_with_migrations() {
+    let out = simulate(&[
+        "-d",
+        "2",
+        "--step-ns",
+        "300000000",
+        "--migrate",
+        "1 5000 2000000000",
+    ]);
+    let rows: Vec<Vec<&str>> = out
+        .lines()
+        .map(|l| l.split_whitespace().collect::<Vec<_>>())
+        .filter(|f| f.len() == 3 && f[0].parse::<f64>().is_ok())
+        .collect();
+    let times: Vec<&str> = rows.iter().map(|f| f[0]).collect();
+
+    // the last step on each host is shortened to land on the migration
+    assert_eq!(
+        times,
+        ["0", "0.3", "0.6", "0.9", "1", "1.3", "1.6", "1.9", "2"],
+        "{}",
+        out
+    );
+    assert_eq!(rows[4][2], "5000");
+    assert_eq!(rows[8][2], "2000005000");
+}
+
+#[test]
+fn drift_accumulates_at_3_2_ratio() {
+    // the multiplier for a 2/3 guest-to-host ratio is truncated, so the guest
+    // falls steadily behind
+    let out = simulate(&[
+        "-d",
+        "100",
+        "-g",
+        "1000000000",
+        "-f",
+        "1500000000",
+        "--show-drift",
+    ]);
+    let drift: Vec<i64> = rows(&out)
+        .iter()
+        .map(|r| r.split_whitespace().last().unwrap().parse().unwrap())
+        .collect();
+
+    assert_eq!(drift.len(), 101, "{}", out);
+    assert_eq!(drift[0], 0);
+    assert!(drift[100] < 0, "{}", out);
+    assert!(drift.windows(2).all(|w| w[1] <= w[0]), "{}", out);
+}
+
+#[test]
+fn tsc_event_jumps_by_scaled_delta() {
+    // the host TSC is rolled back by 2s worth of ticks at t=3; the guest runs
+    // 3/2 as fast, so its TSC drops by 3s worth without the offset changing
+    let out = simulate(&[
+        "-d",
+        "5",
+        "-g",
+        "1500000000",
+        "--format",
+        "csv",
+        "--tsc-event",
+        "3 2000000000",
+    ]);
+    let rows: Vec<Vec<&str>> = out
+        .lines()
+        .skip(1)
+        .map(|l| l.split(',').collect())
+        .collect();
+    let guest = |t: usize| rows[t][1].parse::<i64>().unwrap();
+    let host = |t: usize| rows[t][2].parse::<i64>().unwrap();
+
+    assert_eq!(rows.len(), 6, "{}", out);
+    assert_eq!(host(3), 2_000_000_000);
+    assert_eq!(rows[3][4], "tsc_event");
+
+    // without the reset, the guest would be at 3s * 1.5 GHz
+    assert_eq!(guest(3) - 4_500_000_000, (host(3) - 4_000_000_000) * 3 / 2);
+    assert_eq!(guest(2) - guest(1), 1_500_000_000);
+    assert_eq!(guest(4) - guest(3), 1_500_000_000);
+
+    // and the guest stays on the same host
+    assert!(rows.iter().all(|r| r[3] == "0"), "{}", out);
+}
+
+#[test]
+fn scenario_file_matches_flags() {
+    let path = std::env::temp_dir().join(format!(
+        "tsc-simulator-scenario-{}.toml",
+        std::process::id()
+    ));
+    std::fs::write(
+        &path,
+        r#"
+duration = 6
+initial_host_tsc = 5000
+initial_host_hz = 2000000000
+guest_hz = 1500000000
+arch = "intel"
+
+[[migrate]]
+t = 3
+host_tsc = 70000
+host_hz = 3000000000
+"#,
+    )
+    .unwrap();
+    let scenario = path.to_str().unwrap();
+
+    let from_file = simulate(&["--scenario", scenario]);
+    let from_flags = simulate(&[
+        "-d",
+        "6",
+        "-i",
+        "5000",
+        "-f",
+        "2000000000",
+        "-g",
+        "1500000000",
+        "--arch",
+        "intel",
+        "--migrate",
+        "3 70000 3000000000",
+    ]);
+    assert_eq!(from_file, from_flags);
+
+    // flags override the file
+    let overridden = simulate(&["--scenario", scenario, "-d", "4"]);
+    let rows = rows(&overridden);
+    assert!(rows.last().unwrap().starts_with("4 "), "{}", overridden);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn guest_hrtime_advances_one_second_per_step() {
+    let out = simulate(&["-d", "5", "--show-hrtime"]);
+    let rows = rows(&out);
+    assert_eq!(rows.len(), 6, "{}", out);
+
+    // the last two columns are the guest and host hrtime
+    let hrtimes: Vec<(u64, u64)> = rows
+        .iter()
+        .map(|r| {
+            let cols: Vec<&str> = r.split_whitespace().collect();
+            let n = cols.len();
+            (cols[n - 2].parse().unwrap(), cols[n - 1].parse().unwrap())
+        })
+        .collect();
+    for w in hrtimes.windows(2) {
+        assert_eq!(w[1].0 - w[0].0, 1_000_000_000, "{}", out);
+        assert_eq!(w[1].1 - w[0].1, 1_000_000_000, "{}", out);
+    }
+}
+
+#[test]
+fn host_tsc_overflow_stops_the_run() {
+    let start = (u64::MAX - 2_000_000_000).to_string();
+    let out = Command::new(env!("CARGO_BIN_EXE_tsc-simulator"))
+        .args(["simulate", "-d", "5", "-i", &start])
+        .args(["--initial-guest-tsc", &start])
+        .output()
+        .expect("could not run tsc-simulator");
+    assert!(!out.status.success());
+
+    // the run is rejected up front, as with --dry-run, rather than after the
+    // steps the host TSC can reach
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(rows(&stdout).is_empty(), "{}", stdout);
+
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("TSC overflows"), "{}", stderr);
+}
+
+#[test]
+fn compare_arch_intel_tracks_2_3_ratio_closer() {
+    let out = simulate(&[
+        "-d",
+        "100",
+        "-f",
+        "3000000000",
+        "-g",
+        "2000000000",
+        "--compare-arch",
+    ]);
+    let steps = rows(&out);
+    assert_eq!(steps.len(), 101, "{}", out);
+
+    // time, host TSC, AMD and Intel guest TSCs, and their difference
+    let last: Vec<i128> = steps[100]
+        .split_whitespace()
+        .map(|c| c.parse().unwrap())
+        .collect();
+    let ideal = 100 * 2_000_000_000;
+    let (amd, intel) = ((ideal - last[2]).abs(), (ideal - last[3]).abs());
+    assert!(intel < amd, "{}", out);
+    assert_eq!(last[4], last[3] - last[2]);
+
+    // a ratio too large for 8.32 is only run as 16.48
+    let out = simulate(&["-d", "2", "-g", "300GHz", "--compare-arch"]);
+    let rows = rows(&out);
+    assert_eq!(rows.len(), 3, "{}", out);
+    assert!(rows.iter().all(|r| r.ends_with("(no amd)")), "{}", out);
+}
+
+#[test]
+fn pre_boot_extrapolates_back_from_boot() {
+    let out = simulate(&[
+        "-d",
+        "1",
+        "-i",
+        "5000000000",
+        "--initial-guest-tsc",
+        "3000000000",
+        "--pre-boot-sec",
+        "2",
+    ]);
+    let rows = rows(&out);
+    assert_eq!(rows.len(), 4, "{}", out);
+
+    // a second of guest and host ticks back per second before boot
+    let cols: Vec<Vec<&str>> = rows
+        .iter()
+        .map(|r| r.split_whitespace().collect())
+        .collect();
+    assert_eq!(cols[0], ["-2", "1000000000", "3000000000"]);
+    assert_eq!(cols[1], ["-1", "2000000000", "4000000000"]);
+    assert_eq!(cols[2], ["0", "3000000000", "5000000000"]);
+}
+
+#[test]
+fn pre_boot_underflow_is_noted_once() {
+    // the guest TSC is 0.5s of ticks a second before boot, and would be
+    // negative before that
+    let out = simulate(&[
+        "-d",
+        "1",
+        "-i",
+        "5000000000",
+        "--initial-guest-tsc",
+        "1500000000",
+        "--pre-boot-sec",
+        "3",
+    ]);
+    assert_eq!(out.matches("would underflow").count(), 1, "{}", out);
+    assert!(out.contains("would underflow before t=-1\n"), "{}", out);
+    let rows = rows(&out);
+    assert_eq!(rows.len(), 3, "{}", out);
+    assert!(rows[0].starts_with("-1 "), "{}", out);
+
+    // a guest booted with the host has no second before boot to show, however
+    // far back it's asked for
+    let out = simulate(&[
+        "-d",
+        "1",
+        "--pre-boot-sec",
+        "100000000000",
+        "--format",
+        "json",
+    ]);
+    assert!(!out.contains("pre_boot"), "{}", out);
+}
+
+#[test]
+fn rejected_inputs_exit_nonzero() {
+    for args in [
+        &["simulate", "-d", "4", "--migrate", "5 1 1"][..],
+        &["simulate", "-d", "4", "--retune", "9 1"],
+        &["calc", "freq", "-f", "1", "-g", "1000000000"],
+        &["calc", "hrtime", "-t", "1", "-f", "0"],
+        &["calc", "tsc", "-t", "1", "-f", "0"],
+    ] {
+        let out = Command::new(env!("CARGO_BIN_EXE_tsc-simulator"))
+            .args(args)
+            .output()
+            .expect("could not run tsc-simulator");
+        assert!(!out.status.success(), "{:?} succeeded", args);
+    }
+}